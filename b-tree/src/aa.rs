@@ -0,0 +1,581 @@
+//!
+//! an implementation for AA Tree
+//!
+//! https://en.wikipedia.org/wiki/AA_tree
+//!
+//! An AA tree is a red-black tree whose red nodes must be right children, the mirror image of the
+//! left-leaning `rbt::Rbt`. Instead of a color every node has a level, a red node has the level of
+//! its parent. Two operations keep the shape: `skew` rotates right to remove a left child at the
+//! same level, `split` rotates left and raises the middle node when two right children are at the
+//! same level. The rules, with the empty children at level 0:
+//! 1. A leaf has the level 1.
+//! 2. A left child is one level below its parent.
+//! 3. A right child is at the level of its parent or one below.
+//! 4. A right grandchild is below its grandparent.
+//! 5. A node above the level 1 has two children.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::map::OrderedMap;
+use crate::path::{self, Descend, Path};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+
+#[derive(Debug, PartialEq)]
+struct Node<T, V> {
+    key: T,
+    data: V,
+    level: usize,
+    left: Link<T, V>,
+    right: Link<T, V>,
+}
+
+impl<T, V> Descend for Node<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+type Link<T, V> = Option<Box<Node<T, V>>>;
+
+/// The level of a node, 0 for an empty child.
+fn level<T, V>(node: &Link<T, V>) -> usize {
+    node.as_ref().map_or(0, |node| node.level)
+}
+
+/// An AA tree, a map from `T` to `V` with unique keys ordered by the comparator `C`.
+pub struct AaTree<T, V = (), C = Natural> {
+    root: Link<T, V>,
+    len: usize,
+    rotations: usize,
+    cmp: C,
+}
+
+impl<T, V, C: Default> Default for AaTree<T, V, C> {
+    fn default() -> Self {
+        AaTree::with_comparator(C::default())
+    }
+}
+
+impl<T: Ord, V> AaTree<T, V> {
+    pub fn new() -> AaTree<T, V> {
+        AaTree::with_comparator(Natural)
+    }
+}
+
+impl<T, V, C> AaTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> AaTree<T, V, C> {
+        AaTree { root: None, len: 0, rotations: 0, cmp }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of rotations made by all skews and splits so far.
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { nodes: path::InOrder::new(self.root.as_deref()) }
+    }
+
+    /// Checks the order of the keys, the length and the five rules of the levels.
+    pub fn validate(&self) -> Result<(), String>
+    where
+        C: Compare<T>,
+    {
+        let mut previous = None;
+        let mut count = 0;
+        for (key, _) in self.iter() {
+            if previous.is_some_and(|previous| self.cmp.compare(previous, key) != Ordering::Less) {
+                return Err(format!("the keys at {} and {} are out of order", count - 1, count));
+            }
+            previous = Some(key);
+            count += 1;
+        }
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, count));
+        }
+
+        let mut nodes: Vec<&Node<T, V>> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = nodes.pop() {
+            let (left, right) = (level(&node.left), level(&node.right));
+            if node.left.is_none() && node.right.is_none() && node.level != 1 {
+                return Err(format!("a leaf has the level {}", node.level));
+            }
+            if node.level > 1 && (node.left.is_none() || node.right.is_none()) {
+                return Err(format!("a node at the level {} misses a child", node.level));
+            }
+            if left + 1 != node.level {
+                return Err(format!("a left child has the level {} below a node at {}", left, node.level));
+            }
+            if right != node.level && right + 1 != node.level {
+                return Err(format!("a right child has the level {} below a node at {}", right, node.level));
+            }
+            if node.right.as_ref().is_some_and(|right| level(&right.right) == node.level) {
+                return Err(format!("a right grandchild is at the level {} of its grandparent", node.level));
+            }
+            nodes.extend(node.left.as_deref());
+            nodes.extend(node.right.as_deref());
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree, the level of a node is kept in a machine word.
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root.as_deref(), mem::size_of::<Node<T, V>>(), |_| ());
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// Rotates right if the left child is at the level of `node`. `path`, starting at `node`, is
+    /// updated to lead to the same node afterwards.
+    fn skew(&mut self, mut node: Box<Node<T, V>>, path: Option<&mut Path>) -> Box<Node<T, V>> {
+        if level(&node.left) != node.level {
+            return node;
+        }
+        let mut left = node.left.take().expect("the left child is at the level of its parent");
+        node.left = left.right.take();
+        left.right = Some(node);
+        if let Some(path) = path {
+            path::rotate(path, false);
+        }
+        self.rotations += 1;
+        left
+    }
+
+    /// Rotates left and raises the right child if the right grandchild is at the level of `node`.
+    fn split(&mut self, mut node: Box<Node<T, V>>, path: Option<&mut Path>) -> Box<Node<T, V>> {
+        if node.right.as_ref().is_none_or(|right| level(&right.right) != node.level) {
+            return node;
+        }
+        let mut right = node.right.take().expect("the right grandchild is at the level of its grandparent");
+        node.right = right.left.take();
+        right.left = Some(node);
+        right.level += 1;
+        if let Some(path) = path {
+            path::rotate(path, true);
+        }
+        self.rotations += 1;
+        right
+    }
+
+    /// Restores the rules at `node` after a node was removed below it: lowers its level if a
+    /// child became too low, then skews and splits along the right spine.
+    fn rebalance(&mut self, mut node: Box<Node<T, V>>) -> Box<Node<T, V>> {
+        let expected = level(&node.left).min(level(&node.right)) + 1;
+        if expected < node.level {
+            node.level = expected;
+            if let Some(right) = node.right.as_mut() {
+                right.level = right.level.min(expected);
+            }
+        }
+
+        let mut node = self.skew(node, None);
+        if let Some(right) = node.right.take() {
+            let mut right = self.skew(right, None);
+            if let Some(grandchild) = right.right.take() {
+                right.right = Some(self.skew(grandchild, None));
+            }
+            node.right = Some(right);
+        }
+        let mut node = self.split(node, None);
+        if let Some(right) = node.right.take() {
+            node.right = Some(self.split(right, None));
+        }
+        node
+    }
+
+    /// Removes the leftmost (`left == true`) or the rightmost node below `node`.
+    fn take_end(&mut self, mut node: Box<Node<T, V>>, left: bool) -> (Link<T, V>, (T, V)) {
+        let child = if left { node.left.take() } else { node.right.take() };
+        match child {
+            None => {
+                let Node { key, data, left: left_child, right: right_child, .. } = *node;
+                (if left { right_child } else { left_child }, (key, data))
+            }
+            Some(child) => {
+                let (child, entry) = self.take_end(child, left);
+                if left { node.left = child } else { node.right = child }
+                (Some(self.rebalance(node)), entry)
+            }
+        }
+    }
+}
+
+impl<T, V, C: Compare<T>> AaTree<T, V, C> {
+    pub fn search(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Greater => n.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        let mut node = self.root.as_deref_mut();
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&mut n.data),
+                Ordering::Less => n.left.as_deref_mut(),
+                Ordering::Greater => n.right.as_deref_mut(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        let root = self.root.take();
+        let (root, old) = self.insert_at(root, key, data, None);
+        self.root = Some(root);
+        old
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        self.remove_entry(key).map(|(_, data)| data)
+    }
+
+    /// Removes `key`, returns it with its data if it was present.
+    pub fn remove_entry(&mut self, key: &T) -> Option<(T, V)> {
+        let root = self.root.take();
+        let (root, entry) = self.remove_at(root, key);
+        self.root = root;
+        entry
+    }
+
+    /// Inserts below `node` and returns the new top of the subtree. `path`, if any, is set to lead
+    /// from there to the node of `key`.
+    fn insert_at(&mut self, node: Link<T, V>, key: T, data: V, mut path: Option<&mut Path>)
+                 -> (Box<Node<T, V>>, Option<V>) {
+        let Some(mut node) = node else {
+            self.len += 1;
+            return (Box::new(Node { key, data, level: 1, left: None, right: None }), None);
+        };
+        let left = match self.cmp.compare(&key, &node.key) {
+            Ordering::Equal => {
+                let old = mem::replace(&mut node.data, data);
+                return (node, Some(old));
+            }
+            Ordering::Less => true,
+            Ordering::Greater => false,
+        };
+
+        let child = if left { node.left.take() } else { node.right.take() };
+        let (child, old) = self.insert_at(child, key, data, path.as_deref_mut());
+        if left { node.left = Some(child) } else { node.right = Some(child) }
+        if let Some(path) = path.as_deref_mut() {
+            path.push_front(left);
+        }
+        if old.is_some() {
+            return (node, old);
+        }
+        let node = self.skew(node, path.as_deref_mut());
+        (self.split(node, path), None)
+    }
+
+    /// Removes `key` below `node`, returns the new top of the subtree and the removed entry.
+    fn remove_at(&mut self, node: Link<T, V>, key: &T) -> (Link<T, V>, Option<(T, V)>) {
+        let Some(mut node) = node else {
+            return (None, None);
+        };
+        let entry = match self.cmp.compare(key, &node.key) {
+            Ordering::Less => {
+                let (left, entry) = self.remove_at(node.left.take(), key);
+                node.left = left;
+                entry
+            }
+            Ordering::Greater => {
+                let (right, entry) = self.remove_at(node.right.take(), key);
+                node.right = right;
+                entry
+            }
+            Ordering::Equal => {
+                self.len -= 1;
+                if node.left.is_none() && node.right.is_none() {
+                    let Node { key, data, .. } = *node;
+                    return (None, Some((key, data)));
+                }
+                // the successor, or the predecessor if there is no right child, takes the place
+                let (key, data) = if let Some(right) = node.right.take() {
+                    let (right, successor) = self.take_end(right, true);
+                    node.right = right;
+                    successor
+                } else {
+                    let left = node.left.take().expect("the node is not a leaf");
+                    let (left, predecessor) = self.take_end(left, false);
+                    node.left = left;
+                    predecessor
+                };
+                Some((mem::replace(&mut node.key, key), mem::replace(&mut node.data, data)))
+            }
+        };
+        if entry.is_none() {
+            return (Some(node), None);
+        }
+        (Some(self.rebalance(node)), entry)
+    }
+}
+
+impl<T: Display, V, C> AaTree<T, V, C> {
+    /// The shape of the tree to be drawn by `render`, a node is labeled with its key and level.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V>(node: &Node<T, V>) -> Sketch {
+            let children = vec![node.left.as_deref().map(sketch), node.right.as_deref().map(sketch)];
+            Sketch::new(format!("{} L{}", node.key, node.level), children)
+        }
+        self.root.as_deref().map(sketch)
+    }
+}
+
+impl<T: Display, V, C> Display for AaTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for AaTree<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        AaTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        AaTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        AaTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        AaTree::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        if self.search(&key) {
+            return AaTree::get_mut(self, &key).expect("the key is present");
+        }
+        // the key is moved into the tree, the path follows the new node through the rotations
+        let mut path = Path::new();
+        let root = self.root.take();
+        let (root, _) = self.insert_at(root, key, default(), Some(&mut path));
+        let mut node = self.root.insert(root);
+        for left in path {
+            node = if left { node.left.as_mut() } else { node.right.as_mut() }.expect("the path leads to a node");
+        }
+        &mut node.data
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        AaTree::iter(self)
+    }
+}
+
+pub struct Iter<'a, T, V> {
+    nodes: path::InOrder<'a, Node<T, V>>,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.key, &node.data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    #[cfg(feature = "std")]
+    use std::time::Instant;
+    #[cfg(feature = "std")]
+    use crate::clrs::RedBlackTree;
+    use crate::compare::Reverse;
+    use crate::rbt::{self, Rbt};
+    #[cfg(feature = "std")]
+    use crate::testing::Random;
+    use crate::testing::{check_multiset, check_ordered_map, check_reverse_order};
+    use super::*;
+
+    fn node(key: i32, level: usize, left: Link<i32, ()>, right: Link<i32, ()>) -> Link<i32, ()> {
+        Some(Box::new(Node { key, data: (), level, left, right }))
+    }
+
+    /// Inserts `keys` into an AA tree and a left-leaning red-black tree, returns the AA tree and
+    /// the rotations made by both.
+    fn insert_both(keys: &[i32]) -> (AaTree<i32>, usize, usize) {
+        let mut tree = AaTree::new();
+        let mut rbt = Rbt::new();
+        let before = rbt::rotations();
+        for &key in keys {
+            tree.insert(key, ());
+            rbt.insert(key);
+        }
+        assert!(tree.validate().is_ok());
+        let rotations = tree.rotations();
+        (tree, rotations, rbt::rotations() - before)
+    }
+
+    #[test]
+    fn test_insert_condition_01() {
+        // the red-black tree keeps 1 as a red left child, the AA tree skews it up
+        let (tree, rotations, rbt_rotations) = insert_both(&[2, 1]);
+        assert_eq!(tree.root, node(1, 1, None, node(2, 1, None, None)));
+        assert_eq!((rotations, rbt_rotations), (1, 0));
+    }
+
+    #[test]
+    fn test_insert_condition_02() {
+        // the red-black tree rotates 3 up, a right child at the same level is fine for the AA tree
+        let (tree, rotations, rbt_rotations) = insert_both(&[2, 3]);
+        assert_eq!(tree.root, node(2, 1, None, node(3, 1, None, None)));
+        assert_eq!((rotations, rbt_rotations), (0, 1));
+    }
+
+    #[test]
+    fn test_insert_condition_03() {
+        let (tree, rotations, rbt_rotations) = insert_both(&[1, 3, 5, 4, 2]);
+        let target = node(
+            3,
+            2,
+            node(1, 1, None, node(2, 1, None, None)),
+            node(4, 1, None, node(5, 1, None, None)),
+        );
+        assert_eq!(tree.root, target);
+        assert_eq!((rotations, rbt_rotations), (2, 2));
+    }
+
+    #[test]
+    fn test_rotate_double_left_red() {
+        let (tree, rotations, rbt_rotations) = insert_both(&[6, 2, 1, 0, 3, 9, 5, 7, 8, 4, -1]);
+        for i in 0..9 {
+            assert!(tree.search(&i));
+        }
+
+        let root = tree.root.as_deref().unwrap();
+        let key = |path: &[bool]| path::follow(root, &path.iter().copied().collect()).key;
+        assert_eq!(2, key(&[]));
+
+        assert_eq!(0, key(&[true]));
+        assert_eq!(6, key(&[false]));
+
+        assert_eq!(-1, key(&[true, true]));
+        assert_eq!(1, key(&[true, false]));
+
+        assert_eq!(4, key(&[false, true]));
+        assert_eq!(8, key(&[false, false]));
+
+        assert_eq!(3, key(&[false, true, true]));
+        assert_eq!(5, key(&[false, true, false]));
+        assert_eq!(7, key(&[false, false, true]));
+        assert_eq!(9, key(&[false, false, false]));
+
+        // every red link of the AA tree must lean right, it rotates more often
+        assert_eq!((rotations, rbt_rotations), (17, 10));
+    }
+
+    #[test]
+    fn test_levels_checked() {
+        let (mut tree, _, _) = insert_both(&[1, 3, 5, 4, 2]);
+        tree.root.as_mut().unwrap().level = 3;
+        assert_eq!(tree.validate(), Err("a left child has the level 1 below a node at 3".to_string()));
+
+        // a right child at the level of its parent with a right child at the same level
+        let mut tree = AaTree::new();
+        tree.root = node(1, 1, None, node(2, 1, None, node(3, 1, None, None)));
+        tree.len = 3;
+        assert_eq!(tree.validate(), Err("a right grandchild is at the level 1 of its grandparent".to_string()));
+
+        tree.root = node(1, 2, None, node(2, 1, None, None));
+        tree.len = 2;
+        assert_eq!(tree.validate(), Err("a node at the level 2 misses a child".to_string()));
+    }
+
+    #[test]
+    fn test_sorted_insertions_and_removals() {
+        let mut tree = AaTree::new();
+        for key in 0..100_000 {
+            tree.insert(key, ());
+        }
+        assert!(tree.validate().is_ok());
+        assert!(tree.stats().height <= 2 * stats::optimal_height(100_000));
+        for key in (0..99_990).rev() {
+            tree.remove(&key);
+        }
+        assert!(tree.validate().is_ok());
+        assert!(tree.iter().map(|(&key, _)| key).eq(99_990..100_000));
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<AaTree<i32, i32>>().validate().is_ok());
+        assert!(check_reverse_order::<AaTree<i32, (), Reverse>>().validate().is_ok());
+        check_multiset::<AaTree<i32, usize>>();
+    }
+
+    /// Compares the rotations of the AA tree and both red-black trees, run it with
+    /// `cargo test --release -- --ignored --nocapture bench_rotations`.
+    #[cfg(feature = "std")]
+    #[test]
+    #[ignore]
+    fn bench_rotations() {
+        const N: u64 = 100_000;
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        let shuffled: Vec<u64> = (0..N).map(|_| random.next_u64() % (4 * N)).collect();
+        for (name, keys) in [("sorted", (0..N).collect::<Vec<u64>>()), ("random", shuffled)] {
+            let start = Instant::now();
+            let mut aa = AaTree::new();
+            keys.iter().for_each(|&key| { aa.insert(key, ()); });
+            let aa_time = start.elapsed();
+
+            let start = Instant::now();
+            let before = rbt::rotations();
+            let mut llrb = Rbt::new();
+            keys.iter().for_each(|&key| llrb.insert(key));
+            let llrb_rotations = rbt::rotations() - before;
+            let llrb_time = start.elapsed();
+
+            let start = Instant::now();
+            let mut clrs = RedBlackTree::new();
+            keys.iter().for_each(|&key| { clrs.insert(key, ()); });
+            let clrs_time = start.elapsed();
+
+            println!("{} keys, rotations per insertion and time:", name);
+            for (tree, rotations, time) in [
+                ("aa", aa.rotations(), aa_time),
+                ("llrb", llrb_rotations, llrb_time),
+                ("clrs", clrs.rotations(), clrs_time),
+            ] {
+                println!("  {:<5} {:.3} {:?}", tree, rotations as f64 / N as f64, time);
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//!
+//! an augmented AVL tree answering aggregate queries over ranges of keys
+//!
+//! https://en.wikipedia.org/wiki/Augmented_tree
+//!
+//! Every node keeps the summary of its subtree besides its height, the rotations recompute both.
+//! A summary is a monoid: it has an empty value and an associative `combine`, so the summary of
+//! any range of keys is made of the summaries of O(log n) subtrees and single nodes. `combine` needs
+//! not be commutative, the parts are always combined in the order of their keys. The nodes and the
+//! rotations are those of `avl`, whose trees keep the summary `()`.
+//!
+//! The data can't be borrowed mutably, it would leave the summaries stale: `update` changes it and
+//! recomputes the summaries on the way back to the root.
+//!
+
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use core::ops::{Add, Bound, RangeBounds};
+use crate::avl::{util, AvlBoxOption, Iter};
+use crate::compare::{Compare, Natural};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
+
+/// A monoid summarizing the entries of a subtree.
+pub trait Summary<T, V>: Clone {
+    /// The summary of no entry, `combine` with it changes nothing.
+    fn empty() -> Self;
+
+    /// The summary of a single entry.
+    fn of(key: &T, data: &V) -> Self;
+
+    /// The summary of the entries of `self` followed by the entries of `other`.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// No summary at all, what the nodes of an `AvlTree` keep.
+impl<T, V> Summary<T, V> for () {
+    fn empty() -> Self {}
+
+    fn of(_: &T, _: &V) -> Self {}
+
+    fn combine(&self, _: &Self) -> Self {}
+}
+
+/// The number of entries.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Count(pub usize);
+
+impl<T, V> Summary<T, V> for Count {
+    fn empty() -> Self {
+        Count(0)
+    }
+
+    fn of(_: &T, _: &V) -> Self {
+        Count(1)
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Count(self.0 + other.0)
+    }
+}
+
+/// The sum of the data, starting from its `Default`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Sum<V>(pub V);
+
+impl<T, V: Add<Output = V> + Clone + Default> Summary<T, V> for Sum<V> {
+    fn empty() -> Self {
+        Sum(V::default())
+    }
+
+    fn of(_: &T, data: &V) -> Self {
+        Sum(data.clone())
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+}
+
+/// The least data, `None` without entries.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Min<V>(pub Option<V>);
+
+impl<T, V: Ord + Clone> Summary<T, V> for Min<V> {
+    fn empty() -> Self {
+        Min(None)
+    }
+
+    fn of(_: &T, data: &V) -> Self {
+        Min(Some(data.clone()))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Min(match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Some(a.min(b).clone()),
+            (a, b) => a.clone().or(b.clone()),
+        })
+    }
+}
+
+/// The greatest data, `None` without entries.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Max<V>(pub Option<V>);
+
+impl<T, V: Ord + Clone> Summary<T, V> for Max<V> {
+    fn empty() -> Self {
+        Max(None)
+    }
+
+    fn of(_: &T, data: &V) -> Self {
+        Max(Some(data.clone()))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        Max(match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Some(a.max(b).clone()),
+            (a, b) => a.clone().or(b.clone()),
+        })
+    }
+}
+
+/// Two summaries side by side, nest the pairs for more, as in `(Count, (Min<V>, Max<V>))`.
+impl<T, V, A: Summary<T, V>, B: Summary<T, V>> Summary<T, V> for (A, B) {
+    fn empty() -> Self {
+        (A::empty(), B::empty())
+    }
+
+    fn of(key: &T, data: &V) -> Self {
+        (A::of(key, data), B::of(key, data))
+    }
+
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+/// A map from `T` to `V` keeping the summaries `S` of its subtrees, an AVL tree whose nodes
+/// recompute their summary along with their height.
+pub struct AggregateTree<T, V, S, C = Natural> {
+    root: AvlBoxOption<T, V, S>,
+    len: usize,
+    cmp: C,
+}
+
+impl<T, V, S, C: Default> Default for AggregateTree<T, V, S, C> {
+    fn default() -> Self {
+        AggregateTree::with_comparator(C::default())
+    }
+}
+
+impl<T: Ord, V, S> AggregateTree<T, V, S> {
+    pub fn new() -> AggregateTree<T, V, S> {
+        AggregateTree::with_comparator(Natural)
+    }
+}
+
+impl<T, V, S, C> AggregateTree<T, V, S, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> AggregateTree<T, V, S, C> {
+        AggregateTree { root: None, len: 0, cmp }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, V, S> {
+        Iter::new(&self.root)
+    }
+
+    /// The shape of the tree with the balance factors of its nodes.
+    pub fn stats(&self) -> Stats {
+        let mut stats = util::stats(&self.root);
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+}
+
+impl<T, V, S: Summary<T, V>, C: Compare<T>> AggregateTree<T, V, S, C> {
+    pub fn search(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.value) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => n.left.as_deref(),
+                Ordering::Greater => n.right.as_deref(),
+            };
+        }
+        None
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        match util::entry_path(&self.root, &key, &self.cmp) {
+            (mut path, true) => {
+                let root = self.root.as_mut().expect("the path leads to a node");
+                Some(util::update_at(root, &mut path, |old| mem::replace(old, data)))
+            }
+            (mut path, false) => {
+                self.root = Some(util::attach(self.root.take(), &mut path, key, data));
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        let (mut path, true) = util::entry_path(&self.root, key, &self.cmp) else { return None };
+        let root = self.root.take().expect("the path leads to a node");
+        let (root, (_, data)) = util::detach(root, &mut path);
+        self.root = root;
+        self.len -= 1;
+        Some(data)
+    }
+
+    /// Changes the data of `key` with `f` and updates the summaries, returns `false` if `key` is
+    /// absent.
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: &T, f: F) -> bool {
+        let (mut path, true) = util::entry_path(&self.root, key, &self.cmp) else { return false };
+        util::update_at(self.root.as_mut().expect("the path leads to a node"), &mut path, f);
+        true
+    }
+
+    /// The summary of all the entries.
+    pub fn total(&self) -> S {
+        util::get_summary(&self.root)
+    }
+
+    /// The summary of the entries whose keys are in `range`, combined in the order of the keys.
+    ///
+    /// It goes down to the first node in `range`, then on both sides of it down to the bounds,
+    /// taking the summaries of the subtrees in between: O(log n) combinations.
+    pub fn aggregate<R: RangeBounds<T>>(&self, range: R) -> S {
+        let after_start = |key: &T| match range.start_bound() {
+            Bound::Included(start) => self.cmp.compare(key, start) != Ordering::Less,
+            Bound::Excluded(start) => self.cmp.compare(key, start) == Ordering::Greater,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &T| match range.end_bound() {
+            Bound::Included(end) => self.cmp.compare(key, end) != Ordering::Greater,
+            Bound::Excluded(end) => self.cmp.compare(key, end) == Ordering::Less,
+            Bound::Unbounded => true,
+        };
+
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            if !after_start(&n.value) {
+                node = n.right.as_deref();
+            } else if !before_end(&n.value) {
+                node = n.left.as_deref();
+            } else {
+                break;
+            }
+        }
+        let Some(split) = node else { return S::empty() };
+
+        // the left side, every node after the start is followed by its right subtree and by what
+        // was found before
+        let mut left = S::empty();
+        let mut node = split.left.as_deref();
+        while let Some(n) = node {
+            if after_start(&n.value) {
+                left = S::of(&n.value, &n.data).combine(&util::get_summary(&n.right)).combine(&left);
+                node = n.left.as_deref();
+            } else {
+                node = n.right.as_deref();
+            }
+        }
+        // the mirror image on the right side
+        let mut right = S::empty();
+        let mut node = split.right.as_deref();
+        while let Some(n) = node {
+            if before_end(&n.value) {
+                right = right.combine(&util::get_summary(&n.left)).combine(&S::of(&n.value, &n.data));
+                node = n.right.as_deref();
+            } else {
+                node = n.left.as_deref();
+            }
+        }
+        left.combine(&S::of(&split.value, &split.data)).combine(&right)
+    }
+
+    /// Checks the order of the keys, the length, the heights and the balance of the nodes and
+    /// recomputes every summary.
+    pub fn validate(&self) -> Result<(), String>
+    where
+        S: PartialEq,
+    {
+        util::validate(&self.root, self.len, &self.cmp)
+    }
+}
+
+impl<T: Display, V, S, C> AggregateTree<T, V, S, C> {
+    pub fn sketch(&self) -> Option<Sketch> {
+        self.root.as_deref().map(util::sketch)
+    }
+}
+
+impl<T: Display, V, S, C> Display for AggregateTree<T, V, S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::ops::Range;
+    use crate::compare::Reverse;
+    use crate::testing::Random;
+    use super::*;
+
+    type Rollup = (Count, (Sum<i64>, (Min<i64>, Max<i64>)));
+
+    fn rollup<'a>(entries: impl Iterator<Item = (&'a i64, &'a i64)>) -> Rollup {
+        entries.fold(<Rollup as Summary<i64, i64>>::empty(), |summary, (key, data)| {
+            <Rollup as Summary<i64, i64>>::combine(&summary, &Summary::of(key, data))
+        })
+    }
+
+    /// The keys in order, a summary whose `combine` is not commutative.
+    #[derive(Debug, PartialEq, Clone)]
+    struct Keys(Vec<i32>);
+
+    impl<V> Summary<i32, V> for Keys {
+        fn empty() -> Self {
+            Keys(vec![])
+        }
+
+        fn of(key: &i32, _: &V) -> Self {
+            Keys(vec![*key])
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Keys(self.0.iter().chain(&other.0).copied().collect())
+        }
+    }
+
+    thread_local! {
+        static COMBINATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// A count which counts its combinations as well.
+    #[derive(Debug, PartialEq, Clone)]
+    struct Counted(usize);
+
+    impl<T, V> Summary<T, V> for Counted {
+        fn empty() -> Self {
+            Counted(0)
+        }
+
+        fn of(_: &T, _: &V) -> Self {
+            Counted(1)
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            COMBINATIONS.with(|combinations| combinations.set(combinations.get() + 1));
+            Counted(self.0 + other.0)
+        }
+    }
+
+    #[test]
+    fn test_windowed_rollup() {
+        // a reading every 10 seconds
+        let mut tree: AggregateTree<i64, i64, Rollup> = AggregateTree::new();
+        for (i, reading) in [5, 3, 9, 1, 7, 2, 8].into_iter().enumerate() {
+            tree.insert(10 * i as i64, reading);
+        }
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.total(), (Count(7), (Sum(35), (Min(Some(1)), Max(Some(9))))));
+        assert_eq!(tree.aggregate(10..40), (Count(3), (Sum(13), (Min(Some(1)), Max(Some(9))))));
+        assert_eq!(tree.aggregate(15..=40), (Count(3), (Sum(17), (Min(Some(1)), Max(Some(9))))));
+        assert_eq!(tree.aggregate(..20), (Count(2), (Sum(8), (Min(Some(3)), Max(Some(5))))));
+        assert_eq!(tree.aggregate(61..), (Count(0), (Sum(0), (Min(None), Max(None)))));
+
+        assert!(tree.update(&30, |reading| *reading += 10));
+        assert!(!tree.update(&35, |reading| *reading += 10));
+        assert_eq!(tree.aggregate(30..=30), (Count(1), (Sum(11), (Min(Some(11)), Max(Some(11))))));
+        assert_eq!(tree.remove(&20), Some(9));
+        assert_eq!(tree.aggregate(10..40), (Count(2), (Sum(14), (Min(Some(3)), Max(Some(11))))));
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_order_of_combination() {
+        let mut tree: AggregateTree<i32, (), Keys> = AggregateTree::new();
+        for key in 0..200 {
+            tree.insert((key * 37) % 200, ());
+        }
+        for (start, end) in [(0, 200), (13, 14), (13, 13), (50, 150), (199, 250), (-5, 3)] {
+            assert_eq!(tree.aggregate(start..end).0, (start.max(0)..end.min(200)).collect::<Vec<i32>>());
+        }
+        assert_eq!(tree.aggregate((Bound::Excluded(10), Bound::Included(15))).0, vec![11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_logarithmic_combinations() {
+        let mut tree: AggregateTree<i32, (), Counted> = AggregateTree::new();
+        for key in 0..1 << 16 {
+            tree.insert(key, ());
+        }
+        let height = tree.stats().height;
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let (a, b) = ((random.next_u64() % (1 << 16)) as i32, (random.next_u64() % (1 << 16)) as i32);
+            let range = a.min(b)..a.max(b);
+            COMBINATIONS.with(|combinations| combinations.set(0));
+            assert_eq!(tree.aggregate(range.clone()).0, range.len());
+            // two on every level on each side and two at the top
+            assert!(COMBINATIONS.with(Cell::get) <= 4 * height + 2);
+        }
+    }
+
+    #[test]
+    fn test_random_against_brute_force() {
+        let mut tree: AggregateTree<i64, i64, Rollup> = AggregateTree::new();
+        let mut expected = BTreeMap::new();
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for i in 0..5000 {
+            let key = (random.next_u64() % 500) as i64;
+            let data = (random.next_u64() % 1000) as i64 - 500;
+            match random.next_u64() % 4 {
+                0 => assert_eq!(tree.remove(&key), expected.remove(&key)),
+                1 => {
+                    let found = expected.get_mut(&key).map(|old| *old += data).is_some();
+                    assert_eq!(tree.update(&key, |old| *old += data), found);
+                }
+                _ => assert_eq!(tree.insert(key, data), expected.insert(key, data)),
+            }
+            let Range { start, end } = {
+                let (a, b) = ((random.next_u64() % 520) as i64, (random.next_u64() % 520) as i64);
+                a.min(b)..a.max(b)
+            };
+            assert_eq!(tree.aggregate(start..end), rollup(expected.range(start..end)));
+            assert_eq!(tree.aggregate(start..=end), rollup(expected.range(start..=end)));
+            if i % 100 == 0 {
+                assert!(tree.validate().is_ok());
+            }
+        }
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.total(), rollup(expected.iter()));
+        assert!(tree.iter().map(|(&key, &data)| (key, data)).eq(expected));
+    }
+
+    #[test]
+    fn test_comparator() {
+        let mut tree: AggregateTree<i32, (), Keys, Reverse> = AggregateTree::default();
+        for key in 0..10 {
+            tree.insert(key, ());
+        }
+        // the range follows the order of the tree
+        assert_eq!(tree.aggregate((Bound::Included(7), Bound::Included(2))).0, vec![7, 6, 5, 4, 3, 2]);
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut tree: AggregateTree<i32, i32, Sum<i32>> = AggregateTree::new();
+        for key in 0..1000 {
+            tree.insert(key, key);
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.nodes, 1000);
+        assert!(stats.height_ratio() <= 1.44);
+        assert!(stats.balance.unwrap().keys().all(|factor| factor.abs() <= 1));
+    }
+}
@@ -1,20 +1,37 @@
-#![allow(dead_code, unused_variables, unused_mut, unused_assignments, unused_imports)]
 
-use std::cmp::{max, Ordering};
-use std::fmt::Debug;
-use crate::avl::util::get_height;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::{max, Ordering};
+use core::fmt::{self, Debug, Display};
+use core::mem;
+use crate::aggregate::Summary;
+use crate::compare::{Compare, Natural};
+use crate::error::TreeError;
+use crate::map::OrderedMap;
+use crate::path::{self, Descend, Path};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
 
-type AvlBoxOption<T> = Option<Box<Avl<T>>>;
+pub(crate) type AvlBoxOption<T, V = (), S = ()> = Option<Box<Avl<T, V, S>>>;
 
+/// A node of an AVL tree, every node is the root of a non-empty tree.
+///
+/// `data` is the value associated with `value` when the tree is used as a map, it is `()` for a set.
+/// `summary` summarizes the entries of the subtree for an `AggregateTree`, it is `()` otherwise.
 #[derive(Debug)]
-struct Avl<T: Ord + Debug> {
-    value: T,
-    height: i32,
-    left: Option<Box<Avl<T>>>,
-    right: Option<Box<Avl<T>>>,
+pub(crate) struct Avl<T, V = (), S = ()> {
+    pub(crate) value: T,
+    pub(crate) data: V,
+    pub(crate) height: i32,
+    pub(crate) summary: S,
+    pub(crate) left: Option<Box<Avl<T, V, S>>>,
+    pub(crate) right: Option<Box<Avl<T, V, S>>>,
 }
 
-fn display_avl<T: Ord + std::fmt::Debug>(avl: &Avl<T>, level: usize, v: &str) {
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+fn display_avl<T: Debug, V>(avl: &Avl<T, V>, level: usize, v: &str) {
     println!(
         "{:indent$}{v}{:?}",
         "",
@@ -31,47 +48,58 @@ fn display_avl<T: Ord + std::fmt::Debug>(avl: &Avl<T>, level: usize, v: &str) {
     }
 }
 
-impl<T: Ord + Debug> Avl<T> {
+// The nodes are used directly by the tests only, `AvlTree` is the tree of the library.
+#[allow(dead_code)]
+impl<T: Ord> Avl<T> {
     pub fn new(value: T) -> Box<Avl<T>> {
-        Box::new(Self {
-            value,
-            height: 0,
-            left: None,
-            right: None,
-        })
+        Avl::with_data(value, ())
+    }
+
+    pub fn insert(self, new_value: T) -> Avl<T> {
+        self.insert_data(new_value, ())
     }
+}
 
+#[allow(dead_code, unused_variables)]
+impl<T: Ord, V> Avl<T, V> {
+    #[allow(clippy::needless_borrow)]
     pub fn search(&self, value: &T) -> bool {
         match &self {
             &Self {
                 value: val,
                 height,
                 ref left,
-                ref right
+                ref right,
+                ..
             } => {
                 match val.cmp(&value) {
                     Ordering::Equal => true,
-                    Ordering::Less => util::search(right, value),
-                    Ordering::Greater => util::search(left, value),
+                    Ordering::Less => util::search(right, value, &Natural),
+                    Ordering::Greater => util::search(left, value, &Natural),
                 }
             }
         }
     }
 
-    pub fn insert(mut self, new_value: T) -> Avl<T> {
-        if self.value < new_value {
-            self.right = util::insert(self.right, new_value);
-        } else {
-            self.left = util::insert(self.left, new_value)
-        }
+    pub fn insert_data(self, new_value: T, data: V) -> Avl<T, V> {
+        *util::insert(Some(Box::new(self)), new_value, data, &Natural).expect("a tree with a new node isn't empty")
+    }
+}
 
-        let mut new_node = self.rotate();
-        let lh = get_height(&new_node.left);
-        let rh = get_height(&new_node.right);
-        new_node.update_height();
-        *new_node
+impl<T, V, S: Summary<T, V>> Avl<T, V, S> {
+    pub fn with_data(value: T, data: V) -> Box<Avl<T, V, S>> {
+        let summary = S::of(&value, &data);
+        Box::new(Self {
+            value,
+            data,
+            height: 0,
+            summary,
+            left: None,
+            right: None,
+        })
     }
 
+    #[allow(dead_code, unused_variables)]
     pub fn delete(&mut self, value: T) -> bool {
         // TODO implement me
         false
@@ -79,47 +107,64 @@ impl<T: Ord + Debug> Avl<T> {
 
     // Rotating a Node may modify the height of itself, child, grandchild and all of its parent node.
     // We make sure The height of parent node is correct by call the update_height recursively.
-    fn rotate(mut self) -> Box<Avl<T>> {
+    fn rotate(self) -> Box<Avl<T, V, S>> {
+        self.rotate_tracking(&mut Path::new())
+    }
+
+    // Same as `rotate`, `path` starts at this node and is updated to lead to the same node afterwards.
+    fn rotate_tracking(self, path: &mut Path) -> Box<Avl<T, V, S>> {
+        self.try_rotate_tracking(path).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // A child leaning to neither side only happens after a deletion, a single rotation is enough then.
+    // The heights kept by the tree never ask for a missing child, wrong ones fail with `MissingChild`.
+    fn try_rotate_tracking(mut self, path: &mut Path) -> Result<Box<Avl<T, V, S>>, TreeError> {
         if self.balance_factor() > 1 {
             let left = self.left.take();
             match left {
-                None => panic!("error"),
+                None => Err(TreeError::MissingChild),
                 Some(child) => {
-                    if child.balance_factor() > 0 {
+                    if child.balance_factor() >= 0 {
                         // without right node, move left to the original value
                         self.left.replace(child);
-                        self.right_rotate()
+                        path::rotate(path, false);
+                        Ok(self.right_rotate())
                     } else {
                         // with right node
                         let left = child.left_rotate();
                         self.left.replace(left);
-                        self.right_rotate()
+                        path::rotate_child(path, true, true);
+                        path::rotate(path, false);
+                        Ok(self.right_rotate())
                     }
                 }
             }
         } else if self.balance_factor() < -1 {
             let right = self.right.take();
             match right {
-                None => panic!("error"),
+                None => Err(TreeError::MissingChild),
                 Some(child) => {
                     // Right-leaning tree
-                    if child.balance_factor() < 0 {
+                    if child.balance_factor() <= 0 {
                         // put right back
                         self.right.replace(child);
-                        self.left_rotate()
+                        path::rotate(path, true);
+                        Ok(self.left_rotate())
                     } else {
                         let right = child.right_rotate();
                         self.right.replace(right);
-                        self.left_rotate()
+                        path::rotate_child(path, false, false);
+                        path::rotate(path, true);
+                        Ok(self.left_rotate())
                     }
                 }
             }
         } else {
-            Box::new(self)
+            Ok(Box::new(self))
         }
     }
 
-    fn right_rotate(mut self) -> Box<Avl<T>> {
+    fn right_rotate(mut self) -> Box<Avl<T, V, S>> {
         let mut child = match self.left.take() {
             None => return Box::new(self),
             Some(node) => node
@@ -141,7 +186,7 @@ impl<T: Ord + Debug> Avl<T> {
         }
     }
 
-    fn left_rotate(mut self) -> Box<Avl<T>> {
+    fn left_rotate(mut self) -> Box<Avl<T, V, S>> {
         let mut child = match self.right.take() {
             None => return Box::new(self),
             Some(node) => node
@@ -162,54 +207,553 @@ impl<T: Ord + Debug> Avl<T> {
         }
     }
 
+    /// Recomputes the height and the summary of this node from its children.
     fn update_height(&mut self) {
-        use util::get_height;
-        self.height = max(get_height(&self.left), get_height(&self.right)) + 1
+        use util::{get_height, get_summary};
+        self.height = max(get_height(&self.left), get_height(&self.right)) + 1;
+        self.summary = get_summary(&self.left).combine(&S::of(&self.value, &self.data)).combine(&get_summary(&self.right));
     }
 
     fn balance_factor(&self) -> i32 {
         util::get_height(&self.left) - util::get_height(&self.right)
     }
 
+    #[allow(dead_code)]
     fn height(&self) -> i32 {
         self.height
     }
 }
 
-mod util {
-    use crate::avl::{Avl, AvlBoxOption};
-    use std::cmp::Ordering;
-    use std::fmt::Debug;
+/// An AVL tree owning an optional root, as a node `Avl` can't represent an empty tree.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The values are ordered by
+/// the comparator `C`, their natural order by default.
+pub struct AvlTree<T, V = (), C = Natural> {
+    root: AvlBoxOption<T, V>,
+    len: usize,
+    cmp: C,
+}
+
+impl<T, V, C: Default> Default for AvlTree<T, V, C> {
+    fn default() -> Self {
+        AvlTree { root: None, len: 0, cmp: C::default() }
+    }
+}
+
+impl<T: Ord, V> AvlTree<T, V> {
+    pub fn new() -> AvlTree<T, V> {
+        AvlTree::with_comparator(Natural)
+    }
+
+    /// A balanced tree of `entries`, which are sorted and unique, built in linear time. `validate`
+    /// reports entries out of order.
+    pub fn from_sorted(entries: Vec<(T, V)>) -> AvlTree<T, V> {
+        AvlTree::from_sorted_with_comparator(entries, Natural)
+    }
+}
+
+impl<T, V, C: Compare<T>> AvlTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the values.
+    pub fn with_comparator(cmp: C) -> AvlTree<T, V, C> {
+        AvlTree { root: None, len: 0, cmp }
+    }
+
+    /// Same as `from_sorted`, `entries` are sorted by `cmp`.
+    pub fn from_sorted_with_comparator(entries: Vec<(T, V)>, cmp: C) -> AvlTree<T, V, C> {
+        let len = entries.len();
+        AvlTree { root: util::build(&mut entries.into_iter(), len), len, cmp }
+    }
+
+    /// Keeps the entries `f` accepts and rebuilds the tree balanced, in linear time.
+    pub fn retain<F: FnMut(&T, &mut V) -> bool>(&mut self, mut f: F) {
+        let mut entries = util::into_sorted(self.root.take());
+        entries.retain_mut(|(value, data)| f(value, data));
+        self.len = entries.len();
+        self.root = util::build(&mut entries.into_iter(), self.len);
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn search(&self, value: &T) -> bool {
+        util::search(&self.root, value, &self.cmp)
+    }
+
+    pub fn get(&self, value: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(value, &n.value) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, value: &T) -> Option<&mut V> {
+        match self.entry_path(value) {
+            (path, true) => Some(&mut util::node_at(&mut self.root, &path).data),
+            (_, false) => None,
+        }
+    }
+
+    /// Inserts `value` with `data`, returns the old data if `value` was present.
+    pub fn insert(&mut self, value: T, data: V) -> Option<V> {
+        match self.entry(value) {
+            Entry::Occupied(mut entry) => Some(entry.insert(data)),
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, value: &T) -> Option<V> {
+        match self.entry_path(value) {
+            (path, true) => Some(self.remove_at(path).1),
+            (_, false) => None,
+        }
+    }
+
+    /// Inserts `value` unless it is there already, unlike `insert` which replaces its data.
+    pub fn try_insert(&mut self, value: T, data: V) -> Result<&mut V, TreeError> {
+        match self.entry(value) {
+            Entry::Occupied(_) => Err(TreeError::Duplicate),
+            Entry::Vacant(entry) => Ok(entry.insert(data)),
+        }
+    }
+
+    /// Gets the entry of `value` for in-place manipulation, the tree is walked down only once.
+    pub fn entry(&mut self, value: T) -> Entry<'_, T, V, C> {
+        match self.entry_path(&value) {
+            (path, true) => Entry::Occupied(OccupiedEntry { tree: self, path }),
+            (path, false) => Entry::Vacant(VacantEntry { tree: self, path, value }),
+        }
+    }
+
+    /// A cursor at the ghost position, which is between the last and the first node.
+    pub fn cursor(&self) -> Cursor<'_, T, V, C> {
+        Cursor { tree: self, path: None }
+    }
+
+    /// A cursor at the ghost position, which is between the last and the first node.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, V, C> {
+        CursorMut { tree: self, path: None }
+    }
+
+    /// Visits the values and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter::new(&self.root)
+    }
+
+    /// Checks the order of the values, the height and the balance of every node and the length.
+    pub fn validate(&self) -> Result<(), String> {
+        util::validate(&self.root, self.len, &self.cmp)
+    }
+
+    /// The shape of the tree with the balance factors of its nodes.
+    pub fn stats(&self) -> Stats {
+        let mut stats = util::stats(&self.root);
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// The path to the smallest node not less than `value`, or greater than `value` if not
+    /// `inclusive`.
+    fn bound_path(&self, value: &T, inclusive: bool) -> Option<Path> {
+        let mut path = Path::new();
+        let mut found = None;
+        let mut node = &self.root;
+        while let Some(n) = node {
+            let left = match self.cmp.compare(&n.value, value) {
+                Ordering::Equal if inclusive => return Some(path),
+                Ordering::Greater => true,
+                _ => false,
+            };
+            if left {
+                found = Some(path.len());
+            }
+            path.push_back(left);
+            node = if left { &n.left } else { &n.right };
+        }
+        found.map(|len| {
+            path.truncate(len);
+            path
+        })
+    }
+
+    /// The path to the node holding `value`, or to the empty slot where it belongs.
+    fn entry_path(&self, value: &T) -> (Path, bool) {
+        util::entry_path(&self.root, value, &self.cmp)
+    }
+
+    fn remove_at(&mut self, mut path: Path) -> (T, V) {
+        let root = self.root.take().expect("the path leads to a node");
+        let (root, removed) = util::detach(root, &mut path);
+        self.root = root;
+        self.len -= 1;
+        removed
+    }
+}
+
+impl<T: Display, V, C> AvlTree<T, V, C> {
+    /// The shape of the tree with the height of every node, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        self.root.as_deref().map(util::sketch)
+    }
+}
+
+impl<T: Display, V, C> Display for AvlTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, S> Descend for Avl<T, V, S> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for AvlTree<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        AvlTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        AvlTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        AvlTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        AvlTree::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        self.entry(key).or_insert_with(default)
+    }
+
+    fn iter(&self) -> Iter<'_, T, V> {
+        AvlTree::iter(self)
+    }
+}
+
+/// An in-order iterator over the values of an `AvlTree` and their data.
+pub struct Iter<'a, T, V, S = ()> {
+    nodes: path::InOrder<'a, Avl<T, V, S>>,
+}
+
+impl<'a, T, V, S> Iter<'a, T, V, S> {
+    pub(crate) fn new(root: &'a AvlBoxOption<T, V, S>) -> Iter<'a, T, V, S> {
+        Iter { nodes: path::InOrder::new(root.as_deref()) }
+    }
+}
+
+impl<'a, T, V, S> Iterator for Iter<'a, T, V, S> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.value, &node.data))
+    }
+}
+
+/// A cursor over an `AvlTree`, it moves forward and backward in order from any position.
+///
+/// The cursor remembers the path from the root, every step follows it down again but doesn't
+/// compare keys. `None` is the ghost position between the last and the first node.
+pub struct Cursor<'a, T, V, C = Natural> {
+    tree: &'a AvlTree<T, V, C>,
+    path: Option<Path>,
+}
+
+impl<'a, T, V, C: Compare<T>> Cursor<'a, T, V, C> {
+    pub fn current(&self) -> Option<(&'a T, &'a V)> {
+        let node = path::follow(self.tree.root.as_deref()?, self.path.as_ref()?);
+        Some((&node.value, &node.data))
+    }
+
+    /// Moves to the smallest node not less than `value`, or to the ghost position if there is none.
+    pub fn seek(&mut self, value: &T) {
+        self.path = self.tree.bound_path(value, true);
+    }
+
+    pub fn next(&mut self) {
+        self.path = path::move_cursor(self.tree.root.as_deref(), self.path.take(), true);
+    }
+
+    pub fn prev(&mut self) {
+        self.path = path::move_cursor(self.tree.root.as_deref(), self.path.take(), false);
+    }
+}
+
+/// A cursor which can also change the data in place, and remove or insert nodes next to its
+/// position. The tree is rebalanced as usual.
+pub struct CursorMut<'a, T, V, C = Natural> {
+    tree: &'a mut AvlTree<T, V, C>,
+    path: Option<Path>,
+}
+
+impl<'a, T, V, C: Compare<T>> CursorMut<'a, T, V, C> {
+    pub fn current(&mut self) -> Option<(&T, &mut V)> {
+        let path = self.path.as_ref()?;
+        let node = util::node_at(&mut self.tree.root, path);
+        Some((&node.value, &mut node.data))
+    }
+
+    /// Moves to the smallest node not less than `value`, or to the ghost position if there is none.
+    pub fn seek(&mut self, value: &T) {
+        self.path = self.tree.bound_path(value, true);
+    }
+
+    pub fn next(&mut self) {
+        self.path = path::move_cursor(self.tree.root.as_deref(), self.path.take(), true);
+    }
+
+    pub fn prev(&mut self) {
+        self.path = path::move_cursor(self.tree.root.as_deref(), self.path.take(), false);
+    }
+
+    /// Removes the current node and moves to the next one.
+    pub fn remove_current(&mut self) -> Option<(T, V)> {
+        let path = self.path.take()?;
+        let removed = self.tree.remove_at(path);
+        // the rotations on the way up moved the nodes around, find the next one by its key
+        self.path = self.tree.bound_path(&removed.0, false);
+        Some(removed)
+    }
+
+    /// Inserts a node right after the current one, or before the first node at the ghost position.
+    /// The cursor doesn't move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` doesn't sort between the current and the next node.
+    pub fn insert_after(&mut self, value: T, data: V) {
+        let root = self.tree.root.as_deref();
+        if let Some(current) = self.path.as_ref() {
+            let current = path::follow(root.unwrap(), current);
+            let ordered = self.tree.cmp.compare(&current.value, &value) == Ordering::Less;
+            assert!(ordered, "the value must be greater than the current one");
+        }
+        if let Some(next) = path::move_cursor(root, self.path.clone(), true) {
+            let next = path::follow(root.unwrap(), &next);
+            let ordered = self.tree.cmp.compare(&value, &next.value) == Ordering::Less;
+            assert!(ordered, "the value must be less than the next one");
+        }
+
+        let mut slot = match (root, self.path.as_ref()) {
+            (Some(root), Some(current)) => path::slot_after(root, current),
+            (root, _) => path::slot_first(root),
+        };
+        self.tree.root = Some(util::attach(self.tree.root.take(), &mut slot, value, data));
+        self.tree.len += 1;
+
+        // the current node is right before the new one, wherever the rotations have moved them
+        if self.path.is_some() {
+            path::step(self.tree.root.as_deref().unwrap(), &mut slot, false);
+            self.path = Some(slot);
+        }
+    }
+}
+
+/// A view into a single entry of an `AvlTree`, which may either be vacant or occupied.
+pub enum Entry<'a, T, V, C = Natural> {
+    Occupied(OccupiedEntry<'a, T, V, C>),
+    Vacant(VacantEntry<'a, T, V, C>),
+}
+
+pub struct OccupiedEntry<'a, T, V, C = Natural> {
+    tree: &'a mut AvlTree<T, V, C>,
+    path: Path,
+}
+
+pub struct VacantEntry<'a, T, V, C = Natural> {
+    tree: &'a mut AvlTree<T, V, C>,
+    path: Path,
+    value: T,
+}
+
+impl<'a, T, V, C: Compare<T>> Entry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, T, V, C: Compare<T>> OccupiedEntry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        &util::node_at_ref(&self.tree.root, &self.path).value
+    }
+
+    pub fn get(&self) -> &V {
+        &util::node_at_ref(&self.tree.root, &self.path).data
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut util::node_at(&mut self.tree.root, &self.path).data
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut util::node_at(&mut self.tree.root, &self.path).data
+    }
+
+    pub fn insert(&mut self, data: V) -> V {
+        mem::replace(self.get_mut(), data)
+    }
+
+    pub fn remove_entry(self) -> (T, V) {
+        self.tree.remove_at(self.path)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, T, V, C: Compare<T>> VacantEntry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_key(self) -> T {
+        self.value
+    }
+
+    /// Adds the new node where the search ended and rebalances the nodes above it.
+    pub fn insert(self, data: V) -> &'a mut V {
+        let VacantEntry { tree, mut path, value } = self;
+        tree.root = Some(util::attach(tree.root.take(), &mut path, value, data));
+        tree.len += 1;
+        &mut util::node_at(&mut tree.root, &path).data
+    }
+}
 
-    pub(crate) fn get_height<T: Ord + Debug>(node: &AvlBoxOption<T>) -> i32 {
+pub(crate) mod util {
+    use alloc::boxed::Box;
+    use alloc::collections::BTreeMap;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::{vec, vec::Vec};
+    use core::cmp::{max, Ordering};
+    use core::fmt::Display;
+    use core::mem;
+    use crate::aggregate::Summary;
+    use crate::avl::{Avl, AvlBoxOption, Iter};
+    use crate::compare::Compare;
+    use crate::path::Path;
+    use crate::render::Sketch;
+    use crate::stats::{self, Stats};
+
+    pub(crate) fn get_height<T, V, S>(node: &AvlBoxOption<T, V, S>) -> i32 {
         match node {
             None => -1,
             Some(node) => node.height,
         }
     }
 
-    pub(crate) fn update_height<T: Ord + Debug>(node: &mut AvlBoxOption<T>) {
+    pub(crate) fn get_summary<T, V, S: Summary<T, V>>(node: &AvlBoxOption<T, V, S>) -> S {
+        node.as_ref().map_or_else(S::empty, |node| node.summary.clone())
+    }
+
+    pub(crate) fn update_height<T, V, S: Summary<T, V>>(node: &mut AvlBoxOption<T, V, S>) {
         match node {
             None => {}
             Some(node) => node.update_height(),
         }
     }
 
-    pub(crate) fn insert<T: Ord + Debug>(root_opt: AvlBoxOption<T>, new_value: T) -> AvlBoxOption<T> {
+    /// A tree of the next `len` entries, the middle one at the root. The sizes of the subtrees
+    /// differ by one at most, and so do their heights.
+    pub(crate) fn build<T, V, S: Summary<T, V>>(entries: &mut vec::IntoIter<(T, V)>, len: usize) -> AvlBoxOption<T, V, S> {
+        if len == 0 {
+            return None;
+        }
+        let left = build(entries, len / 2);
+        let (value, data) = entries.next().expect("there are `len` entries");
+        let mut node = Avl::with_data(value, data);
+        node.left = left;
+        node.right = build(entries, len - len / 2 - 1);
+        node.update_height();
+        Some(node)
+    }
+
+    /// The entries in order, the nodes are taken apart on the way.
+    pub(crate) fn into_sorted<T, V, S>(root: AvlBoxOption<T, V, S>) -> Vec<(T, V)> {
+        let mut entries = Vec::new();
+        let mut stack: Vec<Box<Avl<T, V, S>>> = Vec::new();
+        let mut node = root;
+        loop {
+            while let Some(mut next) = node {
+                node = next.left.take();
+                stack.push(next);
+            }
+            let Some(mut next) = stack.pop() else { return entries };
+            node = next.right.take();
+            entries.push((next.value, next.data));
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn insert<T, V, S: Summary<T, V>, C: Compare<T>>(root_opt: AvlBoxOption<T, V, S>, new_value: T, data: V, cmp: &C) -> AvlBoxOption<T, V, S> {
         match root_opt {
             None => {
-                Some(Avl::new(new_value))
+                Some(Avl::with_data(new_value, data))
             }
-            Some(root) => {
-                let mut new_root = Avl::insert(*root, new_value);
-                let mut new_root = new_root.rotate();
+            Some(mut root) => {
+                if cmp.compare(&root.value, &new_value) == Ordering::Less {
+                    root.right = insert(root.right.take(), new_value, data, cmp);
+                } else {
+                    root.left = insert(root.left.take(), new_value, data, cmp);
+                }
+                let mut new_root = root.rotate();
                 new_root.update_height();
                 Some(new_root)
             }
         }
     }
 
-    pub(crate) fn rotate<T: Ord + Debug>(root: AvlBoxOption<T>) -> AvlBoxOption<T> {
+    pub(crate) fn rotate<T, V, S: Summary<T, V>>(root: AvlBoxOption<T, V, S>) -> AvlBoxOption<T, V, S> {
         match root {
             None => { None }
             Some(root) => {
@@ -220,25 +764,197 @@ mod util {
         }
     }
 
-    pub(crate) fn search<T: Ord + Debug>(node: &AvlBoxOption<T>, value: &T) -> bool {
+    pub(crate) fn search<T, V, S, C: Compare<T>>(node: &AvlBoxOption<T, V, S>, value: &T, cmp: &C) -> bool {
         match node {
             None => false,
             Some(n) => {
-                match n.value.cmp(value) {
+                match cmp.compare(&n.value, value) {
                     Ordering::Equal => true,
-                    Ordering::Less => search(&n.right, value),
-                    Ordering::Greater => search(&n.left, value),
+                    Ordering::Less => search(&n.right, value, cmp),
+                    Ordering::Greater => search(&n.left, value, cmp),
                 }
             }
         }
     }
+
+    pub(crate) fn node_at_ref<'a, T, V, S>(root: &'a AvlBoxOption<T, V, S>, path: &Path) -> &'a Avl<T, V, S> {
+        let mut node = root.as_ref().expect("the path leads to a node");
+        for &left in path {
+            node = if left { &node.left } else { &node.right }.as_ref().expect("the path leads to a node");
+        }
+        node
+    }
+
+    pub(crate) fn node_at<'a, T, V, S>(root: &'a mut AvlBoxOption<T, V, S>, path: &Path) -> &'a mut Avl<T, V, S> {
+        let mut node = root.as_mut().expect("the path leads to a node");
+        for &left in path {
+            node = if left { &mut node.left } else { &mut node.right }.as_mut().expect("the path leads to a node");
+        }
+        node
+    }
+
+    /// The path to the node holding `value`, or to the empty slot where it belongs.
+    pub(crate) fn entry_path<T, V, S, C: Compare<T>>(root: &AvlBoxOption<T, V, S>, value: &T, cmp: &C) -> (Path, bool) {
+        let mut path = Path::new();
+        let mut node = root;
+        while let Some(n) = node {
+            let left = match cmp.compare(value, &n.value) {
+                Ordering::Equal => return (path, true),
+                Ordering::Less => true,
+                Ordering::Greater => false,
+            };
+            path.push_back(left);
+            node = if left { &n.left } else { &n.right };
+        }
+        (path, false)
+    }
+
+    /// Checks the order of the values, the height, the balance and the summary of every node and
+    /// the length.
+    pub(crate) fn validate<T, V, S: Summary<T, V> + PartialEq, C: Compare<T>>(root: &AvlBoxOption<T, V, S>, len: usize, cmp: &C) -> Result<(), String> {
+        fn check<T, V, S: Summary<T, V> + PartialEq>(node: &AvlBoxOption<T, V, S>) -> Result<(i32, S), String> {
+            let Some(node) = node else { return Ok((-1, S::empty())) };
+            let (lh, left) = check(&node.left)?;
+            let (rh, right) = check(&node.right)?;
+            if (lh - rh).abs() > 1 {
+                return Err(format!("a node has subtrees of the heights {} and {}", lh, rh));
+            }
+            if node.height != max(lh, rh) + 1 {
+                return Err(format!("a node has the height {} instead of {}", node.height, max(lh, rh) + 1));
+            }
+            let summary = left.combine(&S::of(&node.value, &node.data)).combine(&right);
+            if node.summary != summary {
+                return Err("a node has a stale summary".to_string());
+            }
+            Ok((node.height, summary))
+        }
+
+        check(root)?;
+        let mut previous = None;
+        let mut count = 0;
+        for (value, _) in Iter::new(root) {
+            if previous.is_some_and(|previous| cmp.compare(previous, value) != Ordering::Less) {
+                return Err(format!("the values at {} and {} are out of order", count - 1, count));
+            }
+            previous = Some(value);
+            count += 1;
+        }
+        if count != len {
+            return Err(format!("the length is {} but there are {} values", len, count));
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree with the balance factors of its nodes, without the room of the tree
+    /// itself.
+    pub(crate) fn stats<T, V, S>(root: &AvlBoxOption<T, V, S>) -> Stats {
+        let mut balance = BTreeMap::new();
+        let mut stats = stats::binary(root.as_deref(), mem::size_of::<Avl<T, V, S>>(), |node| {
+            *balance.entry(get_height(&node.left) - get_height(&node.right)).or_insert(0) += 1;
+        });
+        stats.balance = Some(balance);
+        stats
+    }
+
+    /// The shape of the tree with the height of every node, to be drawn by `render`.
+    pub(crate) fn sketch<T: Display, V, S>(node: &Avl<T, V, S>) -> Sketch {
+        let children = vec![node.left.as_deref().map(sketch), node.right.as_deref().map(sketch)];
+        Sketch { height: Some(node.height), ..Sketch::new(node.value.to_string(), children) }
+    }
+
+    /// Changes the data of the node `path` leads to with `f` and recomputes the summaries on the way
+    /// back up.
+    pub(crate) fn update_at<T, V, S: Summary<T, V>, R, F: FnOnce(&mut V) -> R>(node: &mut Avl<T, V, S>, path: &mut Path, f: F) -> R {
+        let result = match path.pop_front() {
+            None => f(&mut node.data),
+            Some(left) => {
+                let child = if left { &mut node.left } else { &mut node.right };
+                update_at(child.as_mut().expect("the path leads to a node"), path, f)
+            }
+        };
+        node.update_height();
+        result
+    }
+
+    /// Adds a new node at the empty slot `path` leads to and rotates the nodes on the way back up,
+    /// on return `path` leads to the new node again.
+    pub(crate) fn attach<T, V, S: Summary<T, V>>(node: AvlBoxOption<T, V, S>, path: &mut Path, value: T, data: V) -> Box<Avl<T, V, S>> {
+        let mut node = match node {
+            None => return Avl::with_data(value, data),
+            Some(node) => node,
+        };
+
+        let left = path.pop_front().expect("the path ends at an empty slot");
+        if left {
+            node.left = Some(attach(node.left.take(), path, value, data));
+        } else {
+            node.right = Some(attach(node.right.take(), path, value, data));
+        }
+        path.push_front(left);
+
+        let mut node = node.rotate_tracking(path);
+        node.update_height();
+        node
+    }
+
+    /// Removes the node `path` leads to and rotates the nodes on the way back up.
+    pub(crate) fn detach<T, V, S: Summary<T, V>>(mut node: Box<Avl<T, V, S>>, path: &mut Path) -> (AvlBoxOption<T, V, S>, (T, V)) {
+        let removed = match path.pop_front() {
+            Some(true) => {
+                let (left, removed) = detach(node.left.take().expect("the path leads to a node"), path);
+                node.left = left;
+                removed
+            }
+            Some(false) => {
+                let (right, removed) = detach(node.right.take().expect("the path leads to a node"), path);
+                node.right = right;
+                removed
+            }
+            None => {
+                // replace the node by its successor, the smallest node of the right subtree
+                let replacement = match (node.left.take(), node.right.take()) {
+                    (left, None) => left,
+                    (None, right) => right,
+                    (left, Some(right)) => {
+                        let (right, mut successor) = detach_min(right);
+                        successor.left = left;
+                        successor.right = right;
+                        rotate(Some(successor))
+                    }
+                };
+                let Avl { value, data, .. } = *node;
+                return (replacement, (value, data));
+            }
+        };
+        (rotate(Some(node)), removed)
+    }
+
+    /// The rest of a subtree and the node taken out of it.
+    type Detached<T, V, S> = (AvlBoxOption<T, V, S>, Box<Avl<T, V, S>>);
+
+    fn detach_min<T, V, S: Summary<T, V>>(mut node: Box<Avl<T, V, S>>) -> Detached<T, V, S> {
+        match node.left.take() {
+            None => (node.right.take(), node),
+            Some(left) => {
+                let (left, min) = detach_min(left);
+                node.left = left;
+                (rotate(Some(node)), min)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::avl::{display_avl, Avl};
+    use crate::avl::{Avl, AvlBoxOption, AvlTree, Entry};
+    use crate::compare::{Natural, Reverse};
+    use crate::error::TreeError;
+    use crate::path::Path;
+    use crate::testing::{check_ordered_map, check_reverse_order};
+    use std::collections::BTreeMap;
 
     #[test]
+    #[allow(unused_mut)]
     fn test_insert() {
         let root = Avl::new(0);
         assert_eq!(root.height, 0);
@@ -448,6 +1164,7 @@ pub mod tests {
         assert_eq!(four.value, 4);
     }
 
+    #[allow(dead_code)]
     fn assert_option<T: Ord + std::fmt::Debug>(data: &Option<Box<Avl<T>>>, value: Option<T>) {
         match data {
             None => assert!(value.is_none()),
@@ -462,4 +1179,364 @@ pub mod tests {
             }
         }
     }
+
+    /// Checks the order, the heights and the balance of every node, returns the height.
+    fn check_avl<V>(node: &AvlBoxOption<i32, V>, low: Option<i32>, high: Option<i32>) -> i32 {
+        match node {
+            None => -1,
+            Some(n) => {
+                assert!(low.is_none_or(|low| n.value > low));
+                assert!(high.is_none_or(|high| n.value < high));
+                let lh = check_avl(&n.left, low, Some(n.value));
+                let rh = check_avl(&n.right, Some(n.value), high);
+                assert!((lh - rh).abs() <= 1, "unbalanced at {}", n.value);
+                assert_eq!(n.height, lh.max(rh) + 1);
+                n.height
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut tree = AvlTree::new();
+        for word in "a b c a b a d".split(' ') {
+            *tree.entry(word.to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.get(&"a".to_string()), Some(&3));
+        assert_eq!(tree.get(&"b".to_string()), Some(&2));
+        assert_eq!(tree.get(&"d".to_string()), Some(&1));
+
+        tree.entry("c".to_string()).and_modify(|n| *n += 10).or_insert(0);
+        tree.entry("e".to_string()).and_modify(|n| *n += 10).or_insert_with(|| 7);
+        assert_eq!(tree.get(&"c".to_string()), Some(&11));
+        assert_eq!(tree.get(&"e".to_string()), Some(&7));
+
+        match tree.entry("a".to_string()) {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.key(), "a");
+                assert_eq!(entry.remove_entry(), ("a".to_string(), 3));
+            }
+            Entry::Vacant(_) => panic!("a is in the tree"),
+        }
+        assert!(!tree.search(&"a".to_string()));
+        assert_eq!(tree.len(), 4);
+
+        match tree.entry("a".to_string()) {
+            Entry::Occupied(_) => panic!("a has been removed"),
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "a"),
+        }
+    }
+
+    #[test]
+    fn test_entry_after_rotation() {
+        // inserting 2 rotates twice and moves the new node to the root
+        let mut tree = AvlTree::new();
+        tree.insert(1, 'a');
+        tree.insert(3, 'c');
+        let data = tree.entry(2).or_insert('x');
+        assert_eq!(*data, 'x');
+        *data = 'b';
+
+        assert_eq!(tree.root.as_ref().unwrap().value, 2);
+        assert_eq!(tree.get(&1), Some(&'a'));
+        assert_eq!(tree.get(&2), Some(&'b'));
+        assert_eq!(tree.get(&3), Some(&'c'));
+        check_avl(&tree.root, None, None);
+    }
+
+    #[test]
+    fn test_tree_against_btree_map() {
+        let mut tree = AvlTree::new();
+        let mut expected = BTreeMap::new();
+
+        for i in 0..500 {
+            let key = (i * 37) % 101;
+            if i % 3 == 0 {
+                assert_eq!(tree.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key, i), expected.insert(key, i));
+            }
+            check_avl(&tree.root, None, None);
+            assert_eq!(tree.len(), expected.len());
+        }
+
+        for key in 0..101 {
+            assert_eq!(tree.get(&key), expected.get(&key));
+            if let Some(data) = tree.get_mut(&key) {
+                *data += 1;
+            }
+        }
+        while let Some(&key) = expected.keys().next() {
+            assert_eq!(tree.remove(&key), expected.remove(&key).map(|data| data + 1));
+            check_avl(&tree.root, None, None);
+        }
+        assert!(tree.is_empty());
+    }
+
+    fn even_tree() -> AvlTree<i32, i32> {
+        let mut tree = AvlTree::new();
+        for i in (0..20).step_by(2) {
+            tree.insert(i, i * 10);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_cursor() {
+        let tree = even_tree();
+        let mut cursor = tree.cursor();
+        assert_eq!(cursor.current(), None);
+
+        cursor.seek(&5);
+        assert_eq!(cursor.current(), Some((&6, &60)));
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&8, &80)));
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.current(), Some((&4, &40)));
+        cursor.seek(&18);
+        assert_eq!(cursor.current(), Some((&18, &180)));
+        cursor.next();
+        assert_eq!(cursor.current(), None);
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&0, &0)));
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.current(), Some((&18, &180)));
+        cursor.seek(&19);
+        assert_eq!(cursor.current(), None);
+
+        let mut forward = vec![];
+        let mut cursor = tree.cursor();
+        cursor.next();
+        while let Some((value, _)) = cursor.current() {
+            forward.push(*value);
+            cursor.next();
+        }
+        assert_eq!(forward, (0..20).step_by(2).collect::<Vec<_>>());
+
+        let mut backward = vec![];
+        cursor.prev();
+        while let Some((value, _)) = cursor.current() {
+            backward.push(*value);
+            cursor.prev();
+        }
+        forward.reverse();
+        assert_eq!(backward, forward);
+    }
+
+    #[test]
+    fn test_cursor_mut() {
+        let mut tree = even_tree();
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&6);
+        *cursor.current().unwrap().1 += 1;
+        assert_eq!(cursor.remove_current(), Some((6, 61)));
+        assert_eq!(cursor.current(), Some((&8, &mut 80)));
+
+        cursor.insert_after(9, 90);
+        assert_eq!(cursor.current(), Some((&8, &mut 80)));
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&9, &mut 90)));
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.current(), Some((&4, &mut 40)));
+
+        cursor.seek(&100);
+        cursor.insert_after(-1, -10);
+        assert_eq!(cursor.current(), None);
+        cursor.seek(&18);
+        assert_eq!(cursor.remove_current(), Some((18, 180)));
+        assert_eq!(cursor.current(), None);
+
+        check_avl(&tree.root, None, None);
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.get(&-1), Some(&-10));
+        assert_eq!(tree.get(&6), None);
+        assert_eq!(tree.get(&9), Some(&90));
+    }
+
+    #[test]
+    fn test_cursor_mut_rebalance() {
+        let mut tree = AvlTree::new();
+        let mut expected = BTreeMap::new();
+        let mut cursor = tree.cursor_mut();
+        // appending in order through the cursor rotates on almost every insertion
+        for i in 0..200 {
+            cursor.insert_after(i, i);
+            cursor.next();
+            expected.insert(i, i);
+        }
+        check_avl(&tree.root, None, None);
+
+        // remove every third node while walking forward
+        let mut cursor = tree.cursor_mut();
+        cursor.next();
+        let mut i = 0;
+        while let Some((&value, _)) = cursor.current() {
+            if i % 3 == 0 {
+                assert_eq!(cursor.remove_current(), expected.remove_entry(&value));
+            } else {
+                cursor.next();
+            }
+            i += 1;
+        }
+        check_avl(&tree.root, None, None);
+        assert_eq!(tree.len(), expected.len());
+        for (value, data) in expected {
+            assert_eq!(tree.get(&value), Some(&data));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cursor_insert_out_of_order() {
+        let mut tree = even_tree();
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&6);
+        cursor.insert_after(8, 0);
+    }
+
+    #[test]
+    fn test_comparator() {
+        // neither `Ord` nor `Debug`
+        struct Person {
+            name: &'static str,
+            age: u32,
+        }
+
+        let mut tree = AvlTree::with_comparator(|a: &Person, b: &Person| a.age.cmp(&b.age));
+        for (name, age) in [("carol", 41), ("alice", 29), ("bob", 35), ("dave", 29)] {
+            tree.insert(Person { name, age }, ());
+        }
+        // dave has the same age as alice and replaces her data, the key stays alice
+        assert_eq!(tree.len(), 3);
+        let names: Vec<_> = tree.iter().map(|(person, _)| person.name).collect();
+        assert_eq!(names, vec!["alice", "bob", "carol"]);
+        assert!(tree.search(&Person { name: "anyone", age: 35 }));
+
+        let mut tree = AvlTree::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+        tree.insert("Hello".to_string(), 1);
+        assert_eq!(tree.insert("HELLO".to_string(), 2), Some(1));
+        tree.insert("world".to_string(), 3);
+        assert_eq!(tree.get(&"hello".to_string()), Some(&2));
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&"Hi".to_string());
+        assert_eq!(cursor.current(), Some((&"world".to_string(), &mut 3)));
+        assert_eq!(tree.remove(&"WORLD".to_string()), Some(3));
+    }
+
+    #[test]
+    fn test_reverse_comparator() {
+        let mut tree: AvlTree<i32, (), Reverse> = AvlTree::default();
+        let mut cursor = tree.cursor_mut();
+        for i in (0..100).rev() {
+            cursor.insert_after(i, ());
+            cursor.next();
+        }
+        assert!(tree.iter().map(|(&i, _)| i).eq((0..100).rev()));
+        assert_eq!(tree.remove(&50), Some(()));
+        assert!(!tree.search(&50));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut tree = AvlTree::new();
+        assert_eq!(tree.to_string(), "(empty)\n");
+        for i in 1..=4 {
+            tree.insert(i, ());
+        }
+        assert_eq!(tree.to_string(), " 2\n┌┴─┐\n1  3\n   └┐\n    4\n");
+    }
+
+    #[test]
+    fn test_million_sorted_keys() {
+        let mut tree = AvlTree::new();
+        for i in 0..1_000_000 {
+            tree.insert(i, ());
+        }
+        assert_eq!(tree.len(), 1_000_000);
+        assert!(tree.validate().is_ok());
+        assert!(tree.search(&999_999));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut tree = AvlTree::new();
+        assert_eq!(tree.stats().nodes, 0);
+        for i in 1..=4 {
+            tree.insert(i, ());
+        }
+        //  2
+        // / \
+        // 1  3
+        //     \
+        //      4
+        let stats = tree.stats();
+        assert_eq!((stats.nodes, stats.height, stats.leaves), (4, 3, 2));
+        assert_eq!((stats.min_leaf_depth, stats.max_leaf_depth), (1, 2));
+        assert_eq!(stats.balance, Some(BTreeMap::from([(-1, 2), (0, 2)])));
+        assert_eq!(stats.height_excess(), 0);
+
+        for i in 5..1000 {
+            tree.insert(i, ());
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.optimal_height(), 10);
+        // an AVL tree is at most about 1.44 times as high as the optimal height
+        assert!(stats.height_ratio() <= 1.44, "{}", stats);
+        assert!(stats.balance.unwrap().keys().all(|factor| factor.abs() <= 1));
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut tree = AvlTree::new();
+        assert_eq!(tree.try_insert(1, "one").map(|data| *data), Ok("one"));
+        if let Ok(data) = tree.try_insert(2, "two") {
+            *data = "deux";
+        }
+        assert_eq!(tree.try_insert(1, "un"), Err(TreeError::Duplicate));
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&1, &"one"), (&2, &"deux")]);
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_try_rotate() {
+        // the right child claims a height below an empty tree, so the node seems to lean left
+        let mut node = Avl::new(1);
+        let mut right = Avl::new(2);
+        right.height = -5;
+        node.right = Some(right);
+        assert_eq!(node.try_rotate_tracking(&mut Path::new()).err(), Some(TreeError::MissingChild));
+    }
+
+    #[test]
+    fn test_from_sorted_and_retain() {
+        for len in 0..200_usize {
+            let mut tree = AvlTree::from_sorted((0..len).map(|key| (key, key * 2)).collect());
+            assert!(tree.validate().is_ok(), "{}", len);
+            assert!(tree.iter().map(|(&key, &data)| (key, data)).eq((0..len).map(|key| (key, key * 2))));
+            let stats = tree.stats();
+            assert_eq!(stats.height, stats.optimal_height());
+
+            tree.retain(|key, data| {
+                *data += 1;
+                key % 3 == 0
+            });
+            assert!(tree.validate().is_ok(), "{}", len);
+            assert_eq!(tree.len(), len.div_ceil(3));
+            assert!(tree.iter().map(|(&key, &data)| (key, data)).eq((0..len).step_by(3).map(|key| (key, key * 2 + 1))));
+        }
+
+        let tree = AvlTree::from_sorted_with_comparator(vec![(3, ()), (2, ()), (1, ())], Reverse(Natural));
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.get(&1), Some(&()));
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<AvlTree<i32, i32>>().validate().is_ok());
+        assert!(check_reverse_order::<AvlTree<i32, (), Reverse>>().validate().is_ok());
+    }
 }
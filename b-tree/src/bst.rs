@@ -1,9 +1,11 @@
-#![allow(dead_code, unused_variables, unused_mut, unused_assignments, unused_imports)]
 //!
 //! an implementation for BST
 //!
 
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::{vec, vec::Vec};
+use core::fmt;
 
 pub struct Node<T>
 where
@@ -20,32 +22,66 @@ impl<T: fmt::Display + Ord> fmt::Display for Node<T> {
     }
 }
 
+/// Writes the edges in pre-order: the edge to the left child and the edges below it come before the
+/// edge to the right child. The edges still to write are kept on a stack instead of recursing.
 fn write_node<T: fmt::Display + Ord>(node: &Node<T>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    if let Some(left) = &node.left {
-        let _ = f.write_str(format!("{} --> {}[{}L]\n", node.val, left.val, left.val).as_str());
-        let _ = write_node(left, f);
+    let mut edges = vec![];
+    push_edges(&mut edges, node);
+    while let Some((parent, child, side)) = edges.pop() {
+        f.write_str(format!("{} --> {}[{}{}]\n", parent.val, child.val, child.val, side).as_str())?;
+        push_edges(&mut edges, child);
     }
 
+    Ok(())
+}
+
+fn push_edges<'a, T: Ord>(edges: &mut Vec<(&'a Node<T>, &'a Node<T>, char)>, node: &'a Node<T>) {
+    // the right edge goes first, so it is popped last
     if let Some(right) = &node.right {
-        let _ = f.write_str(format!("{} --> {}[{}R]\n", node.val, right.val, right.val).as_str());
-        let _ = write_node(right, f);
+        edges.push((node, right, 'R'));
+    }
+    if let Some(left) = &node.left {
+        edges.push((node, left, 'L'));
     }
+}
 
-    Ok(())
+/// Frees the nodes one by one, dropping the children recursively would take as many stack frames as
+/// the tree is deep, and a tree fed with sorted values is as deep as it is large.
+impl<T: Ord> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut nodes: Vec<Box<Node<T>>> = self.left.take().into_iter().chain(self.right.take()).collect();
+        while let Some(mut node) = nodes.pop() {
+            nodes.extend(node.left.take());
+            nodes.extend(node.right.take());
+        }
+    }
 }
 
 /// a more rust-way implementation for BST
 pub mod rs_bst {
-    use std::cmp::Ordering;
-    use std::fmt;
-
-    pub enum RsNode<T: Ord>
+    use alloc::boxed::Box;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::{vec, vec::Vec};
+    use core::cmp::Ordering;
+    use core::fmt;
+    use core::mem;
+    use crate::map::OrderedMap;
+    use crate::path::{Descend, InOrder};
+    use crate::render::Sketch;
+    use crate::stats::{self, Stats};
+
+    /// `data` is the value associated with `val` when the tree is used as a map, it is `()` for a set.
+    #[derive(Default)]
+    pub enum RsNode<T: Ord, V = ()>
     {
         Node {
             val: T,
-            left: Box<RsNode<T>>,
-            right: Box<RsNode<T>>,
+            data: V,
+            left: Box<RsNode<T, V>>,
+            right: Box<RsNode<T, V>>,
         },
+        #[default]
         Empty,
     }
 
@@ -55,81 +91,293 @@ pub mod rs_bst {
         }
 
         pub fn create(val: T) -> RsNode<T> {
+            RsNode::with_data(val, ())
+        }
+
+        /// Adds `new_value`, nothing happens if it is in the tree already.
+        pub fn add(&mut self, new_value: T) {
+            let node = self.slot(&new_value);
+            if node.is_empty() {
+                *node = RsNode::create(new_value);
+            }
+        }
+    }
+
+    impl<T: Ord, V> RsNode<T, V> {
+        pub fn with_data(val: T, data: V) -> RsNode<T, V> {
             RsNode::Node {
                 val,
+                data,
                 left: Box::new(RsNode::Empty),
                 right: Box::new(RsNode::Empty),
             }
         }
 
-        pub fn add(&mut self, new_value: T) {
+        pub fn find(&self, find_value: T) -> bool {
+            let mut node = self;
+            while let RsNode::Node { val, left, right, .. } = node {
+                node = match find_value.cmp(val) {
+                    Ordering::Less => left,
+                    Ordering::Greater => right,
+                    Ordering::Equal => return true,
+                };
+            }
+            false
+        }
+
+        /// Removes `value`, a node with two children takes the value of the smallest node of its
+        /// right subtree, which is removed instead. Returns `false` if `value` wasn't present.
+        pub fn remove(&mut self, value: &T) -> bool {
+            self.detach(value).is_some()
+        }
+
+        /// Takes the node of `value` out of the tree, with empty children. It is the node of the
+        /// successor of `value` if that has two children, their values and data are swapped first.
+        fn detach(&mut self, value: &T) -> Option<RsNode<T, V>> {
+            let node = self.slot(value);
+            if node.is_empty() {
+                return None;
+            }
+
+            if node.child(true).is_empty() || node.child(false).is_empty() {
+                let only_child = node.child(true).is_empty();
+                let child = mem::replace(node.child_mut(!only_child), RsNode::Empty);
+                return Some(mem::replace(node, child));
+            }
+
+            let RsNode::Node { val, data, right, .. } = node else { unreachable!("the value was found here") };
+            let mut successor: &mut RsNode<T, V> = right;
+            while !successor.child(true).is_empty() {
+                successor = successor.child_mut(true);
+            }
+            if let RsNode::Node { val: successor_val, data: successor_data, .. } = successor {
+                mem::swap(val, successor_val);
+                mem::swap(data, successor_data);
+            }
+            let child = mem::replace(successor.child_mut(false), RsNode::Empty);
+            Some(mem::replace(successor, child))
+        }
+
+        /// The node of `value`, or the empty tree where it belongs.
+        fn slot(&mut self, value: &T) -> &mut RsNode<T, V> {
+            let mut node = self;
+            loop {
+                let ordering = match node {
+                    RsNode::Node { val, .. } => value.cmp(val),
+                    RsNode::Empty => return node,
+                };
+                if ordering == Ordering::Equal {
+                    return node;
+                }
+                node = node.child_mut(ordering == Ordering::Less);
+            }
+        }
+
+        fn is_empty(&self) -> bool {
+            matches!(self, RsNode::Empty)
+        }
+
+        fn child(&self, left: bool) -> &RsNode<T, V> {
             match self {
-                RsNode::Node {
-                    ref val,
-                    ref mut left,
-                    ref mut right,
-                } => match new_value.cmp(val) {
-                    Ordering::Less => left.add(new_value),
-                    Ordering::Greater => right.add(new_value),
-                    Ordering::Equal => return,
-                },
-                RsNode::Empty => {
-                    *self = RsNode::create(new_value);
+                RsNode::Node { left: child_left, right: child_right, .. } => {
+                    if left { child_left } else { child_right }
                 }
+                RsNode::Empty => panic!("an empty tree has no children"),
             }
         }
 
-        pub fn find(&self, find_value: T) -> bool {
+        fn child_mut(&mut self, left: bool) -> &mut RsNode<T, V> {
             match self {
-                RsNode::Node {
-                    ref val,
-                    ref left,
-                    ref right,
-                } => match find_value.cmp(val) {
-                    Ordering::Less => left.find(find_value),
-                    Ordering::Greater => right.find(find_value),
-                    Ordering::Equal => true,
-                },
-                RsNode::Empty => false,
+                RsNode::Node { left: child_left, right: child_right, .. } => {
+                    if left { child_left } else { child_right }
+                }
+                RsNode::Empty => panic!("an empty tree has no children"),
+            }
+        }
+
+        /// Visits the values in order.
+        pub fn iter(&self) -> impl Iterator<Item = &T> {
+            Iter { nodes: InOrder::new(self.root()) }.map(|(val, _)| val)
+        }
+
+        /// Checks that the values are in order.
+        pub fn validate(&self) -> Result<(), String> {
+            let values: Vec<&T> = self.iter().collect();
+            match values.windows(2).position(|pair| pair[0] >= pair[1]) {
+                Some(i) => Err(format!("the values at {} and {} are out of order", i, i + 1)),
+                None => Ok(()),
+            }
+        }
+
+        /// The shape of the tree, to be drawn by `render`.
+        pub fn sketch(&self) -> Option<Sketch>
+        where
+            T: fmt::Display,
+        {
+            match self {
+                RsNode::Node { val, left, right, .. } => Some(Sketch::new(val.to_string(), vec![left.sketch(), right.sketch()])),
+                RsNode::Empty => None,
+            }
+        }
+
+        /// The shape of the tree, every node owns the boxes of its two children, empty or not.
+        pub fn stats(&self) -> Stats {
+            let mut stats = stats::binary(self.root(), 2 * mem::size_of::<Self>(), |_| ());
+            stats.memory += mem::size_of::<Self>();
+            stats
+        }
+
+        fn root(&self) -> Option<&RsNode<T, V>> {
+            match self {
+                RsNode::Node { .. } => Some(self),
+                RsNode::Empty => None,
             }
         }
     }
 
-    impl<T: fmt::Display + Ord> fmt::Display for RsNode<T> {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write_node(self, f)
+    /// Visits the values and their data in order.
+    pub struct Iter<'a, T: Ord, V> {
+        nodes: InOrder<'a, RsNode<T, V>>,
+    }
+
+    impl<'a, T: Ord, V> Iterator for Iter<'a, T, V> {
+        type Item = (&'a T, &'a V);
+
+        fn next(&mut self) -> Option<(&'a T, &'a V)> {
+            self.nodes.next().map(|node| match node {
+                RsNode::Node { val, data, .. } => (val, data),
+                RsNode::Empty => unreachable!("only nodes are visited"),
+            })
         }
     }
 
-    fn write_node<T: fmt::Display + Ord>(node: &RsNode<T>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match node {
-            RsNode::Node {
-                ref val,
-                ref left,
-                ref right
-            } => {
-                // 访问 left 和 right 中的 val
-                if let RsNode::Node { val: left_val, .. } = &**left {
-                    let _ = f.write_str(format!("{} --> {}[{}L]\n", val, left_val, left_val).as_str());
+    /// The data of a removed node is taken out with `mem::take`, a node can't be taken apart as it
+    /// frees its children in `drop`.
+    impl<T: Ord, V: Default> OrderedMap<T, V> for RsNode<T, V> {
+        type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a;
+
+        fn get(&self, key: &T) -> Option<&V> {
+            let mut node = self;
+            while let RsNode::Node { val, data, left, right } = node {
+                node = match key.cmp(val) {
+                    Ordering::Less => left,
+                    Ordering::Greater => right,
+                    Ordering::Equal => return Some(data),
+                };
+            }
+            None
+        }
+
+        fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+            match self.slot(key) {
+                RsNode::Node { data, .. } => Some(data),
+                RsNode::Empty => None,
+            }
+        }
+
+        fn insert(&mut self, key: T, value: V) -> Option<V> {
+            match self.slot(&key) {
+                RsNode::Node { data, .. } => Some(mem::replace(data, value)),
+                node => {
+                    *node = RsNode::with_data(key, value);
+                    None
                 }
-                let _ = write_node(left, f);
+            }
+        }
+
+        fn remove(&mut self, key: &T) -> Option<V> {
+            match &mut self.detach(key)? {
+                RsNode::Node { data, .. } => Some(mem::take(data)),
+                RsNode::Empty => unreachable!("a node is detached"),
+            }
+        }
 
-                if let RsNode::Node { val: right_val, .. } = &**right {
-                    let _ = f.write_str(format!("{} --> {}[{}R]\n", val, right_val, right_val).as_str());
+        fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+            let node = self.slot(&key);
+            if node.is_empty() {
+                *node = RsNode::with_data(key, default());
+            }
+            match node {
+                RsNode::Node { data, .. } => data,
+                RsNode::Empty => unreachable!("the key is in the tree now"),
+            }
+        }
+
+        fn iter(&self) -> Iter<'_, T, V> {
+            Iter { nodes: InOrder::new(self.root()) }
+        }
+    }
+
+    impl<T: Ord, V> Descend for RsNode<T, V> {
+        fn descend(&self, left: bool) -> Option<&Self> {
+            match self {
+                RsNode::Node { left: child_left, right: child_right, .. } => {
+                    if left { child_left.root() } else { child_right.root() }
                 }
-                let _ = write_node(right, f);
+                RsNode::Empty => None,
             }
-            RsNode::Empty => {}
+        }
+    }
+
+    impl<T: fmt::Display + Ord, V> fmt::Display for RsNode<T, V> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write_node(self, f)
+        }
+    }
+
+    /// Writes the edges in pre-order like `bst::write_node`, with a stack instead of recursion.
+    fn write_node<T: fmt::Display + Ord, V>(node: &RsNode<T, V>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut edges = vec![];
+        push_edges(&mut edges, node);
+        while let Some((val, child, side)) = edges.pop() {
+            if let RsNode::Node { val: child_val, .. } = child {
+                f.write_str(format!("{} --> {}[{}{}]\n", val, child_val, child_val, side).as_str())?;
+            }
+            push_edges(&mut edges, child);
         }
 
         Ok(())
     }
+
+    fn push_edges<'a, T: Ord, V>(edges: &mut Vec<(&'a T, &'a RsNode<T, V>, char)>, node: &'a RsNode<T, V>) {
+        if let RsNode::Node { val, left, right, .. } = node {
+            // the right edge goes first, so it is popped last
+            if !right.is_empty() {
+                edges.push((val, right, 'R'));
+            }
+            if !left.is_empty() {
+                edges.push((val, left, 'L'));
+            }
+        }
+    }
+
+    /// Frees the nodes one by one like `bst::Node` does, without recursing once per level.
+    impl<T: Ord, V> Drop for RsNode<T, V> {
+        fn drop(&mut self) {
+            let mut nodes = vec![];
+            if let RsNode::Node { left, right, .. } = self {
+                nodes.push(mem::replace(&mut **left, RsNode::Empty));
+                nodes.push(mem::replace(&mut **right, RsNode::Empty));
+            }
+            while let Some(mut node) = nodes.pop() {
+                if let RsNode::Node { left, right, .. } = &mut node {
+                    nodes.push(mem::replace(&mut **left, RsNode::Empty));
+                    nodes.push(mem::replace(&mut **right, RsNode::Empty));
+                }
+            }
+        }
+    }
 }
 
 /// a traditional implementation for BST
 pub mod tradition {
-    use std::cmp::Ordering;
+    use alloc::boxed::Box;
+    use core::cmp::Ordering;
+    use core::mem;
     use crate::bst::Node;
+    use crate::error::TreeError;
+    use crate::path::Descend;
+    use crate::stats::{self, Stats};
 
     impl<T> Node<T>
     where
@@ -143,50 +391,56 @@ pub mod tradition {
             }
         }
 
+        /// Adds `val` to the tree of `root` and returns the tree. Panics if `val` is in the tree
+        /// already, `try_add` reports it instead.
+        #[allow(clippy::should_implement_trait)]
         pub fn add(mut root: Node<T>, val: T) -> Node<T> {
-            assert!(root.val != val);
-            if root.val < val {
-                root.right = Self::add_child(root.right, val)
-            } else {
-                root.left = Self::add_child(root.left, val);
-            }
+            root.add_self(val);
             root
         }
 
-        fn add_child(child: Option<Box<Node<T>>>, val: T) -> Option<Box<Node<T>>> {
-            match child {
-                Some(node) => Some(Box::new(Self::add(*node, val))),
-                None => Some(Box::new(Node::new(val))),
-            }
+        /// Same as `add`, a value in the tree already is reported instead of panicking.
+        pub fn try_add(mut root: Node<T>, val: T) -> Result<Node<T>, TreeError> {
+            root.try_add_self(val)?;
+            Ok(root)
         }
 
         pub fn find(&self, val: T) -> bool {
-            match &self {
-                &Self {
-                    val: value,
-                    ..
-                } => {
-                    match value.cmp(&val) {
-                        Ordering::Less => Node::find_with_option(&self.right, val),
-                        Ordering::Equal => true,
-                        Ordering::Greater => Node::find_with_option(&self.left, val)
-                    }
+            let mut node = self;
+            loop {
+                let child = match node.val.cmp(&val) {
+                    Ordering::Less => &node.right,
+                    Ordering::Equal => return true,
+                    Ordering::Greater => &node.left,
+                };
+                match child {
+                    Some(child) => node = child,
+                    None => return false,
                 }
             }
         }
 
         pub fn find_with_option(node : &Option<Box<Node<T>>>, val: T) -> bool {
-            match node {
-                None => false,
-                Some(node) => node.find(val),
-            }
+            node.as_ref().is_some_and(|node| node.find(val))
+        }
+
+        pub fn stats(&self) -> Stats {
+            stats::binary(Some(self), mem::size_of::<Self>(), |_| ())
+        }
+    }
+
+    impl<T: Ord> Descend for Node<T> {
+        fn descend(&self, left: bool) -> Option<&Self> {
+            if left { self.left.as_deref() } else { self.right.as_deref() }
         }
     }
 }
 
 /// another traditional implementation with add(*self) for BST
 pub mod se1f {
+    use alloc::boxed::Box;
     use crate::bst::Node;
+    use crate::error::TreeError;
 
     impl<T> Node<T>
     where
@@ -195,17 +449,28 @@ pub mod se1f {
         // `self` must be `mut` rather than `&mut` or `&` due to :
         //  1.1 the struct must be mutable because we are going to modify it;
         //  1.2 when we modify a node, we take the ownership because it maybe changes.
+        //
+        // Panics if `val` is in the tree already, `try_add_self` reports it instead.
         pub fn add_self(&mut self, val: T) {
-            assert!(self.val != val);
-            if self.val < val {
-                match &mut self.right {
-                    None => self.right = Some(Box::new(Node::new(val))),
-                    Some(right) => right.add_self(val),
+            if let Err(err) = self.try_add_self(val) {
+                panic!("{}", err);
+            }
+        }
+
+        /// Same as `add_self`, a value in the tree already is reported instead of panicking.
+        pub fn try_add_self(&mut self, val: T) -> Result<(), TreeError> {
+            let mut node = self;
+            loop {
+                if node.val == val {
+                    return Err(TreeError::Duplicate);
                 }
-            } else {
-                match &mut self.left {
-                    None => self.left = Some(Box::new(Node::new(val))),
-                    Some(left) => left.add_self(val),
+                let child = if node.val < val { &mut node.right } else { &mut node.left };
+                match child {
+                    Some(child) => node = child,
+                    None => {
+                        *child = Some(Box::new(Node::new(val)));
+                        return Ok(());
+                    }
                 }
             }
         }
@@ -223,6 +488,8 @@ pub mod se1f {
 pub mod tests {
     use crate::bst::Node;
     use crate::bst::rs_bst::RsNode;
+    use crate::error::TreeError;
+    use crate::testing::{check_multiset, check_ordered_map};
 
     #[test]
     fn test_rust_style(){
@@ -261,4 +528,115 @@ pub mod tests {
         assert!(node.find(2));
 
     }
-}
\ No newline at end of file
+
+    // Sorted insertions make a chain, which `add` would build in quadratic time, so the chains of a
+    // million nodes are put together by hand and only the last values go through `add`.
+    const DEPTH: i32 = 1_000_000;
+
+    #[test]
+    fn test_traditional_degenerate() {
+        let mut root = Node::new(DEPTH - 1);
+        for val in (0..DEPTH - 1).rev() {
+            let mut node = Node::new(val);
+            node.right = Some(Box::new(root));
+            root = node;
+        }
+        root.add_self(DEPTH);
+        let root = Node::add(root, DEPTH + 1);
+        assert!(root.find(DEPTH + 1));
+        assert!(!root.find(-1));
+        assert!(!root.find(DEPTH + 2));
+
+        let printed = root.to_string();
+        assert_eq!(printed.lines().count(), DEPTH as usize + 1);
+        assert!(printed.starts_with("0 --> 1[1R]\n1 --> 2[2R]\n"));
+    }
+
+    #[test]
+    fn test_rust_style_degenerate() {
+        let mut root = RsNode::create(DEPTH - 1);
+        for val in (0..DEPTH - 1).rev() {
+            root = RsNode::Node { val, data: (), left: Box::new(RsNode::Empty), right: Box::new(root) };
+        }
+        root.add(DEPTH);
+        assert!(root.find(DEPTH));
+        assert!(!root.find(DEPTH + 1));
+        assert_eq!(root.to_string().lines().count(), DEPTH as usize);
+
+        assert!(root.remove(&DEPTH));
+        assert!(root.remove(&0));
+        assert!(!root.remove(&0));
+        assert!(root.iter().copied().eq(1..DEPTH));
+        assert!(root.validate().is_ok());
+    }
+
+    #[test]
+    fn test_rust_style_remove() {
+        let mut root = RsNode::new();
+        for val in [4, 2, 6, 1, 3, 5, 7] {
+            root.add(val);
+        }
+        assert!(root.remove(&4));
+        assert!(root.remove(&2));
+        assert!(root.remove(&7));
+        assert!(!root.remove(&7));
+        assert_eq!(root.iter().copied().collect::<Vec<_>>(), vec![1, 3, 5, 6]);
+        assert_eq!(root.to_string(), "5 --> 3[3L]\n3 --> 1[1L]\n5 --> 6[6R]\n");
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<RsNode<i32, i32>>().validate().is_ok());
+        check_multiset::<RsNode<i32, usize>>();
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut root = RsNode::new();
+        for val in 0..100 {
+            root.add(val);
+        }
+        let stats = root.stats();
+        assert_eq!((stats.nodes, stats.height, stats.leaves), (100, 100, 1));
+        assert_eq!(stats.height_excess(), 93);
+
+        let mut node = Node::new(1);
+        node.add_self(0);
+        node.add_self(2);
+        let stats = node.stats();
+        assert_eq!((stats.nodes, stats.height, stats.leaves), (3, 2, 2));
+        assert_eq!(stats.avg_leaf_depth(), 1.0);
+        assert_eq!(stats.memory, 3 * std::mem::size_of::<Node<i32>>());
+    }
+
+    #[test]
+    fn test_try_add_self() {
+        let mut node = Node::new(1);
+        assert_eq!(node.try_add_self(2), Ok(()));
+        assert_eq!(node.try_add_self(2), Err(TreeError::Duplicate));
+        assert_eq!(node.try_add_self(1), Err(TreeError::Duplicate));
+        assert!(node.find(2));
+        assert_eq!(node.stats().nodes, 2);
+    }
+
+    #[test]
+    fn test_try_add() {
+        let root = Node::try_add(Node::new(1), 0).unwrap();
+        let root = Node::try_add(root, 2).unwrap();
+        assert!(root.find(0) && root.find(2));
+        assert_eq!(Node::try_add(root, 0).err(), Some(TreeError::Duplicate));
+    }
+
+    #[test]
+    #[should_panic(expected = "the key is in the tree already")]
+    fn test_add_duplicate() {
+        Node::add(Node::new(1), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the key is in the tree already")]
+    fn test_add_self_duplicate() {
+        let mut node = Node::new(1);
+        node.add_self(1);
+    }
+}
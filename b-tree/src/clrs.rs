@@ -0,0 +1,651 @@
+//!
+//! an implementation for the classic Red-Black Tree of CLRS
+//!
+//! Introduction to Algorithms, chapter 13, and https://en.wikipedia.org/wiki/Red%E2%80%93black_tree
+//!
+//! Unlike the left-leaning `rbt::Rbt` a red node may lean to either side, so an insertion needs at
+//! most two rotations and a deletion at most three, the other fix-ups only recolor. The tree follows
+//! the five rules listed in the docs of `rbt`.
+//!
+//! The nodes live in an arena and refer to each other by their index, parent links included. Index
+//! 0 is the sentinel `NIL` of CLRS, a black node standing for every empty child. Its parent is
+//! scratch space: a deletion sets it to start the fix-up from an empty child.
+//!
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::map::OrderedMap;
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
+
+const NIL: usize = 0;
+
+/// `entry` is `None` for the sentinel and for the free slots of the arena.
+struct Node<T, V> {
+    entry: Option<(T, V)>,
+    red: bool,
+    parent: usize,
+    left: usize,
+    right: usize,
+}
+
+impl<T, V> Node<T, V> {
+    fn sentinel() -> Node<T, V> {
+        Node { entry: None, red: false, parent: NIL, left: NIL, right: NIL }
+    }
+}
+
+/// A red-black tree with parent links.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The keys are ordered by the
+/// comparator `C`, their natural order by default.
+pub struct RedBlackTree<T, V = (), C = Natural> {
+    nodes: Vec<Node<T, V>>,
+    /// the slots of removed nodes, reused by the next insertions
+    free: Vec<usize>,
+    root: usize,
+    len: usize,
+    rotations: usize,
+    cmp: C,
+}
+
+impl<T, V, C: Default> Default for RedBlackTree<T, V, C> {
+    fn default() -> Self {
+        RedBlackTree::with_comparator(C::default())
+    }
+}
+
+impl<T: Ord, V> RedBlackTree<T, V> {
+    pub fn new() -> RedBlackTree<T, V> {
+        RedBlackTree::with_comparator(Natural)
+    }
+}
+
+impl<T, V, C> RedBlackTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> RedBlackTree<T, V, C> {
+        RedBlackTree { nodes: vec![Node::sentinel()], free: vec![], root: NIL, len: 0, rotations: 0, cmp }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of rotations made by all insertions and deletions so far.
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// Visits the keys and their data in order, following the parent links.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        let next = if self.root == NIL { NIL } else { self.minimum(self.root) };
+        Iter { nodes: &self.nodes, next }
+    }
+
+    fn key(&self, x: usize) -> &T {
+        &self.nodes[x].entry.as_ref().expect("only the sentinel and free slots have no key").0
+    }
+
+    fn child(&self, x: usize, left: bool) -> usize {
+        if left { self.nodes[x].left } else { self.nodes[x].right }
+    }
+
+    fn set_child(&mut self, x: usize, left: bool, child: usize) {
+        if left { self.nodes[x].left = child } else { self.nodes[x].right = child }
+    }
+
+    fn is_red(&self, x: usize) -> bool {
+        self.nodes[x].red
+    }
+
+    fn minimum(&self, mut x: usize) -> usize {
+        while self.nodes[x].left != NIL {
+            x = self.nodes[x].left;
+        }
+        x
+    }
+
+    /// Rotates left (`left == true`) or right around `x`: the child on the other side moves up and
+    /// `x` becomes its child on the `left` side.
+    fn rotate(&mut self, x: usize, left: bool) {
+        let y = self.child(x, !left);
+        let inner = self.child(y, left);
+        self.set_child(x, !left, inner);
+        if inner != NIL {
+            self.nodes[inner].parent = x;
+        }
+        self.replace_in_parent(x, y);
+        self.set_child(y, left, x);
+        self.nodes[x].parent = y;
+        self.rotations += 1;
+    }
+
+    /// Puts `v` where `u` hangs below its parent, `v` may be the sentinel (TRANSPLANT in CLRS).
+    fn replace_in_parent(&mut self, u: usize, v: usize) {
+        let parent = self.nodes[u].parent;
+        if parent == NIL {
+            self.root = v;
+        } else if self.nodes[parent].left == u {
+            self.nodes[parent].left = v;
+        } else {
+            self.nodes[parent].right = v;
+        }
+        self.nodes[v].parent = parent;
+    }
+
+    fn allocate(&mut self, key: T, data: V, parent: usize) -> usize {
+        let node = Node { entry: Some((key, data)), red: true, parent, left: NIL, right: NIL };
+        match self.free.pop() {
+            Some(z) => {
+                self.nodes[z] = node;
+                z
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Restores the rules after the red node `z` was added.
+    fn insert_fixup(&mut self, mut z: usize) {
+        while self.is_red(self.nodes[z].parent) {
+            let parent = self.nodes[z].parent;
+            let grandparent = self.nodes[parent].parent;
+            // the side of the grandparent the parent hangs on
+            let left = self.nodes[grandparent].left == parent;
+            let uncle = self.child(grandparent, !left);
+            if self.is_red(uncle) {
+                // case 1: push the red up
+                self.nodes[parent].red = false;
+                self.nodes[uncle].red = false;
+                self.nodes[grandparent].red = true;
+                z = grandparent;
+            } else {
+                if self.child(parent, !left) == z {
+                    // case 2: turn the inner grandchild into an outer one
+                    z = parent;
+                    self.rotate(z, left);
+                }
+                // case 3
+                let parent = self.nodes[z].parent;
+                self.nodes[parent].red = false;
+                self.nodes[grandparent].red = true;
+                self.rotate(grandparent, !left);
+            }
+        }
+        let root = self.root;
+        self.nodes[root].red = false;
+    }
+
+    /// Restores the rules after a black node was removed above `x`, which carries an extra black.
+    fn delete_fixup(&mut self, mut x: usize) {
+        while x != self.root && !self.is_red(x) {
+            let parent = self.nodes[x].parent;
+            let left = self.nodes[parent].left == x;
+            let mut sibling = self.child(parent, !left);
+            if self.is_red(sibling) {
+                // case 1: make the sibling black
+                self.nodes[sibling].red = false;
+                self.nodes[parent].red = true;
+                self.rotate(parent, left);
+                sibling = self.child(parent, !left);
+            }
+            if !self.is_red(self.nodes[sibling].left) && !self.is_red(self.nodes[sibling].right) {
+                // case 2: move the extra black up
+                self.nodes[sibling].red = true;
+                x = parent;
+            } else {
+                if !self.is_red(self.child(sibling, !left)) {
+                    // case 3: make the outer child of the sibling red
+                    let inner = self.child(sibling, left);
+                    self.nodes[inner].red = false;
+                    self.nodes[sibling].red = true;
+                    self.rotate(sibling, !left);
+                    sibling = self.child(parent, !left);
+                }
+                // case 4
+                self.nodes[sibling].red = self.nodes[parent].red;
+                self.nodes[parent].red = false;
+                let outer = self.child(sibling, !left);
+                self.nodes[outer].red = false;
+                self.rotate(parent, left);
+                x = self.root;
+            }
+        }
+        self.nodes[x].red = false;
+    }
+}
+
+impl<T, V, C: Compare<T>> RedBlackTree<T, V, C> {
+    pub fn search(&self, key: &T) -> bool {
+        self.find(key) != NIL
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        match self.find(key) {
+            NIL => None,
+            x => self.nodes[x].entry.as_ref().map(|(_, data)| data),
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        match self.find(key) {
+            NIL => None,
+            x => self.nodes[x].entry.as_mut().map(|(_, data)| data),
+        }
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        match self.attach(key, data) {
+            Ok(_) => None,
+            Err((x, data)) => {
+                let (_, old) = self.nodes[x].entry.as_mut().expect("the key was found");
+                Some(mem::replace(old, data))
+            }
+        }
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        self.remove_entry(key).map(|(_, data)| data)
+    }
+
+    /// Removes `key`, returns it with its data if it was present.
+    pub fn remove_entry(&mut self, key: &T) -> Option<(T, V)> {
+        let z = self.find(key);
+        if z == NIL {
+            return None;
+        }
+
+        // `y` is the node leaving its place: `z` itself or its successor, which takes the place
+        // and the color of `z`. `x` moves into the place of `y`.
+        let mut removed_red = self.is_red(z);
+        let x;
+        if self.nodes[z].left == NIL {
+            x = self.nodes[z].right;
+            self.replace_in_parent(z, x);
+        } else if self.nodes[z].right == NIL {
+            x = self.nodes[z].left;
+            self.replace_in_parent(z, x);
+        } else {
+            let y = self.minimum(self.nodes[z].right);
+            removed_red = self.is_red(y);
+            x = self.nodes[y].right;
+            if self.nodes[y].parent == z {
+                self.nodes[x].parent = y;
+            } else {
+                self.replace_in_parent(y, x);
+                let right = self.nodes[z].right;
+                self.nodes[y].right = right;
+                self.nodes[right].parent = y;
+            }
+            self.replace_in_parent(z, y);
+            let left = self.nodes[z].left;
+            self.nodes[y].left = left;
+            self.nodes[left].parent = y;
+            self.nodes[y].red = self.nodes[z].red;
+        }
+        if !removed_red {
+            self.delete_fixup(x);
+        }
+
+        self.len -= 1;
+        self.free.push(z);
+        self.nodes[NIL].parent = NIL;
+        let node = mem::replace(&mut self.nodes[z], Node::sentinel());
+        node.entry
+    }
+
+    /// Checks the rules of a red-black tree as listed in the docs of `rbt`, the order of the keys,
+    /// the parent links and the length.
+    pub fn validate(&self) -> Result<(), String> {
+        // 1. every node is either red or black: a node has a single `red` flag
+        // 2. all NIL nodes are considered black
+        if self.is_red(NIL) {
+            return Err("the sentinel is red".to_string());
+        }
+        if self.is_red(self.root) {
+            return Err("the root is red".to_string());
+        }
+        if self.root != NIL && self.nodes[self.root].parent != NIL {
+            return Err("the root has a parent".to_string());
+        }
+        let (count, _) = self.validate_node(self.root)?;
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, count));
+        }
+
+        let mut previous = None;
+        for (i, (key, _)) in self.iter().enumerate() {
+            if previous.is_some_and(|previous| self.cmp.compare(previous, key) != Ordering::Less) {
+                return Err(format!("the keys at {} and {} are out of order", i - 1, i));
+            }
+            previous = Some(key);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of nodes below `x` and its black height, not counting `x` itself.
+    fn validate_node(&self, x: usize) -> Result<(usize, usize), String> {
+        if x == NIL {
+            return Ok((0, 0));
+        }
+        let Node { red, left, right, .. } = self.nodes[x];
+        for child in [left, right] {
+            if child != NIL && self.nodes[child].parent != x {
+                return Err("a child doesn't link back to its parent".to_string());
+            }
+            // 3. a red node does not have a red child
+            if red && self.is_red(child) {
+                return Err("a red node has a red child".to_string());
+            }
+        }
+        // 5. a node with exactly one child has a red child
+        if (left == NIL) != (right == NIL) && !self.is_red(left.max(right)) {
+            return Err("a node has a single black child".to_string());
+        }
+
+        let (left_count, left_black) = self.validate_node(left)?;
+        let (right_count, right_black) = self.validate_node(right)?;
+        let left_black = left_black + usize::from(left != NIL && !self.is_red(left));
+        let right_black = right_black + usize::from(right != NIL && !self.is_red(right));
+        // 4. every path down to the NIL nodes goes through the same number of black nodes
+        if left_black != right_black {
+            return Err(format!("the paths below a node go through {} and {} black nodes", left_black, right_black));
+        }
+        Ok((left_count + right_count + 1, left_black))
+    }
+
+    /// The index of the node of `key`, `NIL` if there is none.
+    fn find(&self, key: &T) -> usize {
+        let mut x = self.root;
+        while x != NIL {
+            x = match self.cmp.compare(key, self.key(x)) {
+                Ordering::Equal => return x,
+                Ordering::Less => self.nodes[x].left,
+                Ordering::Greater => self.nodes[x].right,
+            };
+        }
+        NIL
+    }
+
+    /// Adds `key` as a new node and returns its index, or returns the node of `key` and `data` if
+    /// `key` is present.
+    fn attach(&mut self, key: T, data: V) -> Result<usize, (usize, V)> {
+        let mut parent = NIL;
+        let mut left = false;
+        let mut x = self.root;
+        while x != NIL {
+            parent = x;
+            left = match self.cmp.compare(&key, self.key(x)) {
+                Ordering::Equal => return Err((x, data)),
+                Ordering::Less => true,
+                Ordering::Greater => false,
+            };
+            x = self.child(x, left);
+        }
+
+        let z = self.allocate(key, data, parent);
+        if parent == NIL {
+            self.root = z;
+        } else {
+            self.set_child(parent, left, z);
+        }
+        self.len += 1;
+        self.insert_fixup(z);
+        Ok(z)
+    }
+
+    /// The shape of the tree with its black height and its red nodes. The memory includes the
+    /// sentinel and the free slots of the arena.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut red = 0;
+        let mut nodes: Vec<(usize, usize)> = if self.root == NIL { vec![] } else { vec![(self.root, 0)] };
+        while let Some((x, depth)) = nodes.pop() {
+            let Node { red: is_red, left, right, .. } = self.nodes[x];
+            stats.visit(depth, left == NIL && right == NIL);
+            red += usize::from(is_red);
+            nodes.extend([right, left].into_iter().filter(|&child| child != NIL).map(|child| (child, depth + 1)));
+        }
+
+        let mut black_height = 0;
+        let mut x = self.root;
+        while x != NIL {
+            black_height += usize::from(!self.is_red(x));
+            x = self.nodes[x].left;
+        }
+        stats.black_height = Some(black_height);
+        stats.red_nodes = Some(red);
+        stats.memory = mem::size_of::<Self>()
+            + self.nodes.capacity() * mem::size_of::<Node<T, V>>()
+            + self.free.capacity() * mem::size_of::<usize>();
+        stats
+    }
+}
+
+impl<T: Display, V, C> RedBlackTree<T, V, C> {
+    /// The shape of the tree with the color of every node, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V, C>(tree: &RedBlackTree<T, V, C>, x: usize) -> Option<Sketch> {
+            if x == NIL {
+                return None;
+            }
+            let node = &tree.nodes[x];
+            let children = vec![sketch(tree, node.left), sketch(tree, node.right)];
+            Some(Sketch { red: node.red, ..Sketch::new(tree.key(x).to_string(), children) })
+        }
+        sketch(self, self.root)
+    }
+}
+
+impl<T: Display, V, C> Display for RedBlackTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for RedBlackTree<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        RedBlackTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        RedBlackTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        RedBlackTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        RedBlackTree::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        // the index of a node doesn't change when the fix-up moves it around
+        let x = match self.find(&key) {
+            NIL => self.attach(key, default()).ok().expect("the key is absent"),
+            x => x,
+        };
+        &mut self.nodes[x].entry.as_mut().expect("the node has a key").1
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        RedBlackTree::iter(self)
+    }
+}
+
+pub struct Iter<'a, T, V> {
+    nodes: &'a [Node<T, V>],
+    /// the node to visit next, `NIL` at the end
+    next: usize,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let x = self.next;
+        if x == NIL {
+            return None;
+        }
+        // the successor is the leftmost node on the right, or the first ancestor reached from the
+        // left
+        let nodes = self.nodes;
+        self.next = if nodes[x].right != NIL {
+            let mut y = nodes[x].right;
+            while nodes[y].left != NIL {
+                y = nodes[y].left;
+            }
+            y
+        } else {
+            let mut y = x;
+            while nodes[y].parent != NIL && nodes[nodes[y].parent].right == y {
+                y = nodes[y].parent;
+            }
+            nodes[y].parent
+        };
+        nodes[x].entry.as_ref().map(|(key, data)| (key, data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compare::Reverse;
+    use crate::stats;
+    use crate::testing::{check_multiset, check_ordered_map, check_reverse_order, Random};
+    use super::*;
+
+    fn tree_of(keys: impl IntoIterator<Item = i32>) -> RedBlackTree<i32> {
+        let mut tree = RedBlackTree::new();
+        for key in keys {
+            tree.insert(key, ());
+        }
+        tree
+    }
+
+    #[test]
+    fn test_free_slots_reused() {
+        let mut tree = tree_of(0..100);
+        for key in 0..50 {
+            tree.remove(&key);
+        }
+        for key in 100..150 {
+            tree.insert(key, ());
+        }
+        // the sentinel and 100 nodes
+        assert_eq!(tree.nodes.len(), 101);
+        assert!(tree.free.is_empty());
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_rotations_per_update() {
+        // at most two rotations for an insertion and three for a deletion
+        let mut tree = RedBlackTree::new();
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..5000 {
+            let key = random.next_u64() % 1000;
+            let rotations = tree.rotations();
+            if random.next_u64().is_multiple_of(2) {
+                tree.insert(key, ());
+                assert!(tree.rotations() - rotations <= 2);
+            } else {
+                tree.remove(&key);
+                assert!(tree.rotations() - rotations <= 3);
+            }
+        }
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_sorted_insertions() {
+        let mut tree = tree_of(0..100_000);
+        assert!(tree.validate().is_ok());
+        // no deeper than twice the optimal height
+        assert!(tree.stats().height <= 2 * stats::optimal_height(100_000));
+        for key in 0..99_990 {
+            tree.remove(&key);
+        }
+        assert!(tree.validate().is_ok());
+        assert!(tree.iter().map(|(&key, _)| key).eq(99_990..100_000));
+    }
+
+    #[test]
+    fn test_sentinel_is_black() {
+        let mut tree = tree_of(1..=3);
+        tree.nodes[NIL].red = true;
+        assert_eq!(tree.validate(), Err("the sentinel is red".to_string()));
+    }
+
+    #[test]
+    fn test_root_is_black() {
+        let mut tree = tree_of(1..=3);
+        tree.nodes[tree.root].red = true;
+        assert_eq!(tree.validate(), Err("the root is red".to_string()));
+    }
+
+    #[test]
+    fn test_no_red_child_of_red() {
+        //     2
+        //    / \
+        //   1   3
+        //        \
+        //         4 (red)
+        let mut tree = tree_of(1..=4);
+        assert!(tree.validate().is_ok());
+        let x = tree.find(&3);
+        tree.nodes[x].red = true;
+        assert_eq!(tree.validate(), Err("a red node has a red child".to_string()));
+    }
+
+    #[test]
+    fn test_equal_black_heights() {
+        // 2 with the red children 1 and 3
+        let mut tree = tree_of(1..=3);
+        let x = tree.find(&1);
+        tree.nodes[x].red = false;
+        assert_eq!(tree.validate(), Err("the paths below a node go through 1 and 0 black nodes".to_string()));
+    }
+
+    #[test]
+    fn test_single_child_is_red() {
+        // 1 with the red right child 2
+        let mut tree = tree_of(1..=2);
+        let x = tree.find(&2);
+        tree.nodes[x].red = false;
+        assert_eq!(tree.validate(), Err("a node has a single black child".to_string()));
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = tree_of(1..=3).stats();
+        assert_eq!((stats.nodes, stats.height), (3, 2));
+        assert_eq!((stats.black_height, stats.red_nodes), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<RedBlackTree<i32, i32>>().validate().is_ok());
+        assert!(check_reverse_order::<RedBlackTree<i32, (), Reverse>>().validate().is_ok());
+        check_multiset::<RedBlackTree<i32, usize>>();
+    }
+}
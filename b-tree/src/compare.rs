@@ -0,0 +1,38 @@
+//!
+//! comparators, to order a tree by something else than the `Ord` of its keys
+//!
+//! A case-insensitive order, a reverse order or the order of a single field of a struct don't need
+//! a newtype wrapper around the key, any `Fn(&T, &T) -> Ordering` is a comparator.
+//!
+
+use core::cmp::Ordering;
+
+pub trait Compare<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// The order given by `Ord`, the comparator of a tree unless another one is chosen.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Natural;
+
+impl<T: Ord + ?Sized> Compare<T> for Natural {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// The reverse of the order of another comparator.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Reverse<C = Natural>(pub C);
+
+impl<T: ?Sized, C: Compare<T>> Compare<T> for Reverse<C> {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.0.compare(b, a)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Compare<T> for F {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
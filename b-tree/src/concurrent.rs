@@ -0,0 +1,852 @@
+//!
+//! a concurrent ordered map built on a B+ tree with latch crabbing
+//!
+//! Every node carries its own reader-writer latch. A thread walking down the tree always takes the
+//! latch of the child before it releases the latch of the parent (hand-over-hand locking), so no
+//! thread can ever observe a node while another thread is restructuring it.
+//!
+//! - readers hold shared latches and release the parent as soon as the child is latched;
+//! - writers hold exclusive latches and fix a child *before* descending into it: a full child is
+//!   split on the way down of `insert`, a child with the minimum number of keys borrows from or is
+//!   merged with a sibling on the way down of `remove`. Because of that a child is always safe once
+//!   it is reached and the parent can be released immediately;
+//! - leaves are linked to their right sibling, `range` moves along this chain hand-over-hand.
+//!
+//! Latches are always acquired from top to bottom and, on the same level, from left to right, so
+//! the tree is deadlock free.
+//!
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Display};
+use std::mem;
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
+
+const MIN_ORDER: usize = 4;
+const DEFAULT_ORDER: usize = 32;
+
+type NodeRef<K, V> = Arc<Page<K, V>>;
+
+/// A reader-writer latch with explicit lock and unlock, the guards in `std::sync` borrow the lock
+/// and therefore can't be handed over from a parent to a child.
+///
+/// `state` is the number of readers, or `-1` if a writer holds the latch.
+struct Latch {
+    state: Mutex<isize>,
+    released: Condvar,
+}
+
+impl Latch {
+    fn new() -> Latch {
+        Latch {
+            state: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    fn lock_shared(&self) {
+        let mut state = self.state.lock().unwrap();
+        while *state < 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        *state += 1;
+    }
+
+    fn unlock_shared(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state -= 1;
+        if *state == 0 {
+            self.released.notify_all();
+        }
+    }
+
+    fn lock_exclusive(&self) {
+        let mut state = self.state.lock().unwrap();
+        while *state != 0 {
+            state = self.released.wait(state).unwrap();
+        }
+        *state = -1;
+    }
+
+    fn unlock_exclusive(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = 0;
+        self.released.notify_all();
+    }
+}
+
+/// A node together with the latch protecting it.
+struct Page<K, V> {
+    latch: Latch,
+    node: UnsafeCell<Node<K, V>>,
+}
+
+// The node is only ever accessed through `ReadGuard` and `WriteGuard`, which hold the latch.
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Page<K, V> {}
+
+impl<K, V> Page<K, V> {
+    fn new(node: Node<K, V>) -> NodeRef<K, V> {
+        Arc::new(Page {
+            latch: Latch::new(),
+            node: UnsafeCell::new(node),
+        })
+    }
+}
+
+/// For an internal node `keys[i]` separates `children[i]` (keys less than `keys[i]`) from
+/// `children[i + 1]` (keys greater than or equal to `keys[i]`).
+enum Node<K, V> {
+    Internal {
+        keys: Vec<K>,
+        children: Vec<NodeRef<K, V>>,
+    },
+    Leaf {
+        keys: Vec<K>,
+        values: Vec<V>,
+        next: Option<NodeRef<K, V>>,
+    },
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn keys(&self) -> &Vec<K> {
+        match self {
+            Node::Internal { keys, .. } | Node::Leaf { keys, .. } => keys,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys().len()
+    }
+}
+
+/// The index of the child which may contain `key`.
+fn child_index<K: Ord>(keys: &[K], key: &K) -> usize {
+    keys.partition_point(|k| k <= key)
+}
+
+struct ReadGuard<K, V> {
+    page: NodeRef<K, V>,
+}
+
+impl<K, V> ReadGuard<K, V> {
+    fn acquire(page: &NodeRef<K, V>) -> ReadGuard<K, V> {
+        page.latch.lock_shared();
+        ReadGuard { page: page.clone() }
+    }
+}
+
+impl<K, V> Deref for ReadGuard<K, V> {
+    type Target = Node<K, V>;
+
+    fn deref(&self) -> &Node<K, V> {
+        // SAFETY: the shared latch is held until the guard is dropped.
+        unsafe { &*self.page.node.get() }
+    }
+}
+
+impl<K, V> Drop for ReadGuard<K, V> {
+    fn drop(&mut self) {
+        self.page.latch.unlock_shared();
+    }
+}
+
+struct WriteGuard<K, V> {
+    page: NodeRef<K, V>,
+}
+
+impl<K, V> WriteGuard<K, V> {
+    fn acquire(page: &NodeRef<K, V>) -> WriteGuard<K, V> {
+        page.latch.lock_exclusive();
+        WriteGuard { page: page.clone() }
+    }
+}
+
+impl<K, V> Deref for WriteGuard<K, V> {
+    type Target = Node<K, V>;
+
+    fn deref(&self) -> &Node<K, V> {
+        // SAFETY: the exclusive latch is held until the guard is dropped.
+        unsafe { &*self.page.node.get() }
+    }
+}
+
+impl<K, V> DerefMut for WriteGuard<K, V> {
+    fn deref_mut(&mut self) -> &mut Node<K, V> {
+        // SAFETY: the exclusive latch is held until the guard is dropped.
+        unsafe { &mut *self.page.node.get() }
+    }
+}
+
+impl<K, V> Drop for WriteGuard<K, V> {
+    fn drop(&mut self) {
+        self.page.latch.unlock_exclusive();
+    }
+}
+
+/// A thread-safe ordered map, all operations take `&self` and can be called from any number of
+/// threads at the same time.
+pub struct ConcurrentBTree<K, V> {
+    order: usize,
+    root: RwLock<NodeRef<K, V>>,
+    len: AtomicUsize,
+}
+
+impl<K: Ord + Clone, V> Default for ConcurrentBTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, V> ConcurrentBTree<K, V> {
+    pub fn new() -> ConcurrentBTree<K, V> {
+        Self::with_order(DEFAULT_ORDER)
+    }
+
+    /// `order` is the maximum number of children of a node.
+    pub fn with_order(order: usize) -> ConcurrentBTree<K, V> {
+        assert!(order >= MIN_ORDER, "order must be at least {}", MIN_ORDER);
+        ConcurrentBTree {
+            order,
+            root: RwLock::new(Page::new(Node::Leaf {
+                keys: vec![],
+                values: vec![],
+                next: None,
+            })),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(AtomicOrdering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        let leaf = self.read_leaf(Some(key));
+        leaf.keys().binary_search(key).is_ok()
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let leaf = self.read_leaf(Some(key));
+        match &*leaf {
+            Node::Leaf { keys, values, .. } => keys.binary_search(key).ok().map(|i| values[i].clone()),
+            Node::Internal { .. } => unreachable!("read_leaf always returns a leaf"),
+        }
+    }
+
+    /// Returns the entries within `range` in ascending order.
+    ///
+    /// The leaves are visited hand-over-hand, so every entry is read while it is in the tree, but
+    /// the result is not a snapshot: writers may change leaves which have already been visited.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        let mut result = vec![];
+        let mut leaf = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => self.read_leaf(Some(key)),
+            Bound::Unbounded => self.read_leaf(None),
+        };
+
+        loop {
+            let next = match &*leaf {
+                Node::Leaf { keys, values, next } => {
+                    for (key, value) in keys.iter().zip(values) {
+                        if past_end(range.end_bound(), key) {
+                            return result;
+                        }
+                        if range.contains(key) {
+                            result.push((key.clone(), value.clone()));
+                        }
+                    }
+                    next.clone()
+                }
+                Node::Internal { .. } => unreachable!("leaves are only linked to leaves"),
+            };
+
+            match next {
+                None => return result,
+                // the next leaf is latched before the current one is released
+                Some(next) => leaf = ReadGuard::acquire(&next),
+            }
+        }
+    }
+
+    /// Inserts a key-value pair, returns the old value if the key was present.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let mut root = self.root.write().unwrap();
+        let mut guard = WriteGuard::acquire(&root);
+        if guard.len() == self.max_keys() {
+            // the tree grows at the root: the old root becomes the only child of a new root
+            let new_root = Page::new(Node::Internal {
+                keys: vec![],
+                children: vec![root.clone()],
+            });
+            let mut new_guard = WriteGuard::acquire(&new_root);
+            Self::split_child(&mut new_guard, 0, guard);
+            *root = new_root;
+            guard = new_guard;
+        }
+        drop(root);
+
+        loop {
+            let child = match &mut *guard {
+                Node::Leaf { keys, values, .. } => {
+                    return match keys.binary_search(&key) {
+                        Ok(i) => Some(mem::replace(&mut values[i], value)),
+                        Err(i) => {
+                            keys.insert(i, key);
+                            values.insert(i, value);
+                            self.len.fetch_add(1, AtomicOrdering::SeqCst);
+                            None
+                        }
+                    };
+                }
+                Node::Internal { keys, children } => {
+                    let index = child_index(keys, &key);
+                    WriteGuard::acquire(&children[index])
+                }
+            };
+
+            if child.len() < self.max_keys() {
+                guard = child;
+                continue;
+            }
+
+            let index = child_index(guard.keys(), &key);
+            Self::split_child(&mut guard, index, child);
+            let index = child_index(guard.keys(), &key);
+            guard = match &*guard {
+                Node::Internal { children, .. } => WriteGuard::acquire(&children[index]),
+                Node::Leaf { .. } => unreachable!("only internal nodes have children"),
+            };
+        }
+    }
+
+    /// Removes a key from the map, returns its value if the key was present.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut root = Some(self.root.write().unwrap());
+        let mut guard = WriteGuard::acquire(root.as_ref().unwrap());
+
+        loop {
+            let index = match &mut *guard {
+                Node::Leaf { keys, values, .. } => {
+                    return keys.binary_search(key).ok().map(|i| {
+                        keys.remove(i);
+                        self.len.fetch_sub(1, AtomicOrdering::SeqCst);
+                        values.remove(i)
+                    });
+                }
+                Node::Internal { keys, .. } => child_index(keys, key),
+            };
+
+            let child = self.fix_child(&mut guard, index);
+
+            if let Some(mut root) = root.take() {
+                // the root lost its last key in a merge, its only child becomes the new root
+                if guard.len() == 0 {
+                    *root = child.page.clone();
+                }
+            }
+            guard = child;
+        }
+    }
+
+    /// Checks the key ranges and the sizes of the nodes, that all leaves are on the same level and
+    /// the length. Writers running at the same time may make a valid tree look broken.
+    pub fn validate(&self) -> Result<(), String> {
+        let root = self.root.read().unwrap().clone();
+        let (_, count) = self.validate_node(&root, true, None, None)?;
+        if count != self.len() {
+            return Err(format!("the length is {} but there are {} keys", self.len(), count));
+        }
+        Ok(())
+    }
+
+    /// Returns the depth of the leaves below `node` and the number of keys in them.
+    fn validate_node(&self, node: &NodeRef<K, V>, is_root: bool, low: Option<&K>, high: Option<&K>) -> Result<(usize, usize), String> {
+        let guard = ReadGuard::acquire(node);
+        if guard.len() > self.max_keys() {
+            return Err(format!("a node has {} keys, more than {}", guard.len(), self.max_keys()));
+        }
+        if !is_root && guard.len() < self.min_keys() {
+            return Err(format!("a node has {} keys, less than {}", guard.len(), self.min_keys()));
+        }
+        if !guard.keys().windows(2).all(|w| w[0] < w[1]) {
+            return Err("the keys of a node are out of order".to_string());
+        }
+        let in_range = |key: &K| low.is_none_or(|low| key >= low) && high.is_none_or(|high| key < high);
+        if !guard.keys().iter().all(in_range) {
+            return Err("a key is outside of the range given by the parent".to_string());
+        }
+
+        match &*guard {
+            Node::Leaf { keys, values, .. } => {
+                if keys.len() != values.len() {
+                    return Err(format!("a leaf has {} keys but {} values", keys.len(), values.len()));
+                }
+                Ok((0, keys.len()))
+            }
+            Node::Internal { keys, children } => {
+                if children.len() != keys.len() + 1 {
+                    return Err(format!("a node has {} keys but {} children", keys.len(), children.len()));
+                }
+                let mut depths = vec![];
+                let mut count = 0;
+                for (i, child) in children.iter().enumerate() {
+                    let low = if i == 0 { low } else { Some(&keys[i - 1]) };
+                    let high = if i == keys.len() { high } else { Some(&keys[i]) };
+                    let (depth, keys) = self.validate_node(child, false, low, high)?;
+                    depths.push(depth);
+                    count += keys;
+                }
+                if !depths.windows(2).all(|w| w[0] == w[1]) {
+                    return Err("the leaves are not all on the same level".to_string());
+                }
+                Ok((depths[0] + 1, count))
+            }
+        }
+    }
+
+    /// The shape of the tree with the keys of every node, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch>
+    where
+        K: Display,
+    {
+        fn sketch<K: Ord + Display, V>(node: &NodeRef<K, V>) -> Sketch {
+            let guard = ReadGuard::acquire(node);
+            let keys: Vec<String> = guard.keys().iter().map(ToString::to_string).collect();
+            let children = match &*guard {
+                Node::Internal { children, .. } => children.iter().map(|child| Some(sketch(child))).collect(),
+                Node::Leaf { .. } => vec![],
+            };
+            Sketch::new(format!("[{}]", keys.join(" ")), children)
+        }
+
+        if self.is_empty() {
+            return None;
+        }
+        let root = self.root.read().unwrap().clone();
+        Some(sketch(&root))
+    }
+
+    /// The shape of the tree, the nodes are counted rather than the keys. The memory includes the
+    /// capacity of the vectors of every node. Writers running at the same time skew the figures.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut nodes = vec![(self.root.read().unwrap().clone(), 0)];
+        while let Some((node, depth)) = nodes.pop() {
+            let guard = ReadGuard::acquire(&node);
+            // the `Arc` keeps its two counters next to the page
+            stats.memory += mem::size_of::<Page<K, V>>() + 2 * mem::size_of::<usize>();
+            stats.memory += guard.keys().capacity() * mem::size_of::<K>();
+            match &*guard {
+                Node::Internal { children, .. } => {
+                    stats.visit(depth, false);
+                    stats.memory += children.capacity() * mem::size_of::<NodeRef<K, V>>();
+                    nodes.extend(children.iter().rev().map(|child| (child.clone(), depth + 1)));
+                }
+                Node::Leaf { values, .. } => {
+                    stats.visit(depth, true);
+                    stats.memory += values.capacity() * mem::size_of::<V>();
+                }
+            }
+        }
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    fn min_keys(&self) -> usize {
+        (self.order - 2) / 2
+    }
+
+    /// Walks down to the leaf which may contain `key`, or to the leftmost leaf.
+    fn read_leaf(&self, key: Option<&K>) -> ReadGuard<K, V> {
+        let root = self.root.read().unwrap();
+        let mut guard = ReadGuard::acquire(&root);
+        drop(root);
+
+        loop {
+            let child = match &*guard {
+                Node::Leaf { .. } => return guard,
+                Node::Internal { keys, children } => {
+                    let index = key.map_or(0, |key| child_index(keys, key));
+                    ReadGuard::acquire(&children[index])
+                }
+            };
+            guard = child;
+        }
+    }
+
+    /// Splits the full child at `index` of `parent` in half and inserts the new right half at
+    /// `index + 1`.
+    fn split_child(parent: &mut WriteGuard<K, V>, index: usize, mut child: WriteGuard<K, V>) {
+        let (separator, sibling) = match &mut *child {
+            Node::Leaf { keys, values, next } => {
+                let mid = keys.len() / 2;
+                let right_keys = keys.split_off(mid);
+                let right_values = values.split_off(mid);
+                let separator = right_keys[0].clone();
+                let sibling = Page::new(Node::Leaf {
+                    keys: right_keys,
+                    values: right_values,
+                    next: next.take(),
+                });
+                *next = Some(sibling.clone());
+                (separator, sibling)
+            }
+            Node::Internal { keys, children } => {
+                let mid = keys.len() / 2;
+                let mut right_keys = keys.split_off(mid);
+                let separator = right_keys.remove(0);
+                let right_children = children.split_off(mid + 1);
+                let sibling = Page::new(Node::Internal {
+                    keys: right_keys,
+                    children: right_children,
+                });
+                (separator, sibling)
+            }
+        };
+
+        match &mut **parent {
+            Node::Internal { keys, children } => {
+                keys.insert(index, separator);
+                children.insert(index + 1, sibling);
+            }
+            Node::Leaf { .. } => unreachable!("only internal nodes have children"),
+        }
+    }
+
+    /// Makes sure the child at `index` has more than the minimum number of keys, by borrowing a key
+    /// from a sibling or by merging with a sibling. Returns the latched node to descend into.
+    fn fix_child(&self, parent: &mut WriteGuard<K, V>, index: usize) -> WriteGuard<K, V> {
+        let (keys, children) = match &mut **parent {
+            Node::Internal { keys, children } => (keys, children),
+            Node::Leaf { .. } => unreachable!("only internal nodes have children"),
+        };
+
+        let child = WriteGuard::acquire(&children[index]);
+        if child.len() > self.min_keys() {
+            return child;
+        }
+
+        // latch the siblings from left to right
+        let mut left = None;
+        let mut child = if index > 0 {
+            drop(child);
+            left = Some(WriteGuard::acquire(&children[index - 1]));
+            WriteGuard::acquire(&children[index])
+        } else {
+            child
+        };
+
+        if let Some(left) = left.as_mut() {
+            if left.len() > self.min_keys() {
+                borrow_from_left(&mut keys[index - 1], left, &mut child);
+                return child;
+            }
+        }
+
+        if index + 1 < children.len() {
+            let mut right = WriteGuard::acquire(&children[index + 1]);
+            if right.len() > self.min_keys() {
+                borrow_from_right(&mut keys[index], &mut child, &mut right);
+                return child;
+            }
+            if left.is_none() {
+                merge(keys.remove(index), &mut child, &mut right);
+                children.remove(index + 1);
+                return child;
+            }
+        }
+
+        let mut left = left.expect("a node other than the root has at least one sibling");
+        merge(keys.remove(index - 1), &mut left, &mut child);
+        children.remove(index);
+        left
+    }
+}
+
+impl<K: Ord + Clone + Display, V> Display for ConcurrentBTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+fn past_end<K: Ord>(end: Bound<&K>, key: &K) -> bool {
+    match end {
+        Bound::Included(end) => key > end,
+        Bound::Excluded(end) => key >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Moves the greatest entry of `left` into `child`.
+fn borrow_from_left<K: Clone, V>(separator: &mut K, left: &mut Node<K, V>, child: &mut Node<K, V>) {
+    match (left, child) {
+        (
+            Node::Leaf { keys: left_keys, values: left_values, .. },
+            Node::Leaf { keys, values, .. },
+        ) => {
+            keys.insert(0, left_keys.pop().unwrap());
+            values.insert(0, left_values.pop().unwrap());
+            *separator = keys[0].clone();
+        }
+        (
+            Node::Internal { keys: left_keys, children: left_children },
+            Node::Internal { keys, children },
+        ) => {
+            let key = mem::replace(separator, left_keys.pop().unwrap());
+            keys.insert(0, key);
+            children.insert(0, left_children.pop().unwrap());
+        }
+        _ => unreachable!("siblings are on the same level"),
+    }
+}
+
+/// Moves the smallest entry of `right` into `child`.
+fn borrow_from_right<K: Clone, V>(separator: &mut K, child: &mut Node<K, V>, right: &mut Node<K, V>) {
+    match (child, right) {
+        (
+            Node::Leaf { keys, values, .. },
+            Node::Leaf { keys: right_keys, values: right_values, .. },
+        ) => {
+            keys.push(right_keys.remove(0));
+            values.push(right_values.remove(0));
+            *separator = right_keys[0].clone();
+        }
+        (
+            Node::Internal { keys, children },
+            Node::Internal { keys: right_keys, children: right_children },
+        ) => {
+            let key = mem::replace(separator, right_keys.remove(0));
+            keys.push(key);
+            children.push(right_children.remove(0));
+        }
+        _ => unreachable!("siblings are on the same level"),
+    }
+}
+
+/// Moves everything of `right` into `left`, the caller removes `separator` and `right` from the
+/// parent.
+fn merge<K, V>(separator: K, left: &mut Node<K, V>, right: &mut Node<K, V>) {
+    match (left, right) {
+        (
+            Node::Leaf { keys, values, next },
+            Node::Leaf { keys: right_keys, values: right_values, next: right_next },
+        ) => {
+            keys.append(right_keys);
+            values.append(right_values);
+            *next = right_next.take();
+        }
+        (
+            Node::Internal { keys, children },
+            Node::Internal { keys: right_keys, children: right_children },
+        ) => {
+            keys.push(separator);
+            keys.append(right_keys);
+            children.append(right_children);
+        }
+        _ => unreachable!("siblings are on the same level"),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::testing::Random;
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    /// Panics with the broken invariant if the tree is not a valid B+ tree.
+    fn check_tree<K: Ord + Clone, V>(tree: &ConcurrentBTree<K, V>) {
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let tree = ConcurrentBTree::with_order(4);
+        for i in 0..100 {
+            assert_eq!(tree.insert(i, i * 10), None);
+            check_tree(&tree);
+        }
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.insert(42, 0), Some(420));
+        assert_eq!(tree.len(), 100);
+
+        for i in 0..100 {
+            assert!(tree.contains_key(&i));
+            assert_eq!(tree.get(&i), Some(if i == 42 { 0 } else { i * 10 }));
+        }
+        assert_eq!(tree.get(&100), None);
+        assert_eq!(tree.get(&-1), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let tree = ConcurrentBTree::with_order(4);
+        for i in (0..200).rev() {
+            tree.insert(i, i);
+        }
+
+        for i in (0..200).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+            assert_eq!(tree.remove(&i), None);
+            check_tree(&tree);
+        }
+        assert_eq!(tree.len(), 100);
+
+        for i in 0..200 {
+            assert_eq!(tree.get(&i), if i % 2 == 0 { None } else { Some(i) });
+        }
+
+        for i in (1..200).step_by(2) {
+            assert_eq!(tree.remove(&i), Some(i));
+            check_tree(&tree);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.range(..), vec![]);
+    }
+
+    #[test]
+    fn test_range() {
+        let tree = ConcurrentBTree::with_order(5);
+        for i in 0..50 {
+            tree.insert(i * 2, i);
+        }
+
+        let keys = |v: Vec<(i32, i32)>| v.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(10..16)), vec![10, 12, 14]);
+        assert_eq!(keys(tree.range(9..=16)), vec![10, 12, 14, 16]);
+        assert_eq!(keys(tree.range((Bound::Excluded(10), Bound::Included(14)))), vec![12, 14]);
+        assert_eq!(keys(tree.range(95..)), vec![96, 98]);
+        assert_eq!(keys(tree.range(..3)), vec![0, 2]);
+        assert_eq!(tree.range(..).len(), 50);
+        assert_eq!(tree.range(200..), vec![]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let tree = ConcurrentBTree::with_order(4);
+        let stats = tree.stats();
+        assert_eq!((stats.nodes, stats.height, stats.leaves), (1, 1, 1));
+        for i in 0..100 {
+            tree.insert(i, ());
+        }
+        let stats = tree.stats();
+        assert!(stats.leaves >= 100 / 3 && stats.nodes > stats.leaves);
+        // all leaves are on the same level
+        assert_eq!(stats.min_leaf_depth, stats.max_leaf_depth);
+        assert_eq!(stats.height, stats.max_leaf_depth + 1);
+        assert!(stats.memory > 100 * mem::size_of::<i32>());
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        let tree = ConcurrentBTree::with_order(4);
+        let mut oracle = BTreeMap::new();
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..5000 {
+            let key = random.next_u64() % 300;
+            if random.next_u64().is_multiple_of(3) {
+                assert_eq!(tree.remove(&key), oracle.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key, key + 1), oracle.insert(key, key + 1));
+            }
+        }
+        check_tree(&tree);
+        assert_eq!(tree.len(), oracle.len());
+        assert_eq!(tree.range(..), oracle.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConcurrentBTree<String, Vec<u8>>>();
+    }
+
+    /// Every writer owns the keys congruent to its id, so the history of every key is serial and
+    /// each writer can check every result against its own `BTreeMap`. Readers scan concurrently
+    /// and check the order of what they see.
+    #[test]
+    fn test_concurrent_stress() {
+        const WRITERS: u64 = 8;
+        const READERS: u64 = 4;
+        const OPERATIONS: u64 = 4000;
+
+        let tree = Arc::new(ConcurrentBTree::with_order(4));
+
+        let readers: Vec<_> = (0..READERS)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    let mut random = Random(id + 1);
+                    for _ in 0..200 {
+                        let start = random.next_u64() % 1000;
+                        let entries = tree.range(start..start + 100);
+                        assert!(entries.windows(2).all(|w| w[0].0 < w[1].0));
+                        for (key, value) in entries {
+                            assert!((start..start + 100).contains(&key));
+                            assert_eq!(value % 1000, key);
+                        }
+                        if let Some(value) = tree.get(&start) {
+                            assert_eq!(value % 1000, start);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    let mut oracle = BTreeMap::new();
+                    let mut random = Random(id * 7919 + 17);
+                    for round in 0..OPERATIONS {
+                        let key = random.next_u64() % (1000 / WRITERS) * WRITERS + id;
+                        match random.next_u64() % 4 {
+                            0 => assert_eq!(tree.remove(&key), oracle.remove(&key)),
+                            1 => assert_eq!(tree.get(&key), oracle.get(&key).copied()),
+                            _ => {
+                                let value = round * 1000 + key;
+                                assert_eq!(tree.insert(key, value), oracle.insert(key, value));
+                            }
+                        }
+                    }
+                    oracle
+                })
+            })
+            .collect();
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let mut expected = BTreeMap::new();
+        for writer in writers {
+            expected.append(&mut writer.join().unwrap());
+        }
+
+        check_tree(&tree);
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.range(..), expected.into_iter().collect::<Vec<_>>());
+    }
+}
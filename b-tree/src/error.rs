@@ -0,0 +1,30 @@
+//!
+//! the errors of the fallible operations
+//!
+//! The `try_*` methods report with a `TreeError` what their counterparts treat as a bug of the
+//! caller and panic on, so a library user can recover instead of crashing the process.
+//!
+
+use core::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TreeError {
+    /// the key is in the tree already
+    Duplicate,
+    /// the tree is empty, a leaf has neither a value nor children
+    Empty,
+    /// a rotation needs a child which is missing, the heights of the nodes are wrong
+    MissingChild,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TreeError::Duplicate => "the key is in the tree already",
+            TreeError::Empty => "the tree is empty",
+            TreeError::MissingChild => "a rotation needs a child which is missing",
+        })
+    }
+}
+
+impl core::error::Error for TreeError {}
@@ -0,0 +1,252 @@
+//!
+//! fuzz targets for the trees
+//!
+//! https://rust-fuzz.github.io/book/cargo-fuzz.html
+//!
+//! Every target takes arbitrary bytes, decodes them into a sequence of operations with `Ops`, applies
+//! them to a tree and to a `BTreeMap` playing the model, and checks the tree after each step with its
+//! `validate` and against the model. A broken invariant, a wrong answer or a panic inside the tree,
+//! such as the arms of `Avl::rotate` or `Rbt::child` which are never supposed to run, all end in a
+//! panic, which is what a fuzzer looks for. A target for `cargo fuzz` is a single line:
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| b_tree::fuzz::avl(data));
+//! ```
+//!
+//! Without a fuzzer `drive` feeds a target with random bytes.
+//!
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use crate::avl::AvlTree;
+use crate::bst::rs_bst::RsNode;
+use crate::range_search::RangeQueryTree;
+use crate::rbt::RbTree;
+use crate::treap::Rng;
+
+/// An operation decoded from the bytes, the keys are single bytes so they collide often.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Insert(u8, u8),
+    Remove(u8),
+    Get(u8),
+    /// moves a cursor to the first key not less than this one
+    Seek(u8),
+    /// removes the first key not less than this one through a cursor
+    Take(u8),
+    /// the keys within `start..=end`
+    Range(u8, u8),
+}
+
+/// The operations encoded in bytes: a byte picks the operation, the next ones are its arguments. The
+/// bytes left over at the end are ignored.
+pub struct Ops<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Ops<'a> {
+    pub fn new(data: &'a [u8]) -> Ops<'a> {
+        Ops { data }
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let (&first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(first)
+    }
+}
+
+impl Iterator for Ops<'_> {
+    type Item = Op;
+
+    fn next(&mut self) -> Option<Op> {
+        let op = match self.byte()? % 6 {
+            0 => Op::Insert(self.byte()?, self.byte()?),
+            1 => Op::Remove(self.byte()?),
+            2 => Op::Get(self.byte()?),
+            3 => Op::Seek(self.byte()?),
+            4 => Op::Take(self.byte()?),
+            _ => Op::Range(self.byte()?, self.byte()?),
+        };
+        Some(op)
+    }
+}
+
+/// Panics with the step and the operation which went wrong.
+fn check<T: PartialEq + Debug>(step: usize, op: Op, found: T, expected: T) {
+    assert!(found == expected, "step {} {:?}: found {:?} instead of {:?}", step, op, found, expected);
+}
+
+fn validate(step: usize, op: Op, result: Result<(), String>) {
+    if let Err(err) = result {
+        panic!("step {} {:?}: {}", step, op, err);
+    }
+}
+
+/// The entries of `model` within `start..=end`, nothing if the range is empty.
+fn range(model: &BTreeMap<u8, u8>, start: u8, end: u8) -> Vec<(u8, u8)> {
+    if start > end {
+        return Vec::new();
+    }
+    model.range(start..=end).map(|(&key, &data)| (key, data)).collect()
+}
+
+/// Inserts, removes and looks up keys in an `AvlTree`, directly and through cursors.
+pub fn avl(data: &[u8]) {
+    let mut tree = AvlTree::new();
+    let mut model = BTreeMap::new();
+    for (step, op) in Ops::new(data).enumerate() {
+        match op {
+            Op::Insert(key, data) => check(step, op, tree.insert(key, data), model.insert(key, data)),
+            Op::Remove(key) => check(step, op, tree.remove(&key), model.remove(&key)),
+            Op::Get(key) => check(step, op, tree.get(&key), model.get(&key)),
+            Op::Seek(key) => {
+                let mut cursor = tree.cursor();
+                cursor.seek(&key);
+                check(step, op, cursor.current(), model.range(key..).next());
+            }
+            Op::Take(key) => {
+                let expected = model.range(key..).next().map(|(&key, &data)| (key, data));
+                if let Some((key, _)) = expected {
+                    model.remove(&key);
+                }
+                let mut cursor = tree.cursor_mut();
+                cursor.seek(&key);
+                check(step, op, cursor.remove_current(), expected);
+            }
+            Op::Range(start, end) => {
+                let mut cursor = tree.cursor();
+                cursor.seek(&start);
+                let mut found = Vec::new();
+                while let Some((&key, &data)) = cursor.current().filter(|(&key, _)| key <= end) {
+                    found.push((key, data));
+                    cursor.next();
+                }
+                check(step, op, found, range(&model, start, end));
+            }
+        }
+        validate(step, op, tree.validate());
+        check(step, op, tree.len(), model.len());
+    }
+}
+
+/// Same as `avl` for a left-leaning red-black tree.
+pub fn rbt(data: &[u8]) {
+    let mut tree = RbTree::new();
+    let mut model = BTreeMap::new();
+    for (step, op) in Ops::new(data).enumerate() {
+        match op {
+            Op::Insert(key, data) => check(step, op, tree.insert(key, data), model.insert(key, data)),
+            Op::Remove(key) => check(step, op, tree.remove(&key), model.remove(&key)),
+            Op::Get(key) => check(step, op, tree.get(&key), model.get(&key)),
+            Op::Seek(key) => {
+                let mut cursor = tree.cursor();
+                cursor.seek(&key);
+                check(step, op, cursor.current(), model.range(key..).next());
+            }
+            Op::Take(key) => {
+                let expected = model.range(key..).next().map(|(&key, &data)| (key, data));
+                if let Some((key, _)) = expected {
+                    model.remove(&key);
+                }
+                let mut cursor = tree.cursor_mut();
+                cursor.seek(&key);
+                check(step, op, cursor.remove_current(), expected);
+            }
+            Op::Range(start, end) => {
+                let found: Vec<(u8, u8)> = tree.iter().filter(|(&key, _)| start <= key && key <= end).map(|(&key, &data)| (key, data)).collect();
+                check(step, op, found, range(&model, start, end));
+            }
+        }
+        validate(step, op, tree.validate());
+        check(step, op, tree.len(), model.len());
+    }
+}
+
+/// Adds, removes and finds values in an unbalanced `RsNode`, the data of the operations is ignored.
+pub fn rs_node(data: &[u8]) {
+    let mut tree = RsNode::new();
+    let mut model = BTreeMap::new();
+    for (step, op) in Ops::new(data).enumerate() {
+        match op {
+            Op::Insert(key, _) => {
+                tree.add(key);
+                model.insert(key, 0);
+            }
+            Op::Remove(key) | Op::Take(key) => check(step, op, tree.remove(&key), model.remove(&key).is_some()),
+            Op::Get(key) | Op::Seek(key) => check(step, op, tree.find(key), model.contains_key(&key)),
+            Op::Range(start, end) => {
+                let found: Vec<u8> = tree.iter().copied().filter(|&key| start <= key && key <= end).collect();
+                check(step, op, found, range(&model, start, end).into_iter().map(|(key, _)| key).collect());
+            }
+        }
+        validate(step, op, tree.validate());
+        check(step, op, tree.iter().count(), model.len());
+    }
+}
+
+/// Inserts values into a `RangeQueryTree`, which can't remove them, and queries ranges of it.
+pub fn range_query(data: &[u8]) {
+    let mut tree: Option<RangeQueryTree> = None;
+    let mut model = BTreeMap::new();
+    for (step, op) in Ops::new(data).enumerate() {
+        match op {
+            Op::Insert(key, _) => {
+                match &mut tree {
+                    Some(root) => root.insert(key as i32),
+                    None => tree = Some(RangeQueryTree::new(key as i32, None, None)),
+                }
+                model.insert(key, 0);
+            }
+            Op::Range(start, end) => {
+                // the values come in pre-order
+                let mut found: Vec<u8> = tree.as_ref().map_or(Vec::new(), |root| root.range_query(start as i32, end as i32))
+                    .into_iter().map(|val| val as u8).collect();
+                found.sort_unstable();
+                check(step, op, found, range(&model, start, end).into_iter().map(|(key, _)| key).collect());
+            }
+            _ => continue,
+        }
+        if let Some(root) = &tree {
+            validate(step, op, root.validate());
+        }
+    }
+}
+
+/// Runs `target` `runs` times on random bytes, up to `max_len` of them, drawn by the generator of
+/// the treaps seeded with `seed`.
+pub fn drive(target: fn(&[u8]), seed: u64, runs: usize, max_len: usize) {
+    let mut rng = Rng::new(seed);
+    let mut data = Vec::with_capacity(max_len);
+    for _ in 0..runs {
+        data.clear();
+        let len = rng.next_u64() as usize % (max_len + 1);
+        data.extend((0..len).map(|_| rng.next_u64() as u8));
+        target(&data);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ops() {
+        let ops: Vec<Op> = Ops::new(&[0, 7, 1, 1, 7, 8, 2, 3, 3, 10, 4, 5, 6, 1]).collect();
+        assert_eq!(ops, vec![Op::Insert(7, 1), Op::Remove(7), Op::Get(2), Op::Seek(3), Op::Take(4), Op::Range(6, 1)]);
+        // an operation missing its arguments is dropped
+        assert_eq!(Ops::new(&[0, 7]).count(), 0);
+        assert_eq!(Ops::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_targets() {
+        // the small keys of long runs collide, the large ones of short runs mostly don't
+        for target in [avl, rbt, rs_node, range_query] {
+            drive(target, 0x2545_f491_4f6c_dd1d, 200, 64);
+            drive(target, 0x9e37_79b9_7f4a_7c15, 20, 4096);
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//!
+//! a collection of records indexed by their primary key and by any number of fields
+//!
+//! https://en.wikipedia.org/wiki/Database_index#Secondary_index
+//!
+//! The records live in a primary ordered map. Every secondary index maps the value of a field to
+//! the set of primary keys of the records having it, an `AvlTree` of `AvlTree`s, so a field may be
+//! shared by many records and both exact and range queries walk the index in order.
+//!
+//! An index is added with a function extracting its field from a record and is queried through the
+//! typed `IndexId` it returns. A unique index refuses a second record with the same field. Every
+//! change is checked against the unique indexes first and only then applied to the primary map and
+//! all the indexes, so a refused change leaves everything as it was.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::any::Any;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use crate::avl::AvlTree;
+use crate::map::OrderedMap;
+
+/// The handle of a secondary index on a field of type `F`.
+pub struct IndexId<F> {
+    position: usize,
+    field: PhantomData<fn() -> F>,
+}
+
+impl<F> Clone for IndexId<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for IndexId<F> {}
+
+/// A change refused because a unique index already has its field for another record.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UniqueViolation {
+    pub index: String,
+}
+
+impl fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the unique index {} already has this value", self.index)
+    }
+}
+
+impl core::error::Error for UniqueViolation {}
+
+/// What the collection needs from a secondary index, whatever the type of its field.
+trait Secondary<K, R> {
+    fn name(&self) -> &str;
+
+    /// Whether the index is unique and has the field of `record` for a key other than `key`.
+    fn conflicts(&self, key: &K, record: &R) -> bool;
+
+    fn insert(&mut self, key: &K, record: &R);
+
+    fn remove(&mut self, key: &K, record: &R);
+
+    /// Whether the index has `key` under the field of `record`.
+    fn contains(&self, key: &K, record: &R) -> bool;
+
+    /// The number of keys in the index.
+    fn len(&self) -> usize;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct FieldIndex<K, R, F> {
+    name: String,
+    unique: bool,
+    extract: Box<dyn Fn(&R) -> F>,
+    /// the keys of the records having each value of the field, a set never empty
+    entries: AvlTree<F, AvlTree<K, ()>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone + 'static, R: 'static, F: Ord + 'static> Secondary<K, R> for FieldIndex<K, R, F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn conflicts(&self, key: &K, record: &R) -> bool {
+        self.unique && self.entries.get(&(self.extract)(record)).is_some_and(|keys| keys.iter().any(|(other, _)| other != key))
+    }
+
+    fn insert(&mut self, key: &K, record: &R) {
+        let keys = self.entries.get_or_insert_with((self.extract)(record), AvlTree::new);
+        if keys.insert(key.clone(), ()).is_none() {
+            self.len += 1;
+        }
+    }
+
+    fn remove(&mut self, key: &K, record: &R) {
+        let field = (self.extract)(record);
+        let Some(keys) = self.entries.get_mut(&field) else { return };
+        if keys.remove(key).is_some() {
+            self.len -= 1;
+        }
+        if keys.is_empty() {
+            self.entries.remove(&field);
+        }
+    }
+
+    fn contains(&self, key: &K, record: &R) -> bool {
+        self.entries.get(&(self.extract)(record)).is_some_and(|keys| keys.get(key).is_some())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Records of type `R` under unique keys `K`, in the ordered map `M`, an `AvlTree` by default,
+/// with secondary indexes.
+pub struct IndexedCollection<K, R, M: OrderedMap<K, R> = AvlTree<K, R>> {
+    primary: M,
+    indexes: Vec<Box<dyn Secondary<K, R>>>,
+    len: usize,
+}
+
+impl<K: Ord + Clone + 'static, R: 'static, M: OrderedMap<K, R>> Default for IndexedCollection<K, R, M> {
+    fn default() -> Self {
+        IndexedCollection { primary: M::default(), indexes: vec![], len: 0 }
+    }
+}
+
+impl<K: Ord + Clone + 'static, R: 'static> IndexedCollection<K, R> {
+    pub fn new() -> IndexedCollection<K, R> {
+        IndexedCollection::default()
+    }
+}
+
+impl<K: Ord + Clone + 'static, R: 'static, M: OrderedMap<K, R>> IndexedCollection<K, R, M> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Indexes the field `extract` returns, the records already there included.
+    pub fn add_index<F: Ord + 'static>(&mut self, name: &str, extract: impl Fn(&R) -> F + 'static) -> IndexId<F> {
+        self.add(name, false, Box::new(extract)).expect("an index which is not unique accepts any record")
+    }
+
+    /// Same as `add_index`, refusing two records with the same field. Fails if two records
+    /// already have the same field.
+    pub fn add_unique_index<F: Ord + 'static>(&mut self, name: &str, extract: impl Fn(&R) -> F + 'static)
+                                              -> Result<IndexId<F>, UniqueViolation> {
+        self.add(name, true, Box::new(extract))
+    }
+
+    fn add<F: Ord + 'static>(&mut self, name: &str, unique: bool, extract: Box<dyn Fn(&R) -> F>) -> Result<IndexId<F>, UniqueViolation> {
+        let mut index = FieldIndex { name: name.to_string(), unique, extract, entries: AvlTree::new(), len: 0 };
+        for (key, record) in self.primary.iter() {
+            if index.conflicts(key, record) {
+                return Err(UniqueViolation { index: name.to_string() });
+            }
+            index.insert(key, record);
+        }
+        self.indexes.push(Box::new(index));
+        Ok(IndexId { position: self.indexes.len() - 1, field: PhantomData })
+    }
+
+    pub fn get(&self, key: &K) -> Option<&R> {
+        self.primary.get(key)
+    }
+
+    /// Inserts `record` under `key` and indexes it, returns the record it replaces. Nothing
+    /// changes if a unique index refuses it.
+    pub fn insert(&mut self, key: K, record: R) -> Result<Option<R>, UniqueViolation> {
+        if let Some(index) = self.indexes.iter().find(|index| index.conflicts(&key, &record)) {
+            return Err(UniqueViolation { index: index.name().to_string() });
+        }
+        if let Some(old) = self.primary.get(&key) {
+            for index in &mut self.indexes {
+                index.remove(&key, old);
+            }
+        }
+        for index in &mut self.indexes {
+            index.insert(&key, &record);
+        }
+        let old = self.primary.insert(key, record);
+        if old.is_none() {
+            self.len += 1;
+        }
+        Ok(old)
+    }
+
+    /// Changes the record of `key` with `f` and reindexes it, returns `false` if `key` is absent.
+    /// `f` works on a copy, nothing changes if a unique index refuses the result.
+    pub fn update<U: FnOnce(&mut R)>(&mut self, key: &K, f: U) -> Result<bool, UniqueViolation>
+    where
+        R: Clone,
+    {
+        let Some(mut record) = self.primary.get(key).cloned() else { return Ok(false) };
+        f(&mut record);
+        self.insert(key.clone(), record).map(|_| true)
+    }
+
+    /// Removes the record of `key` from the primary map and from every index.
+    pub fn remove(&mut self, key: &K) -> Option<R> {
+        let record = self.primary.remove(key)?;
+        for index in &mut self.indexes {
+            index.remove(key, &record);
+        }
+        self.len -= 1;
+        Some(record)
+    }
+
+    /// The records whose field indexed by `id` is `value`, in the order of their keys.
+    pub fn lookup<F: Ord + 'static>(&self, id: IndexId<F>, value: &F) -> Vec<(&K, &R)> {
+        let Some(keys) = self.index(id).entries.get(value) else { return vec![] };
+        keys.iter().map(|(key, _)| (key, self.record(key))).collect()
+    }
+
+    /// The records whose field indexed by `id` is in `range`, in the order of the field, then of
+    /// their keys.
+    pub fn range<F: Ord + 'static, B: RangeBounds<F>>(&self, id: IndexId<F>, range: B) -> Vec<(&K, &R)> {
+        let entries = &self.index(id).entries;
+        let mut cursor = entries.cursor();
+        match range.start_bound() {
+            Bound::Included(start) | Bound::Excluded(start) => cursor.seek(start),
+            // from the ghost position to the first node
+            Bound::Unbounded => cursor.next(),
+        }
+        let mut found = vec![];
+        while let Some((field, keys)) = cursor.current() {
+            if !range.contains(field) {
+                if matches!(range.start_bound(), Bound::Excluded(start) if start == field) {
+                    cursor.next();
+                    continue;
+                }
+                break;
+            }
+            found.extend(keys.iter().map(|(key, _)| (key, self.record(key))));
+            cursor.next();
+        }
+        found
+    }
+
+    /// Visits the keys and their records in order.
+    pub fn iter(&self) -> M::Iter<'_> {
+        self.primary.iter()
+    }
+
+    /// Checks that every index holds every record under its field and nothing else.
+    pub fn validate(&self) -> Result<(), String> {
+        let count = self.primary.iter().count();
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} records", self.len, count));
+        }
+        for index in &self.indexes {
+            if index.len() != self.len {
+                return Err(format!("the index {} has {} keys for {} records", index.name(), index.len(), self.len));
+            }
+            if self.primary.iter().any(|(key, record)| !index.contains(key, record)) {
+                return Err(format!("the index {} misses a record", index.name()));
+            }
+        }
+        Ok(())
+    }
+
+    fn index<F: Ord + 'static>(&self, id: IndexId<F>) -> &FieldIndex<K, R, F> {
+        self.indexes[id.position].as_any().downcast_ref().expect("the id was made for this index")
+    }
+
+    fn record(&self, key: &K) -> &R {
+        self.primary.get(key).expect("an index only holds the keys of records")
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use crate::rbt::RbTree;
+    use crate::testing::Random;
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Person {
+        email: String,
+        city: &'static str,
+        age: u32,
+    }
+
+    fn person(email: &str, city: &'static str, age: u32) -> Person {
+        Person { email: email.to_string(), city, age }
+    }
+
+    fn keys<R>(found: Vec<(&u32, &R)>) -> Vec<u32> {
+        found.into_iter().map(|(&key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_exact_and_range_queries() {
+        let mut people = IndexedCollection::new();
+        people.insert(1, person("ada@example.com", "London", 36)).unwrap();
+        people.insert(2, person("alan@example.com", "London", 41)).unwrap();
+        let city = people.add_index("city", |person: &Person| person.city);
+        let age = people.add_index("age", |person: &Person| person.age);
+        people.insert(3, person("grace@example.com", "New York", 36)).unwrap();
+        people.insert(4, person("edsger@example.com", "Austin", 29)).unwrap();
+        assert!(people.validate().is_ok());
+
+        assert_eq!(keys(people.lookup(city, &"London")), vec![1, 2]);
+        assert_eq!(keys(people.lookup(city, &"Paris")), Vec::<u32>::new());
+        assert_eq!(keys(people.lookup(age, &36)), vec![1, 3]);
+        // in the order of the ages, then of the keys
+        assert_eq!(keys(people.range(age, 30..)), vec![1, 3, 2]);
+        assert_eq!(keys(people.range(age, ..=36)), vec![4, 1, 3]);
+        assert_eq!(keys(people.range(age, (Bound::Excluded(29), Bound::Excluded(41)))), vec![1, 3]);
+        assert_eq!(keys(people.range(city, "B".."O")), vec![1, 2, 3]);
+        assert_eq!(people.range(age, 50..).len(), 0);
+    }
+
+    #[test]
+    fn test_update_and_remove() {
+        let mut people = IndexedCollection::new();
+        let city = people.add_index("city", |person: &Person| person.city);
+        people.insert(1, person("ada@example.com", "London", 36)).unwrap();
+        people.insert(2, person("alan@example.com", "London", 41)).unwrap();
+
+        assert_eq!(people.update(&2, |person| person.city = "Manchester"), Ok(true));
+        assert_eq!(people.update(&3, |person| person.city = "Manchester"), Ok(false));
+        assert_eq!(keys(people.lookup(city, &"London")), vec![1]);
+        assert_eq!(keys(people.lookup(city, &"Manchester")), vec![2]);
+
+        assert_eq!(people.insert(1, person("ada@example.com", "Manchester", 37)), Ok(Some(person("ada@example.com", "London", 36))));
+        assert_eq!(keys(people.lookup(city, &"London")), Vec::<u32>::new());
+        assert_eq!(people.remove(&2).map(|person| person.age), Some(41));
+        assert_eq!(people.remove(&2), None);
+        assert_eq!(keys(people.lookup(city, &"Manchester")), vec![1]);
+        assert_eq!(people.len(), 1);
+        assert!(people.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unique_index() {
+        let mut people = IndexedCollection::new();
+        people.insert(1, person("ada@example.com", "London", 36)).unwrap();
+        let email = people.add_unique_index("email", |person: &Person| person.email.clone()).unwrap();
+        let city = people.add_index("city", |person: &Person| person.city);
+        people.insert(2, person("alan@example.com", "London", 41)).unwrap();
+
+        // refused changes leave the records and every index as they were
+        let violation = UniqueViolation { index: "email".to_string() };
+        assert_eq!(people.insert(3, person("ada@example.com", "Paris", 20)), Err(violation.clone()));
+        assert_eq!(people.update(&2, |person| person.email = "ada@example.com".to_string()), Err(violation));
+        assert_eq!(people.len(), 2);
+        assert_eq!(keys(people.lookup(city, &"Paris")), Vec::<u32>::new());
+        assert_eq!(people.get(&2).map(|person| person.email.as_str()), Some("alan@example.com"));
+        assert!(people.validate().is_ok());
+
+        // a record may keep its own value
+        assert_eq!(people.update(&1, |person| person.age += 1), Ok(true));
+        assert_eq!(keys(people.lookup(email, &"ada@example.com".to_string())), vec![1]);
+
+        // the cities are shared already
+        assert!(people.add_unique_index("unique city", |person: &Person| person.city).is_err());
+    }
+
+    #[test]
+    fn test_random_against_brute_force() {
+        type Pairs = IndexedCollection<u32, (u32, u32), RbTree<u32, (u32, u32)>>;
+        let mut collection = Pairs::default();
+        let first = collection.add_index("first", |record: &(u32, u32)| record.0);
+        let second = collection.add_index("second", |record: &(u32, u32)| record.1);
+        let mut expected = BTreeMap::new();
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for i in 0..3000 {
+            let key = (random.next_u64() % 200) as u32;
+            match random.next_u64() % 4 {
+                0 => assert_eq!(collection.remove(&key), expected.remove(&key)),
+                1 => {
+                    let found = expected.get_mut(&key).map(|record: &mut (u32, u32)| record.1 = i % 7).is_some();
+                    assert_eq!(collection.update(&key, |record| record.1 = i % 7), Ok(found));
+                }
+                _ => {
+                    let record = ((random.next_u64() % 20) as u32, (random.next_u64() % 7) as u32);
+                    assert_eq!(collection.insert(key, record), Ok(expected.insert(key, record)));
+                }
+            }
+            let (a, b) = ((random.next_u64() % 22) as u32, (random.next_u64() % 22) as u32);
+            let range = a.min(b)..a.max(b);
+            let mut brute: Vec<(u32, u32)> = expected.iter().filter(|(_, record)| range.contains(&record.0)).map(|(&key, record)| (record.0, key)).collect();
+            brute.sort();
+            assert_eq!(keys(collection.range(first, range)), brute.into_iter().map(|(_, key)| key).collect::<Vec<u32>>());
+            let value = i % 7;
+            let brute: Vec<u32> = expected.iter().filter(|(_, record)| record.1 == value).map(|(&key, _)| key).collect();
+            assert_eq!(keys(collection.lookup(second, &value)), brute);
+        }
+        assert!(collection.validate().is_ok());
+    }
+}
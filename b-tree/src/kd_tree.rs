@@ -0,0 +1,442 @@
+//!
+//! an implementation for a 2-D k-d tree
+//!
+//! https://en.wikipedia.org/wiki/K-d_tree
+//!
+//! The nodes are laid out like `bst::rs_bst::RsNode`, but a node at an even depth compares the
+//! points by their x coordinate and one at an odd depth by their y coordinate, so every node splits
+//! the region of its subtree in two halves. The points less than the node on its axis go to the
+//! left, the others to the right.
+//!
+//! Insertions keep the tree as it comes, `from_points` builds it balanced by splitting at the
+//! median. The searches walk the nodes with a stack instead of recursing, an unbalanced tree can be
+//! as deep as it is large.
+//!
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::format;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt;
+use core::mem;
+use crate::path::Descend;
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+
+/// The x and y coordinates.
+pub type Point = [i32; 2];
+
+/// A 2-D tree of points with their data `V`.
+#[derive(Default)]
+pub enum KdNode<V = ()> {
+    Node {
+        point: Point,
+        data: V,
+        left: Box<KdNode<V>>,
+        right: Box<KdNode<V>>,
+    },
+    #[default]
+    Empty,
+}
+
+/// The axis compared at `depth`, 0 for x and 1 for y.
+fn axis(depth: usize) -> usize {
+    depth % 2
+}
+
+/// The square of the euclidean distance, which can't overflow.
+fn distance(a: &Point, b: &Point) -> u128 {
+    a.iter().zip(b).map(|(&a, &b)| u128::from((i64::from(a) - i64::from(b)).unsigned_abs()).pow(2)).sum()
+}
+
+impl<V> KdNode<V> {
+    pub fn new() -> KdNode<V> {
+        KdNode::Empty
+    }
+
+    pub fn create(point: Point, data: V) -> KdNode<V> {
+        KdNode::Node {
+            point,
+            data,
+            left: Box::new(KdNode::Empty),
+            right: Box::new(KdNode::Empty),
+        }
+    }
+
+    /// A balanced tree of `entries`: the median on the axis of every level goes up, and the
+    /// points equal to it on that axis go to its right. Takes O(n log n).
+    pub fn from_points(entries: Vec<(Point, V)>) -> KdNode<V> {
+        fn build<V>(mut entries: Vec<(Point, V)>, depth: usize) -> KdNode<V> {
+            if entries.is_empty() {
+                return KdNode::Empty;
+            }
+            let axis = axis(depth);
+            let mid = entries.len() / 2;
+            entries.select_nth_unstable_by_key(mid, |(point, _)| point[axis]);
+            // the points before the median are not greater on the axis, the equal ones move after
+            let pivot = entries[mid].0[axis];
+            let mut less = 0;
+            for i in 0..mid {
+                if entries[i].0[axis] < pivot {
+                    entries.swap(less, i);
+                    less += 1;
+                }
+            }
+            entries.swap(less, mid);
+
+            let right = entries.split_off(less + 1);
+            let (point, data) = entries.pop().expect("the median is there");
+            KdNode::Node {
+                point,
+                data,
+                left: Box::new(build(entries, depth + 1)),
+                right: Box::new(build(right, depth + 1)),
+            }
+        }
+
+        build(entries, 0)
+    }
+
+    /// Inserts `point` with `data` as a leaf, returns the old data if `point` was there already.
+    pub fn insert(&mut self, new_point: Point, new_data: V) -> Option<V> {
+        let mut node = self;
+        let mut depth = 0;
+        while let KdNode::Node { point, data, left, right } = node {
+            if *point == new_point {
+                return Some(mem::replace(data, new_data));
+            }
+            let axis = axis(depth);
+            node = if new_point[axis] < point[axis] { left } else { right };
+            depth += 1;
+        }
+        *node = KdNode::create(new_point, new_data);
+        None
+    }
+
+    pub fn get(&self, find_point: &Point) -> Option<&V> {
+        let mut node = self;
+        let mut depth = 0;
+        while let KdNode::Node { point, data, left, right } = node {
+            if point == find_point {
+                return Some(data);
+            }
+            let axis = axis(depth);
+            node = if find_point[axis] < point[axis] { left } else { right };
+            depth += 1;
+        }
+        None
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        self.get(point).is_some()
+    }
+
+    /// The number of points, it visits them all.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, KdNode::Empty)
+    }
+
+    /// Visits the points in pre-order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Point, &V)> {
+        let mut nodes = vec![self];
+        core::iter::from_fn(move || loop {
+            if let KdNode::Node { point, data, left, right } = nodes.pop()? {
+                nodes.push(right);
+                nodes.push(left);
+                return Some((point, data));
+            }
+        })
+    }
+
+    /// The points within the rectangle from `min` to `max`, bounds included, in pre-order. Like
+    /// `range_search::range_query` a subtree is skipped when its half of the plane misses the range.
+    pub fn range(&self, min: Point, max: Point) -> Vec<(&Point, &V)> {
+        let mut found = vec![];
+        let mut nodes = vec![(self, 0)];
+        while let Some((node, depth)) = nodes.pop() {
+            let KdNode::Node { point, data, left, right } = node else { continue };
+            if (0..2).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis]) {
+                found.push((point, data));
+            }
+            let axis = axis(depth);
+            // the right subtree goes first, so it is visited after the left one
+            if point[axis] <= max[axis] {
+                nodes.push((right, depth + 1));
+            }
+            if point[axis] > min[axis] {
+                nodes.push((left, depth + 1));
+            }
+        }
+        found
+    }
+
+    /// The `k` points nearest to `target`, the nearest first, the ties broken by the smaller point.
+    pub fn nearest(&self, target: &Point, k: usize) -> Vec<(&Point, &V)> {
+        if k == 0 {
+            return vec![];
+        }
+        let mut best: BinaryHeap<Neighbour<'_, V>> = BinaryHeap::with_capacity(k);
+        // every subtree comes with a lower bound of the distance from `target` to its region, the
+        // nearer child is pushed last so it is searched first and makes the bound of the other one
+        // more likely to rule it out
+        let mut nodes = vec![(self, 0, 0)];
+        while let Some((node, depth, bound)) = nodes.pop() {
+            let KdNode::Node { point, data, left, right } = node else { continue };
+            if best.len() == k && best.peek().is_some_and(|worst| bound > worst.distance) {
+                continue;
+            }
+            let neighbour = Neighbour { distance: distance(point, target), point, data };
+            if best.len() < k {
+                best.push(neighbour);
+            } else if best.peek().is_some_and(|worst| neighbour < *worst) {
+                best.pop();
+                best.push(neighbour);
+            }
+
+            let axis = axis(depth);
+            let across = u128::from((i64::from(target[axis]) - i64::from(point[axis])).unsigned_abs()).pow(2);
+            let (near, far) = if target[axis] < point[axis] { (left, right) } else { (right, left) };
+            nodes.push((far, depth + 1, bound.max(across)));
+            nodes.push((near, depth + 1, bound));
+        }
+        best.into_sorted_vec().into_iter().map(|neighbour| (neighbour.point, neighbour.data)).collect()
+    }
+
+    /// Checks that every point is on the right side of all its ancestors.
+    pub fn validate(&self) -> Result<(), String> {
+        // the region of a subtree, the lower bounds included and the upper ones excluded
+        type Bounds = [Option<i32>; 2];
+        let mut nodes: Vec<(&KdNode<V>, usize, Bounds, Bounds)> = vec![(self, 0, [None; 2], [None; 2])];
+        while let Some((node, depth, lower, upper)) = nodes.pop() {
+            let KdNode::Node { point, left, right, .. } = node else { continue };
+            for axis in 0..2 {
+                if lower[axis].is_some_and(|lower| point[axis] < lower) || upper[axis].is_some_and(|upper| point[axis] >= upper) {
+                    return Err(format!("the point {:?} is out of the region of its subtree", point));
+                }
+            }
+            let axis = axis(depth);
+            let (mut left_upper, mut right_lower) = (upper, lower);
+            left_upper[axis] = Some(point[axis]);
+            right_lower[axis] = Some(point[axis]);
+            nodes.push((left, depth + 1, lower, left_upper));
+            nodes.push((right, depth + 1, right_lower, upper));
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        match self {
+            KdNode::Node { point, left, right, .. } => {
+                Some(Sketch::new(format!("({}, {})", point[0], point[1]), vec![left.sketch(), right.sketch()]))
+            }
+            KdNode::Empty => None,
+        }
+    }
+
+    /// The shape of the tree, every node owns the boxes of its two children, empty or not.
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root(), 2 * mem::size_of::<Self>(), |_| ());
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    fn root(&self) -> Option<&KdNode<V>> {
+        match self {
+            KdNode::Node { .. } => Some(self),
+            KdNode::Empty => None,
+        }
+    }
+}
+
+/// A candidate of `KdNode::nearest`, the greatest is the farthest.
+struct Neighbour<'a, V> {
+    distance: u128,
+    point: &'a Point,
+    data: &'a V,
+}
+
+impl<V> PartialEq for Neighbour<'_, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<V> Eq for Neighbour<'_, V> {}
+
+impl<V> PartialOrd for Neighbour<'_, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V> Ord for Neighbour<'_, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.distance, self.point).cmp(&(other.distance, other.point))
+    }
+}
+
+impl<V> Descend for KdNode<V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        match self {
+            KdNode::Node { left: child_left, right: child_right, .. } => {
+                if left { child_left.root() } else { child_right.root() }
+            }
+            KdNode::Empty => None,
+        }
+    }
+}
+
+impl<V> fmt::Display for KdNode<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+/// Frees the nodes one by one like `RsNode` does, without recursing once per level.
+impl<V> Drop for KdNode<V> {
+    fn drop(&mut self) {
+        let mut nodes = vec![];
+        if let KdNode::Node { left, right, .. } = self {
+            nodes.push(mem::take(&mut **left));
+            nodes.push(mem::take(&mut **right));
+        }
+        while let Some(mut node) = nodes.pop() {
+            if let KdNode::Node { left, right, .. } = &mut node {
+                nodes.push(mem::take(&mut **left));
+                nodes.push(mem::take(&mut **right));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::testing::Random;
+    use super::*;
+
+    fn points(tree: &[(&Point, &())]) -> Vec<Point> {
+        let mut points: Vec<Point> = tree.iter().map(|(&point, _)| point).collect();
+        points.sort_unstable();
+        points
+    }
+
+    #[test]
+    fn test_insert() {
+        //           (5, 5)
+        //          /      \
+        //      (2, 8)    (7, 3)
+        //      /          /   \
+        //  (1, 1)     (9, 1) (6, 6)
+        let mut tree = KdNode::new();
+        for (point, data) in [([5, 5], 'a'), ([2, 8], 'b'), ([7, 3], 'c'), ([1, 1], 'd'), ([9, 1], 'e'), ([6, 6], 'f')] {
+            assert_eq!(tree.insert(point, data), None);
+        }
+        assert_eq!(tree.insert([6, 6], 'g'), Some('f'));
+        assert_eq!(tree.get(&[6, 6]), Some(&'g'));
+        assert_eq!(tree.get(&[6, 5]), None);
+        assert!(tree.contains(&[1, 1]));
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.iter().map(|(point, _)| *point).collect::<Vec<_>>(), vec![[5, 5], [2, 8], [1, 1], [7, 3], [9, 1], [6, 6]]);
+        assert!(tree.validate().is_ok());
+
+        assert_eq!(tree.range([2, 1], [7, 6]).into_iter().map(|(_, &data)| data).collect::<String>(), "acg");
+        assert_eq!(tree.nearest(&[9, 2], 2).into_iter().map(|(_, &data)| data).collect::<String>(), "ec");
+        assert_eq!(tree.nearest(&[0, 0], 10).len(), 6);
+        assert!(tree.nearest(&[0, 0], 0).is_empty());
+        assert!(KdNode::<()>::new().nearest(&[0, 0], 3).is_empty());
+
+        // the distances across the whole plane don't overflow
+        let mut corners = KdNode::new();
+        corners.insert([i32::MIN, i32::MIN], ());
+        corners.insert([i32::MAX, i32::MAX], ());
+        assert_eq!(corners.nearest(&[i32::MAX - 1, i32::MIN], 1)[0].0, &[i32::MIN, i32::MIN]);
+    }
+
+    #[test]
+    fn test_from_points() {
+        let entries: Vec<(Point, usize)> = (0..1000).map(|i| ([i * 7919 % 1000, i], i as usize)).collect();
+        let tree = KdNode::from_points(entries);
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.stats().height, stats::optimal_height(1000));
+        assert_eq!(tree.get(&[7919 % 1000, 1]), Some(&1));
+
+        // the points equal on an axis to a median are all on its right
+        let entries: Vec<(Point, ())> = (0..1000).map(|i| ([i % 10, i / 10 % 7], ())).collect();
+        let tree = KdNode::from_points(entries);
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.range([3, 2], [4, 2]).len(), 28);
+    }
+
+    #[test]
+    fn test_validate() {
+        // (3, 9) is on the left of (5, 5) but not less than it on x
+        let mut tree = KdNode::create([5, 5], ());
+        if let KdNode::Node { left, .. } = &mut tree {
+            **left = KdNode::create([3, 9], ());
+            if let KdNode::Node { right, .. } = &mut **left {
+                **right = KdNode::create([6, 9], ());
+            }
+        }
+        assert_eq!(tree.validate(), Err("the point [6, 9] is out of the region of its subtree".to_string()));
+    }
+
+    #[test]
+    fn test_random_against_brute_force() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let coordinate = |random: &mut Random| (random.next_u64() % 200) as i32 - 100;
+        let entries: Vec<(Point, ())> = (0..2000).map(|_| ([coordinate(&mut random), coordinate(&mut random)], ())).collect();
+        let mut inserted = KdNode::new();
+        for &(point, data) in &entries {
+            inserted.insert(point, data);
+        }
+        let mut all: Vec<Point> = entries.iter().map(|&(point, _)| point).collect();
+        all.sort_unstable();
+        all.dedup();
+        let mut unique: Vec<(Point, ())> = all.iter().map(|&point| (point, ())).collect();
+        unique.reverse();
+        let built = KdNode::from_points(unique);
+
+        for tree in [&inserted, &built] {
+            assert!(tree.validate().is_ok());
+            assert_eq!(tree.len(), all.len());
+            for _ in 0..200 {
+                let (a, b) = ([coordinate(&mut random), coordinate(&mut random)], [coordinate(&mut random), coordinate(&mut random)]);
+                let (min, max) = ([a[0].min(b[0]), a[1].min(b[1])], [a[0].max(b[0]), a[1].max(b[1])]);
+                let expected: Vec<Point> = all.iter().copied().filter(|p| min[0] <= p[0] && p[0] <= max[0] && min[1] <= p[1] && p[1] <= max[1]).collect();
+                assert_eq!(points(&tree.range(min, max)), expected);
+
+                let k = (random.next_u64() % 20) as usize;
+                let mut expected = all.clone();
+                expected.sort_by_key(|point| (distance(point, &a), *point));
+                expected.truncate(k);
+                let found: Vec<Point> = tree.nearest(&a, k).into_iter().map(|(&point, _)| point).collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate() {
+        // points sorted on both axes make a chain going right
+        let mut tree = KdNode::create([0, 0], ());
+        let mut node = &mut tree;
+        for i in 1..1_000_000 {
+            let KdNode::Node { right, .. } = node else { unreachable!() };
+            **right = KdNode::create([i, i], ());
+            node = &mut **right;
+        }
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.stats().height, 1_000_000);
+        assert_eq!(tree.range([10, 10], [12, 20]).len(), 3);
+        assert_eq!(tree.nearest(&[999_999, 999_999], 1)[0].0, &[999_999, 999_999]);
+    }
+}
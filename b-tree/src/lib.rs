@@ -0,0 +1,63 @@
+//!
+//! binary search trees and B-trees
+//!
+//! The trees only need `alloc`, the crate is `#![no_std]` unless the `std` feature, on by default,
+//! brings what needs an operating system: printing, the concurrent B+ tree, the versioned map, the
+//! REPL and the benchmarks.
+//!
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+pub mod bst;
+pub mod rbt;
+pub mod avl;
+pub mod range_search;
+#[cfg(feature = "std")]
+pub mod concurrent;
+pub mod path;
+pub mod map;
+pub mod compare;
+pub mod multiset;
+pub mod render;
+#[cfg(feature = "std")]
+pub mod repl;
+pub mod stats;
+pub mod splay;
+pub mod treap;
+pub mod scapegoat;
+pub mod clrs;
+pub mod aa;
+pub mod aggregate;
+#[cfg(feature = "std")]
+pub mod mvcc;
+pub mod prefix;
+pub mod index;
+pub mod fuzz;
+pub mod error;
+pub mod tombstone;
+pub mod static_tree;
+pub mod kd_tree;
+pub mod merkle;
+#[cfg(test)]
+pub mod testing;
+
+#[cfg(test)]
+pub mod tests {
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Builds the library without `std`, as firmware would, in a target directory of its own so the
+    /// build running this test is not locked.
+    #[test]
+    fn test_no_std_build() {
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let output = Command::new(env!("CARGO"))
+            .args(["build", "--lib", "--no-default-features", "--target-dir"])
+            .arg(manifest.join("target").join("no-std"))
+            .current_dir(manifest)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
@@ -1,7 +1,20 @@
-mod bst;
-mod rbt;
-mod avl;
-mod range_search;
+use std::{env, io, process};
+use b_tree::repl;
 
+
+/// Without arguments the REPL reads commands from the terminal, otherwise it runs the scripts
+/// given as arguments one after another.
 fn main() {
+    let mut repl = repl::Repl::new();
+    let mut out = io::stdout();
+    let scripts: Vec<String> = env::args().skip(1).collect();
+    let result = if scripts.is_empty() {
+        repl.run(io::stdin().lock(), &mut out, true)
+    } else {
+        scripts.iter().try_for_each(|script| repl.execute(&format!("load {}", script), &mut out).map(drop))
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
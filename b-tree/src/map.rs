@@ -0,0 +1,29 @@
+//!
+//! the operations shared by the ordered maps of this crate
+//!
+//! `AvlTree` and `RbTree` implement it, so structures built on top of an ordered map can work with
+//! either of them.
+//!
+
+pub trait OrderedMap<K, V>: Default {
+    type Iter<'a>: Iterator<Item = (&'a K, &'a V)>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn get(&self, key: &K) -> Option<&V>;
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+
+    /// Inserts `key` with `value`, returns the old value if `key` was present.
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// The value of `key`, `default()` is inserted first if `key` is absent.
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, default: F) -> &mut V;
+
+    /// Visits the keys and their values in order.
+    fn iter(&self) -> Self::Iter<'_>;
+}
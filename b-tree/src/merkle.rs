@@ -0,0 +1,742 @@
+//!
+//! a Merkle-hashed search tree
+//!
+//! https://en.wikipedia.org/wiki/Merkle_tree
+//!
+//! Every node keeps a hash of its subtree, made of the hashes of its children and of its own entry,
+//! so the hash of the root sums up the whole contents. An entry is proven to be in the tree by the
+//! hashes met on the way from its node up to the root, and two trees are compared by walking down
+//! only the subtrees whose hashes differ.
+//!
+//! Comparing subtrees makes sense only if equal contents give equal shapes, which the history of the
+//! insertions decides in the AVL and red-black trees. This is a treap whose priorities come from the
+//! hashes of the keys instead of a generator, the ties broken by the keys: its shape only depends on
+//! the keys it holds, like a treap built from the keys in the order of decreasing priority. The
+//! rotations recompute the hashes of the two nodes they move, like `aggregate` does its summaries.
+//!
+//! The hashes come from the `Hasher` `H`, FNV-1a by default, which is fast but not cryptographic: it
+//! detects differences between replicas, a tree exposed to forged contents needs a hasher which
+//! resists collisions.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem;
+use crate::path::{self, Descend};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+use crate::treap::Rng;
+
+/// The hash of an empty subtree.
+const EMPTY: u64 = 0;
+
+/// The 64-bit FNV-1a hash.
+#[derive(Debug, Clone)]
+pub struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn entry_hash<T: Hash, V: Hash, H: Hasher + Default>(key: &T, data: &V) -> u64 {
+    let mut hasher = H::default();
+    key.hash(&mut hasher);
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn node_hash<H: Hasher + Default>(left: u64, entry: u64, right: u64) -> u64 {
+    let mut hasher = H::default();
+    hasher.write_u64(left);
+    hasher.write_u64(entry);
+    hasher.write_u64(right);
+    hasher.finish()
+}
+
+/// The priority of `key`, its hash mixed once more so that close keys get unrelated priorities.
+fn priority<T: Hash, H: Hasher + Default>(key: &T) -> u64 {
+    let mut hasher = H::default();
+    key.hash(&mut hasher);
+    Rng::new(hasher.finish()).next_u64()
+}
+
+type MerkleBoxOption<T, V> = Option<Box<Node<T, V>>>;
+
+struct Node<T, V> {
+    key: T,
+    data: V,
+    priority: u64,
+    /// the hash of the key and the data
+    entry: u64,
+    /// the hash of the subtree
+    hash: u64,
+    left: MerkleBoxOption<T, V>,
+    right: MerkleBoxOption<T, V>,
+}
+
+fn hash<T, V>(node: &MerkleBoxOption<T, V>) -> u64 {
+    node.as_ref().map_or(EMPTY, |node| node.hash)
+}
+
+impl<T: Ord, V> Node<T, V> {
+    fn update<H: Hasher + Default>(&mut self) {
+        self.hash = node_hash::<H>(hash(&self.left), self.entry, hash(&self.right));
+    }
+
+    /// Whether this node goes above `other`.
+    fn outranks(&self, other: &Node<T, V>) -> bool {
+        (self.priority, &other.key) > (other.priority, &self.key)
+    }
+
+    /// The left child moves up, this node becomes its right child.
+    fn right_rotate<H: Hasher + Default>(mut self: Box<Self>) -> Box<Self> {
+        let Some(mut child) = self.left.take() else { return self };
+        self.left = child.right.take();
+        self.update::<H>();
+        child.right = Some(self);
+        child.update::<H>();
+        child
+    }
+
+    /// The right child moves up, this node becomes its left child.
+    fn left_rotate<H: Hasher + Default>(mut self: Box<Self>) -> Box<Self> {
+        let Some(mut child) = self.right.take() else { return self };
+        self.right = child.left.take();
+        self.update::<H>();
+        child.left = Some(self);
+        child.update::<H>();
+        child
+    }
+}
+
+impl<T, V> Descend for Node<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+/// A map from `T` to `V` hashed with `H`, use `V = ()` for a set.
+pub struct MerkleTree<T, V = (), H = Fnv> {
+    root: MerkleBoxOption<T, V>,
+    len: usize,
+    hasher: PhantomData<fn() -> H>,
+}
+
+impl<T, V, H> Default for MerkleTree<T, V, H> {
+    fn default() -> Self {
+        MerkleTree { root: None, len: 0, hasher: PhantomData }
+    }
+}
+
+/// An entry of `MerkleTree::diff`, the entries of the first tree are on the left.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diff<'a, T, V> {
+    /// only in the left tree
+    Left(&'a T, &'a V),
+    /// only in the right tree
+    Right(&'a T, &'a V),
+    /// in both trees with different data, the left one first
+    Changed(&'a T, &'a V, &'a V),
+}
+
+/// The hashes proving that an entry is in a tree, see `MerkleTree::prove`.
+#[derive(Debug, Clone)]
+pub struct Proof<H = Fnv> {
+    /// the hashes of the children of the node of the entry
+    children: (u64, u64),
+    /// from the parent of the node up to the root: whether the node below is its left child, the
+    /// hash of its entry and the hash of its other child
+    path: Vec<(bool, u64, u64)>,
+    hasher: PhantomData<fn() -> H>,
+}
+
+impl<H: Hasher + Default> Proof<H> {
+    /// Whether `key` with `data` is in the tree whose root has the hash `root_hash`.
+    pub fn verify<T: Hash, V: Hash>(&self, key: &T, data: &V, root_hash: u64) -> bool {
+        let (left, right) = self.children;
+        let mut hash = node_hash::<H>(left, entry_hash::<T, V, H>(key, data), right);
+        for &(from_left, entry, sibling) in &self.path {
+            hash = if from_left { node_hash::<H>(hash, entry, sibling) } else { node_hash::<H>(sibling, entry, hash) };
+        }
+        hash == root_hash
+    }
+
+    /// The number of nodes between the entry and the root.
+    pub fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+}
+
+impl<T: Ord + Hash, V: Hash> MerkleTree<T, V> {
+    pub fn new() -> MerkleTree<T, V> {
+        MerkleTree::default()
+    }
+}
+
+impl<T: Ord + Hash, V: Hash, H: Hasher + Default> MerkleTree<T, V, H> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// The hash of the whole contents, equal for trees holding the same entries.
+    pub fn root_hash(&self) -> u64 {
+        hash(&self.root)
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match key.cmp(&n.key) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            }
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present. The hashes are
+    /// recomputed on the way back to the root.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        let entry = entry_hash::<T, V, H>(&key, &data);
+        let priority = priority::<T, H>(&key);
+        let node = Box::new(Node { key, data, priority, entry, hash: node_hash::<H>(EMPTY, entry, EMPTY), left: None, right: None });
+        let (root, old) = insert::<T, V, H>(self.root.take(), node);
+        self.root = Some(root);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        let (root, removed) = remove::<T, V, H>(self.root.take(), key);
+        self.root = root;
+        let removed = removed?;
+        self.len -= 1;
+        Some(removed.data)
+    }
+
+    /// Changes the data of `key` with `f` and recomputes the hashes above it. Returns `false` if
+    /// `key` isn't there.
+    pub fn update<F: FnOnce(&mut V)>(&mut self, key: &T, f: F) -> bool {
+        fn update<T: Ord + Hash, V: Hash, H: Hasher + Default, F: FnOnce(&mut V)>(node: &mut MerkleBoxOption<T, V>, key: &T, f: F) -> bool {
+            let Some(node) = node else { return false };
+            let found = match key.cmp(&node.key) {
+                Ordering::Equal => {
+                    f(&mut node.data);
+                    node.entry = entry_hash::<T, V, H>(&node.key, &node.data);
+                    true
+                }
+                Ordering::Less => update::<T, V, H, F>(&mut node.left, key, f),
+                Ordering::Greater => update::<T, V, H, F>(&mut node.right, key, f),
+            };
+            if found {
+                node.update::<H>();
+            }
+            found
+        }
+
+        update::<T, V, H, F>(&mut self.root, key, f)
+    }
+
+    /// The hashes proving that `key` is in the tree with its data, `None` if it isn't there.
+    pub fn prove(&self, key: &T) -> Option<Proof<H>> {
+        let mut path = vec![];
+        let mut node = self.root.as_deref()?;
+        loop {
+            let (from_left, child, sibling) = match key.cmp(&node.key) {
+                Ordering::Equal => break,
+                Ordering::Less => (true, node.left.as_deref()?, &node.right),
+                Ordering::Greater => (false, node.right.as_deref()?, &node.left),
+            };
+            path.push((from_left, node.entry, hash(sibling)));
+            node = child;
+        }
+        path.reverse();
+        Some(Proof { children: (hash(&node.left), hash(&node.right)), path, hasher: PhantomData })
+    }
+
+    /// The entries which differ between this tree and `other`, in the order of the keys. Only the
+    /// subtrees whose hashes differ are walked.
+    pub fn diff<'a>(&'a self, other: &'a MerkleTree<T, V, H>) -> Vec<Diff<'a, T, V>> {
+        let mut differ = Differ::<T, V, H>::new();
+        differ.diff(View::of(&self.root), View::of(&other.root));
+        differ.found
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&T, &V)> {
+        path::InOrder::new(self.root.as_deref()).map(|node| (&node.key, &node.data))
+    }
+
+    /// Checks the order of the keys, the priorities, the hashes and the length.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut nodes: Vec<&Node<T, V>> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = nodes.pop() {
+            if node.priority != priority::<T, H>(&node.key) {
+                return Err(format!("a node has the priority {} instead of {}", node.priority, priority::<T, H>(&node.key)));
+            }
+            if node.entry != entry_hash::<T, V, H>(&node.key, &node.data) {
+                return Err("a node has a stale hash of its entry".to_string());
+            }
+            if node.hash != node_hash::<H>(hash(&node.left), node.entry, hash(&node.right)) {
+                return Err("a node has a stale hash of its subtree".to_string());
+            }
+            for child in [&node.left, &node.right].into_iter().flatten() {
+                if child.outranks(node) {
+                    return Err(format!("a node has the priority {} below a node of the priority {}", child.priority, node.priority));
+                }
+                nodes.push(child);
+            }
+        }
+
+        let keys: Vec<&T> = self.iter().map(|(key, _)| key).collect();
+        if let Some(i) = keys.windows(2).position(|pair| pair[0] >= pair[1]) {
+            return Err(format!("the keys at {} and {} are out of order", i, i + 1));
+        }
+        if keys.len() != self.len {
+            return Err(format!("the tree has {} keys but its length is {}", keys.len(), self.len));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root.as_deref(), mem::size_of::<Node<T, V>>(), |_| ());
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+}
+
+/// Inserts `new` as a leaf and rotates it up as long as it outranks its parent. Returns the new
+/// root and the old data of the key.
+fn insert<T: Ord, V, H: Hasher + Default>(node: MerkleBoxOption<T, V>, new: Box<Node<T, V>>) -> (Box<Node<T, V>>, Option<V>) {
+    let Some(mut node) = node else { return (new, None) };
+    match new.key.cmp(&node.key) {
+        Ordering::Equal => {
+            let new = *new;
+            node.entry = new.entry;
+            let old = mem::replace(&mut node.data, new.data);
+            node.update::<H>();
+            (node, Some(old))
+        }
+        Ordering::Less => {
+            let (left, old) = insert::<T, V, H>(node.left.take(), new);
+            let rotate = left.outranks(&node);
+            node.left = Some(left);
+            node.update::<H>();
+            (if rotate { node.right_rotate::<H>() } else { node }, old)
+        }
+        Ordering::Greater => {
+            let (right, old) = insert::<T, V, H>(node.right.take(), new);
+            let rotate = right.outranks(&node);
+            node.right = Some(right);
+            node.update::<H>();
+            (if rotate { node.left_rotate::<H>() } else { node }, old)
+        }
+    }
+}
+
+/// Removes the node of `key` by merging its subtrees. Returns the new root and the removed node.
+fn remove<T: Ord, V, H: Hasher + Default>(node: MerkleBoxOption<T, V>, key: &T) -> (MerkleBoxOption<T, V>, MerkleBoxOption<T, V>) {
+    let Some(mut node) = node else { return (None, None) };
+    let removed = match key.cmp(&node.key) {
+        Ordering::Equal => {
+            let rest = merge::<T, V, H>(node.left.take(), node.right.take());
+            return (rest, Some(node));
+        }
+        Ordering::Less => {
+            let (left, removed) = remove::<T, V, H>(node.left.take(), key);
+            node.left = left;
+            removed
+        }
+        Ordering::Greater => {
+            let (right, removed) = remove::<T, V, H>(node.right.take(), key);
+            node.right = right;
+            removed
+        }
+    };
+    node.update::<H>();
+    (Some(node), removed)
+}
+
+/// Joins two trees, every key of `left` being less than the keys of `right`. The root which
+/// outranks the other stays on top.
+fn merge<T: Ord, V, H: Hasher + Default>(left: MerkleBoxOption<T, V>, right: MerkleBoxOption<T, V>) -> MerkleBoxOption<T, V> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut left), Some(mut right)) => {
+            if left.outranks(&right) {
+                left.right = merge::<T, V, H>(left.right.take(), Some(right));
+                left.update::<H>();
+                Some(left)
+            } else {
+                right.left = merge::<T, V, H>(Some(left), right.left.take());
+                right.update::<H>();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// The root of a `View` with its two subtrees.
+type Opened<'a, T, V> = (&'a Node<T, V>, View<'a, T, V>, View<'a, T, V>);
+
+/// The keys less than a key, its node and the keys greater.
+type Split<'a, T, V> = (View<'a, T, V>, Option<&'a Node<T, V>>, View<'a, T, V>);
+
+/// A subtree of one of the trees compared by `diff`, as it is or cut by a split. Only the nodes on
+/// the path of a split are copied, with the hashes of their new subtrees, the rest is borrowed.
+enum View<'a, T, V> {
+    Empty,
+    Whole(&'a Node<T, V>),
+    Cut {
+        node: &'a Node<T, V>,
+        left: Box<View<'a, T, V>>,
+        right: Box<View<'a, T, V>>,
+        hash: u64,
+    },
+}
+
+impl<'a, T, V> View<'a, T, V> {
+    fn of(node: &'a MerkleBoxOption<T, V>) -> View<'a, T, V> {
+        node.as_deref().map_or(View::Empty, View::Whole)
+    }
+
+    fn hash(&self) -> u64 {
+        match self {
+            View::Empty => EMPTY,
+            View::Whole(node) => node.hash,
+            View::Cut { hash, .. } => *hash,
+        }
+    }
+
+    /// The root and its two subtrees, `None` for an empty subtree.
+    fn open(self) -> Option<Opened<'a, T, V>> {
+        match self {
+            View::Empty => None,
+            View::Whole(node) => Some((node, View::of(&node.left), View::of(&node.right))),
+            View::Cut { node, left, right, .. } => Some((node, *left, *right)),
+        }
+    }
+}
+
+/// The walk of `MerkleTree::diff`, `visited` counts the nodes it looks at.
+struct Differ<'a, T, V, H> {
+    found: Vec<Diff<'a, T, V>>,
+    visited: usize,
+    hasher: PhantomData<fn() -> H>,
+}
+
+impl<'a, T: Ord, V, H: Hasher + Default> Differ<'a, T, V, H> {
+    fn new() -> Differ<'a, T, V, H> {
+        Differ { found: vec![], visited: 0, hasher: PhantomData }
+    }
+
+    /// Compares two subtrees holding the keys of the same range, the left one from the first tree.
+    /// Equal hashes end the walk and equal roots lead to their children. Otherwise the root which
+    /// outranks the other one is missing from the other subtree, which is split by its key so that
+    /// the halves can be compared with the children of the root.
+    fn diff(&mut self, left: View<'a, T, V>, right: View<'a, T, V>) {
+        if left.hash() == right.hash() {
+            return;
+        }
+        let (l, r) = match (left.open(), right.open()) {
+            (None, None) => return,
+            (Some(l), None) => return self.all(Some(l), true),
+            (None, Some(r)) => return self.all(Some(r), false),
+            (Some(l), Some(r)) => (l, r),
+        };
+        self.visited += 2;
+        let ((l, l_left, l_right), (r, r_left, r_right)) = (l, r);
+        if l.key == r.key {
+            self.diff(l_left, r_left);
+            if l.entry != r.entry {
+                self.found.push(Diff::Changed(&l.key, &l.data, &r.data));
+            }
+            self.diff(l_right, r_right);
+        } else if l.outranks(r) {
+            let (less, equal, greater) = self.split(View::Cut { node: r, left: Box::new(r_left), right: Box::new(r_right), hash: r.hash }, &l.key);
+            debug_assert!(equal.is_none(), "a root outranking the other one isn't in its subtree");
+            self.diff(l_left, less);
+            self.found.push(Diff::Left(&l.key, &l.data));
+            self.diff(l_right, greater);
+        } else {
+            let (less, equal, greater) = self.split(View::Cut { node: l, left: Box::new(l_left), right: Box::new(l_right), hash: l.hash }, &r.key);
+            debug_assert!(equal.is_none(), "a root outranking the other one isn't in its subtree");
+            self.diff(less, r_left);
+            self.found.push(Diff::Right(&r.key, &r.data));
+            self.diff(greater, r_right);
+        }
+    }
+
+    /// Splits `view` into the keys less than `key`, the node of `key` and the keys greater, along
+    /// the path of a search for `key`.
+    fn split(&mut self, view: View<'a, T, V>, key: &T) -> Split<'a, T, V> {
+        let Some((node, left, right)) = view.open() else { return (View::Empty, None, View::Empty) };
+        self.visited += 1;
+        match key.cmp(&node.key) {
+            Ordering::Equal => (left, Some(node), right),
+            Ordering::Less => {
+                let (less, equal, greater) = self.split(left, key);
+                (less, equal, self.cut(node, greater, right))
+            }
+            Ordering::Greater => {
+                let (less, equal, greater) = self.split(right, key);
+                (self.cut(node, left, less), equal, greater)
+            }
+        }
+    }
+
+    fn cut(&self, node: &'a Node<T, V>, left: View<'a, T, V>, right: View<'a, T, V>) -> View<'a, T, V> {
+        let hash = node_hash::<H>(left.hash(), node.entry, right.hash());
+        View::Cut { node, left: Box::new(left), right: Box::new(right), hash }
+    }
+
+    /// Reports every entry of an opened subtree as only in the first (`first == true`) or the
+    /// second tree.
+    fn all(&mut self, opened: Option<Opened<'a, T, V>>, first: bool) {
+        let Some((node, left, right)) = opened else { return };
+        self.visited += 1;
+        self.all(left.open(), first);
+        self.found.push(if first { Diff::Left(&node.key, &node.data) } else { Diff::Right(&node.key, &node.data) });
+        self.all(right.open(), first);
+    }
+}
+
+impl<T: Display, V, H> MerkleTree<T, V, H> {
+    /// The shape of the tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V>(node: &Node<T, V>) -> Sketch {
+            let children = [&node.left, &node.right].map(|child| child.as_deref().map(sketch));
+            Sketch::new(node.key.to_string(), children.into())
+        }
+
+        self.root.as_deref().map(sketch)
+    }
+}
+
+impl<T: Display, V, H> Display for MerkleTree<T, V, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use crate::testing::Random;
+    use super::*;
+
+    #[test]
+    fn test_history_independence() {
+        let mut forward = MerkleTree::new();
+        let mut backward = MerkleTree::new();
+        for key in 0..500 {
+            forward.insert(key, key * 2);
+            backward.insert(499 - key, (499 - key) * 2);
+        }
+        assert_eq!(forward.root_hash(), backward.root_hash());
+        assert_eq!(forward.to_string(), backward.to_string());
+        assert!(forward.validate().is_ok());
+        assert!(backward.validate().is_ok());
+
+        // inserted and removed again
+        let before = forward.root_hash();
+        forward.insert(1000, 0);
+        assert_ne!(forward.root_hash(), before);
+        assert_eq!(forward.remove(&1000), Some(0));
+        assert_eq!(forward.root_hash(), before);
+
+        assert!(forward.update(&7, |data| *data += 1));
+        assert!(!forward.update(&7000, |data| *data += 1));
+        assert_ne!(forward.root_hash(), before);
+        assert_eq!(forward.get(&7), Some(&15));
+        assert_eq!(forward.insert(7, 14), Some(15));
+        assert_eq!(forward.root_hash(), before);
+        assert_eq!(forward.len(), 500);
+        assert_eq!(MerkleTree::<i32>::new().root_hash(), EMPTY);
+    }
+
+    #[test]
+    fn test_proof() {
+        let mut tree = MerkleTree::new();
+        for key in 0..100 {
+            tree.insert(key, key.to_string());
+        }
+        for key in 0..100 {
+            let proof = tree.prove(&key).unwrap();
+            assert!(proof.verify(&key, &key.to_string(), tree.root_hash()));
+            assert!(!proof.verify(&key, &"forged".to_string(), tree.root_hash()));
+            assert!(!proof.verify(&(key + 1), &key.to_string(), tree.root_hash()));
+        }
+        assert!(tree.prove(&100).is_none());
+
+        // a proof is bound to the contents it was made for
+        let proof = tree.prove(&5).unwrap();
+        tree.update(&6, |data| data.push('!'));
+        assert!(!proof.verify(&5, &"5".to_string(), tree.root_hash()));
+        assert!(tree.prove(&5).unwrap().verify(&5, &"5".to_string(), tree.root_hash()));
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut left = MerkleTree::new();
+        for key in 0..10_000 {
+            left.insert(key, key);
+        }
+        let mut right: MerkleTree<i32, i32> = MerkleTree::new();
+        for key in (0..10_000).rev() {
+            right.insert(key, key);
+        }
+        assert!(left.diff(&right).is_empty());
+
+        right.remove(&10);
+        right.insert(20_000, 1);
+        right.update(&500, |data| *data = -1);
+        left.insert(-5, 0);
+        assert_eq!(left.diff(&right), vec![
+            Diff::Left(&-5, &0),
+            Diff::Left(&10, &10),
+            Diff::Changed(&500, &500, &-1),
+            Diff::Right(&20_000, &1),
+        ]);
+        assert_eq!(right.diff(&left).len(), 4);
+        assert_eq!(left.diff(&MerkleTree::new()).len(), left.len());
+    }
+
+    #[test]
+    fn test_diff_walks_only_different_subtrees() {
+        let mut left = MerkleTree::new();
+        for key in 0..10_000 {
+            left.insert(key, key);
+        }
+        let mut right: MerkleTree<i32, i32> = MerkleTree::new();
+        for key in 0..10_000 {
+            right.insert(key, key);
+        }
+        let height = left.stats().height;
+        let visited = |left: &MerkleTree<i32, i32>, right: &MerkleTree<i32, i32>| {
+            let mut differ = Differ::<i32, i32, Fnv>::new();
+            differ.diff(View::of(&left.root), View::of(&right.root));
+            (differ.found.len(), differ.visited)
+        };
+
+        // the key of the root splits the other tree along a single path
+        let root = left.root.as_ref().unwrap().key;
+        right.remove(&root);
+        let (found, walked) = visited(&left, &right);
+        assert_eq!(found, 1);
+        assert!(walked <= 2 * height, "{} nodes visited for a height of {}", walked, height);
+        assert_eq!(left.diff(&right), vec![Diff::Left(&root, &root)]);
+        assert_eq!(right.diff(&left), vec![Diff::Right(&root, &root)]);
+
+        // a key under the root as well
+        right.update(&1234, |data| *data = 0);
+        right.insert(-1, -1);
+        let (found, walked) = visited(&left, &right);
+        assert_eq!(found, 3);
+        assert!(walked <= 6 * height, "{} nodes visited for a height of {}", walked, height);
+    }
+
+    #[test]
+    fn test_random_diff() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..50 {
+            let (mut left, mut right) = (MerkleTree::new(), MerkleTree::new());
+            let (mut expected_left, mut expected_right) = (BTreeMap::new(), BTreeMap::new());
+            for _ in 0..300 {
+                let (key, data) = (random.next_u64() % 200, random.next_u64() % 3);
+                match random.next_u64() % 4 {
+                    0 => {
+                        left.insert(key, data);
+                        expected_left.insert(key, data);
+                    }
+                    1 => {
+                        right.insert(key, data);
+                        expected_right.insert(key, data);
+                    }
+                    _ => {
+                        left.insert(key, data);
+                        right.insert(key, data);
+                        expected_left.insert(key, data);
+                        expected_right.insert(key, data);
+                    }
+                }
+            }
+            let mut expected = vec![];
+            for key in expected_left.keys().chain(expected_right.keys()).collect::<std::collections::BTreeSet<_>>() {
+                match (expected_left.get_key_value(key), expected_right.get_key_value(key)) {
+                    (Some((key, l)), Some((_, r))) if l != r => expected.push(Diff::Changed(key, l, r)),
+                    (Some((key, l)), None) => expected.push(Diff::Left(key, l)),
+                    (None, Some((key, r))) => expected.push(Diff::Right(key, r)),
+                    _ => {}
+                }
+            }
+            assert_eq!(left.diff(&right), expected);
+        }
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let mut tree = MerkleTree::new();
+        let mut expected = BTreeMap::new();
+        for i in 0..5000 {
+            let key = random.next_u64() % 300;
+            if random.next_u64() % 5 < 2 {
+                assert_eq!(tree.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key, i), expected.insert(key, i));
+            }
+            if i % 500 == 0 {
+                assert!(tree.validate().is_ok());
+            }
+        }
+        assert!(tree.iter().eq(expected.iter()));
+        assert_eq!(tree.len(), expected.len());
+
+        // the same entries inserted in another order give the same tree
+        let mut rebuilt = MerkleTree::new();
+        for (&key, &data) in expected.iter().rev() {
+            rebuilt.insert(key, data);
+        }
+        assert_eq!(rebuilt.root_hash(), tree.root_hash());
+        let stats = tree.stats();
+        assert!(stats.height < 4 * stats.optimal_height());
+    }
+}
@@ -0,0 +1,168 @@
+//!
+//! a multiset, which keeps duplicates by counting them
+//!
+//! The trees themselves keep their keys unique: `tradition::Node::add` panics on a duplicate,
+//! `RsNode::add` and `Rbt::insert` drop it and `Avl::insert` puts it into the left subtree, where
+//! nothing finds it again. A `Multiset` instead stores every distinct element once, with its
+//! multiplicity as the data of an ordered map. Any tree with data is one, `RsNode` included, so
+//! `Multiset<T, RsNode<T, usize>>` counts in an unbalanced tree. `tradition::Node` has no data and
+//! stays a set.
+//!
+
+use core::marker::PhantomData;
+use crate::avl::AvlTree;
+use crate::map::OrderedMap;
+
+/// A multiset over any ordered map from the elements to their multiplicity, an `AvlTree` by
+/// default. A multiplicity stored in the map is never zero.
+pub struct Multiset<T, M: OrderedMap<T, usize> = AvlTree<T, usize>> {
+    counts: M,
+    len: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T, M: OrderedMap<T, usize>> Default for Multiset<T, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, M: OrderedMap<T, usize>> Multiset<T, M> {
+    pub fn new() -> Multiset<T, M> {
+        Multiset { counts: M::default(), len: 0, marker: PhantomData }
+    }
+
+    /// The number of elements, duplicates included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds one copy of `value`, returns its multiplicity afterwards.
+    pub fn insert(&mut self, value: T) -> usize {
+        let count = self.counts.get_or_insert_with(value, || 0);
+        *count += 1;
+        self.len += 1;
+        *count
+    }
+
+    pub fn count(&self, value: &T) -> usize {
+        self.counts.get(value).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.counts.get(value).is_some()
+    }
+
+    /// Removes one copy of `value`, returns `false` if there was none.
+    pub fn remove_one(&mut self, value: &T) -> bool {
+        match self.counts.get_mut(value) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.counts.remove(value);
+            }
+            None => return false,
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Removes every copy of `value`, returns how many there were.
+    pub fn remove_all(&mut self, value: &T) -> usize {
+        let count = self.counts.remove(value).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    /// Visits the elements in order, an element is repeated as many times as it was inserted.
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter { counts: self.counts.iter(), current: None }
+    }
+
+    /// Visits the distinct elements in order together with their multiplicity.
+    pub fn counts(&self) -> M::Iter<'_> {
+        self.counts.iter()
+    }
+}
+
+impl<T, M: OrderedMap<T, usize>> Extend<T> for Multiset<T, M> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<T, M: OrderedMap<T, usize>> FromIterator<T> for Multiset<T, M> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut multiset = Multiset::new();
+        multiset.extend(iter);
+        multiset
+    }
+}
+
+pub struct Iter<'a, T: 'a, M: OrderedMap<T, usize> + 'a> {
+    counts: M::Iter<'a>,
+    /// the element being repeated and how many copies are still to come
+    current: Option<(&'a T, usize)>,
+}
+
+impl<'a, T: 'a, M: OrderedMap<T, usize> + 'a> Iterator for Iter<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match &mut self.current {
+                Some((value, remaining)) if *remaining > 0 => {
+                    *remaining -= 1;
+                    return Some(*value);
+                }
+                _ => self.current = Some(self.counts.next().map(|(value, &count)| (value, count))?),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compare::Reverse;
+    use crate::rbt::RbTree;
+    use crate::testing::check_multiset;
+    use super::*;
+
+    #[test]
+    fn test_multiset() {
+        let mut multiset: Multiset<i32> = [3, 1, 3, 2, 3, 1].into_iter().collect();
+        assert_eq!(multiset.len(), 6);
+        assert_eq!(multiset.count(&3), 3);
+        assert_eq!(multiset.count(&4), 0);
+        assert_eq!(multiset.iter().copied().collect::<Vec<_>>(), vec![1, 1, 2, 3, 3, 3]);
+
+        assert!(multiset.remove_one(&1));
+        assert_eq!(multiset.count(&1), 1);
+        assert!(multiset.remove_one(&1));
+        assert!(!multiset.contains(&1));
+        assert!(!multiset.remove_one(&1));
+
+        assert_eq!(multiset.remove_all(&3), 3);
+        assert_eq!(multiset.remove_all(&3), 0);
+        assert_eq!(multiset.iter().copied().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(multiset.len(), 1);
+    }
+
+    #[test]
+    fn test_multiset_against_btree_map() {
+        check_multiset::<AvlTree<i32, usize>>();
+        check_multiset::<RbTree<i32, usize>>();
+    }
+
+    #[test]
+    fn test_reverse_order() {
+        let multiset: Multiset<i32, RbTree<i32, usize, Reverse>> = [3, 1, 3, 2].into_iter().collect();
+        assert_eq!(multiset.iter().copied().collect::<Vec<_>>(), vec![3, 3, 2, 1]);
+        assert_eq!(multiset.count(&3), 2);
+    }
+}
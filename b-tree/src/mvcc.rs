@@ -0,0 +1,425 @@
+//!
+//! multi-version concurrency control over an ordered map
+//!
+//! https://en.wikipedia.org/wiki/Multiversion_concurrency_control
+//!
+//! Every key maps to its versions, each stamped with the timestamp of the commit that wrote it, a
+//! removal writes a version without a value. A `Snapshot` reads as of the timestamp it was taken
+//! at and never sees a later commit, while writers go on.
+//!
+//! A commit writes all its versions under the write lock of the map and only then publishes its
+//! timestamp, so a snapshot sees either all of a commit or nothing of it. The snapshots alive are
+//! registered by timestamp: `gc` keeps, for every key, the versions a live snapshot or a future
+//! reader may still need and drops the older ones.
+//!
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Mutex, RwLock};
+use crate::avl::AvlTree;
+use crate::map::OrderedMap;
+
+/// The versions of a key, oldest first, `None` for a removal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Versions<V>(Vec<(u64, Option<V>)>);
+
+impl<V> Default for Versions<V> {
+    fn default() -> Self {
+        Versions(vec![])
+    }
+}
+
+impl<V> Versions<V> {
+    /// The value as of `timestamp`, `None` if the key was absent then.
+    pub fn at(&self, timestamp: u64) -> Option<&V> {
+        // the versions up to `timestamp` come first
+        let visible = self.0.partition_point(|(written, _)| *written <= timestamp);
+        visible.checked_sub(1).and_then(|i| self.0[i].1.as_ref())
+    }
+
+    pub fn latest(&self) -> Option<&V> {
+        self.0.last().and_then(|(_, value)| value.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Drops the versions no reader at `horizon` or later can see: the versions older than the
+    /// last one up to `horizon`, and that one too if it is a removal.
+    fn prune(&mut self, horizon: u64) -> usize {
+        let visible = self.0.partition_point(|(written, _)| *written <= horizon);
+        let mut drop = visible.saturating_sub(1);
+        if visible > 0 && self.0[visible - 1].1.is_none() {
+            drop = visible;
+        }
+        self.0.drain(..drop);
+        drop
+    }
+}
+
+/// A versioned map, all operations take `&self` and can be called from any number of threads.
+///
+/// The versions are kept in the ordered map `M`, an `AvlTree` by default.
+pub struct Mvcc<K, V, M = AvlTree<K, Versions<V>>> {
+    map: RwLock<M>,
+    /// the timestamp of the last published commit
+    clock: AtomicU64,
+    /// how many live snapshots read at each timestamp
+    snapshots: Mutex<BTreeMap<u64, usize>>,
+    entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, M: OrderedMap<K, Versions<V>>> Default for Mvcc<K, V, M> {
+    fn default() -> Self {
+        Mvcc {
+            map: RwLock::new(M::default()),
+            clock: AtomicU64::new(0),
+            snapshots: Mutex::new(BTreeMap::new()),
+            entries: PhantomData,
+        }
+    }
+}
+
+impl<K: Ord, V> Mvcc<K, V> {
+    pub fn new() -> Mvcc<K, V> {
+        Mvcc::default()
+    }
+}
+
+impl<K, V, M: OrderedMap<K, Versions<V>>> Mvcc<K, V, M> {
+    /// The timestamp of the last commit, a snapshot taken now reads at it.
+    pub fn timestamp(&self) -> u64 {
+        self.clock.load(AtomicOrdering::Acquire)
+    }
+
+    /// Writes `value` to `key`, returns the timestamp of the commit.
+    pub fn insert(&self, key: K, value: V) -> u64 {
+        self.commit([(key, Some(value))])
+    }
+
+    /// Removes `key`, returns the timestamp of the commit.
+    pub fn remove(&self, key: K) -> u64 {
+        self.commit([(key, None)])
+    }
+
+    /// Writes all of `writes` in a single commit, `None` removes a key. Returns the timestamp of
+    /// the commit, no snapshot sees a part of it only.
+    pub fn commit<I: IntoIterator<Item = (K, Option<V>)>>(&self, writes: I) -> u64 {
+        let mut map = self.map.write().unwrap();
+        // only the writer holding the lock moves the clock
+        let timestamp = self.clock.load(AtomicOrdering::Relaxed) + 1;
+        for (key, value) in writes {
+            if value.is_none() && map.get(&key).is_none_or(|versions| versions.latest().is_none()) {
+                // removing an absent key, or removing it twice in a commit
+                continue;
+            }
+            let versions = &mut map.get_or_insert_with(key, Versions::default).0;
+            match versions.last_mut() {
+                // the same key written twice in a commit, the last write wins
+                Some((written, previous)) if *written == timestamp => *previous = value,
+                _ => versions.push((timestamp, value)),
+            }
+        }
+        self.clock.store(timestamp, AtomicOrdering::Release);
+        timestamp
+    }
+
+    /// A consistent view of the map as of now, kept alive until the handle is dropped.
+    pub fn snapshot(&self) -> Snapshot<'_, K, V, M> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        // read under the lock, `gc` can't pick a later horizon before the snapshot is registered
+        let timestamp = self.timestamp();
+        *snapshots.entry(timestamp).or_insert(0) += 1;
+        Snapshot { store: self, timestamp }
+    }
+
+    /// The timestamp of the oldest live snapshot, `None` if there is none.
+    pub fn oldest_snapshot(&self) -> Option<u64> {
+        self.snapshots.lock().unwrap().keys().next().copied()
+    }
+
+    /// Drops the versions older than the oldest live snapshot, or than now if there is none, that
+    /// nobody can read anymore. Returns the number of versions dropped.
+    pub fn gc(&self) -> usize
+    where
+        K: Clone,
+    {
+        let horizon = {
+            let snapshots = self.snapshots.lock().unwrap();
+            snapshots.keys().next().copied().unwrap_or_else(|| self.timestamp())
+        };
+        let mut map = self.map.write().unwrap();
+        let keys: Vec<K> = map.iter().map(|(key, _)| key.clone()).collect();
+        let mut dropped = 0;
+        for key in keys {
+            let versions = map.get_mut(&key).expect("the key was just listed");
+            dropped += versions.prune(horizon);
+            if versions.is_empty() {
+                map.remove(&key);
+            }
+        }
+        dropped
+    }
+
+    /// The number of versions stored for all keys.
+    pub fn versions(&self) -> usize {
+        self.map.read().unwrap().iter().map(|(_, versions)| versions.len()).sum()
+    }
+
+    /// The latest value of `key`.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.map.read().unwrap().get(key).and_then(Versions::latest).cloned()
+    }
+
+    /// Checks that the versions of every key are in the order of their timestamps, none of them
+    /// written after the clock.
+    pub fn validate(&self) -> Result<(), String> {
+        let map = self.map.read().unwrap();
+        let now = self.timestamp();
+        for (i, (_, versions)) in map.iter().enumerate() {
+            if versions.is_empty() {
+                return Err(format!("the key at {} has no version", i));
+            }
+            if versions.0.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                return Err(format!("the versions of the key at {} are out of order", i));
+            }
+            if versions.0.last().is_some_and(|(written, _)| *written > now) {
+                return Err(format!("the key at {} has a version after the clock {}", i, now));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A read-only view of an `Mvcc` as of a timestamp.
+pub struct Snapshot<'a, K, V, M: OrderedMap<K, Versions<V>>> {
+    store: &'a Mvcc<K, V, M>,
+    timestamp: u64,
+}
+
+impl<K, V, M: OrderedMap<K, Versions<V>>> Snapshot<'_, K, V, M> {
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let map = self.store.map.read().unwrap();
+        map.get(key).and_then(|versions| versions.at(self.timestamp)).cloned()
+    }
+
+    /// All the entries visible in the snapshot, in order.
+    pub fn entries(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let map = self.store.map.read().unwrap();
+        map.iter()
+            .filter_map(|(key, versions)| versions.at(self.timestamp).map(|value| (key.clone(), value.clone())))
+            .collect()
+    }
+}
+
+impl<K, V, M: OrderedMap<K, Versions<V>>> Drop for Snapshot<'_, K, V, M> {
+    fn drop(&mut self) {
+        let mut snapshots = self.store.snapshots.lock().unwrap();
+        let count = snapshots.get_mut(&self.timestamp).expect("the snapshot is registered");
+        *count -= 1;
+        if *count == 0 {
+            snapshots.remove(&self.timestamp);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use crate::rbt::RbTree;
+    use crate::testing::Random;
+    use super::*;
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let store = Mvcc::new();
+        assert_eq!(store.insert("a", 1), 1);
+        store.insert("b", 2);
+        let before = store.snapshot();
+        assert_eq!(before.timestamp(), 2);
+
+        store.insert("a", 10);
+        store.remove("b");
+        store.insert("c", 3);
+        assert_eq!((before.get(&"a"), before.get(&"b"), before.get(&"c")), (Some(1), Some(2), None));
+        assert_eq!(before.entries(), vec![("a", 1), ("b", 2)]);
+        assert_eq!((store.get(&"a"), store.get(&"b"), store.get(&"c")), (Some(10), None, Some(3)));
+        assert_eq!(store.snapshot().entries(), vec![("a", 10), ("c", 3)]);
+        assert!(store.validate().is_ok());
+    }
+
+    #[test]
+    fn test_atomic_commit() {
+        let store: Mvcc<&str, i32> = Mvcc::new();
+        store.commit([("x", Some(1)), ("y", Some(1))]);
+        let snapshot = store.snapshot();
+        let timestamp = store.commit([("x", Some(2)), ("y", Some(2)), ("x", Some(3)), ("z", None)]);
+        assert_eq!(timestamp, 2);
+        assert_eq!(snapshot.entries(), vec![("x", 1), ("y", 1)]);
+        // the last write of a key wins, removing an absent key writes nothing
+        assert_eq!(store.snapshot().entries(), vec![("x", 3), ("y", 2)]);
+        assert_eq!(store.versions(), 4);
+    }
+
+    #[test]
+    fn test_gc() {
+        let store = Mvcc::new();
+        for value in 0..5 {
+            store.insert(1, value);
+            store.insert(2, value);
+        }
+        store.remove(2);
+        let snapshot = store.snapshot();
+        store.insert(1, 5);
+        assert_eq!(store.versions(), 12);
+        assert_eq!(store.oldest_snapshot(), Some(11));
+
+        // the snapshot still needs the last version of 1 before it, 2 is gone for everyone
+        assert_eq!(store.gc(), 10);
+        assert_eq!(snapshot.entries(), vec![(1, 4)]);
+        assert_eq!(store.snapshot().entries(), vec![(1, 5)]);
+        assert_eq!(store.versions(), 2);
+
+        drop(snapshot);
+        assert_eq!(store.oldest_snapshot(), None);
+        assert_eq!(store.gc(), 1);
+        assert_eq!(store.versions(), 1);
+        assert_eq!(store.get(&1), Some(5));
+        assert!(store.validate().is_ok());
+    }
+
+    #[test]
+    fn test_over_rbt() {
+        let store: Mvcc<i32, i32, RbTree<i32, Versions<i32>>> = Mvcc::default();
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let mut history = vec![BTreeMap::new()];
+        for _ in 0..2000 {
+            let mut state = history.last().unwrap().clone();
+            let key = (random.next_u64() % 100) as i32;
+            if random.next_u64().is_multiple_of(3) {
+                store.remove(key);
+                state.remove(&key);
+            } else {
+                let value = random.next_u64() as i32;
+                store.insert(key, value);
+                state.insert(key, value);
+            }
+            history.push(state);
+        }
+        let snapshots: Vec<_> = (0..5).map(|_| store.snapshot()).collect();
+        store.gc();
+        assert!(store.validate().is_ok());
+        for snapshot in &snapshots {
+            let expected = &history[snapshot.timestamp() as usize];
+            assert!(snapshot.entries().into_iter().eq(expected.clone()));
+        }
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Mvcc<String, Vec<u8>>>();
+    }
+
+    /// Every writer moves money between its own accounts, one commit per transfer, so the total
+    /// never changes. Readers check it in every snapshot and read every snapshot twice while the
+    /// writers and the garbage collector go on.
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        const WRITERS: u64 = 4;
+        const ACCOUNTS: u64 = 8;
+        const BALANCE: u64 = 1000;
+
+        let store = Arc::new(Mvcc::new());
+        store.commit((0..WRITERS * ACCOUNTS).map(|account| (account, Some(BALANCE))));
+        let total = WRITERS * ACCOUNTS * BALANCE;
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|id| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    let mut random = Random(id * 7919 + 17);
+                    for _ in 0..2000 {
+                        let from = id * ACCOUNTS + random.next_u64() % ACCOUNTS;
+                        let to = id * ACCOUNTS + random.next_u64() % ACCOUNTS;
+                        // nobody else writes these accounts, the balances can't change meanwhile
+                        let (a, b) = (store.get(&from).unwrap(), store.get(&to).unwrap());
+                        let amount = random.next_u64() % (a + 1);
+                        if from != to {
+                            store.commit([(from, Some(a - amount)), (to, Some(b + amount))]);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut snapshots = 0;
+                    while !done.load(AtomicOrdering::Relaxed) || snapshots == 0 {
+                        let snapshot = store.snapshot();
+                        let entries = snapshot.entries();
+                        assert_eq!(entries.len() as u64, WRITERS * ACCOUNTS);
+                        assert_eq!(entries.iter().map(|(_, balance)| balance).sum::<u64>(), total);
+                        thread::yield_now();
+                        assert_eq!(snapshot.entries(), entries);
+                        snapshots += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let collector = {
+            let store = store.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(AtomicOrdering::Relaxed) {
+                    store.gc();
+                    thread::yield_now();
+                }
+            })
+        };
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, AtomicOrdering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        collector.join().unwrap();
+
+        assert!(store.validate().is_ok());
+        store.gc();
+        assert_eq!(store.oldest_snapshot(), None);
+        // one version left for every account
+        assert_eq!(store.versions() as u64, WRITERS * ACCOUNTS);
+        assert_eq!(store.snapshot().entries().iter().map(|(_, balance)| balance).sum::<u64>(), total);
+    }
+}
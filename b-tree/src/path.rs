@@ -0,0 +1,196 @@
+//!
+//! a path from a node down to one of its descendants
+//!
+//! Entries and cursors remember the way to the node they found, so they don't have to compare keys
+//! again. Rotations move nodes around, `rotate` keeps such a path pointing at the same node.
+//!
+//! The in-order navigation shared by the trees lives here as well.
+//!
+
+use alloc::collections::VecDeque;
+use alloc::{vec, vec::Vec};
+
+/// `true` means going to the left child, the front is the step taken from the top node.
+pub(crate) type Path = VecDeque<bool>;
+
+/// Updates `path`, which starts at the node being rotated, so that it leads to the same node after
+/// the rotation. Rotating left (`left == true`) moves the right child up and the rotated node down
+/// to the left, rotating right is the mirror image.
+pub(crate) fn rotate(path: &mut Path, left: bool) {
+    match path.front() {
+        // the rotated node and everything below its other child go one level down
+        None => path.push_front(left),
+        Some(&step) if step == left => path.push_front(left),
+        // the child moving up
+        Some(_) => {
+            path.pop_front();
+            // the inner grandchild changes its parent from the child to the rotated node
+            if path.front() == Some(&left) {
+                path.pop_front();
+                path.push_front(!left);
+                path.push_front(left);
+            }
+        }
+    }
+}
+
+/// Same as `rotate` for a rotation of the left (`child == true`) or the right child of the node
+/// `path` starts at.
+pub(crate) fn rotate_child(path: &mut Path, child: bool, left: bool) {
+    if path.front() == Some(&child) {
+        path.pop_front();
+        rotate(path, left);
+        path.push_front(child);
+    }
+}
+
+/// A binary tree node the navigation below can walk through.
+pub(crate) trait Descend {
+    /// The left (`left == true`) or the right child, `None` if it is empty.
+    fn descend(&self, left: bool) -> Option<&Self>;
+}
+
+pub(crate) fn follow<'a, N: Descend>(root: &'a N, path: &Path) -> &'a N {
+    path.iter().fold(root, |node, &left| node.descend(left).expect("the path leads to a node"))
+}
+
+/// Extends `path`, which leads to `node`, to the leftmost (`left == true`) or the rightmost node
+/// below `node`.
+pub(crate) fn extend_to_end<N: Descend>(node: &N, path: &mut Path, left: bool) {
+    let mut node = node;
+    while let Some(child) = node.descend(left) {
+        path.push_back(left);
+        node = child;
+    }
+}
+
+/// Moves `path` to the in-order successor (`forward == true`) or predecessor of the node it leads
+/// to. Returns `false` if there is no such node, `path` is empty then.
+pub(crate) fn step<N: Descend>(root: &N, path: &mut Path, forward: bool) -> bool {
+    let node = follow(root, path);
+    if let Some(child) = node.descend(!forward) {
+        path.push_back(!forward);
+        extend_to_end(child, path, forward);
+        return true;
+    }
+
+    // climb up until we come from the child on the other side
+    while let Some(left) = path.pop_back() {
+        if left == forward {
+            return true;
+        }
+    }
+    false
+}
+
+/// Moves a cursor position, `None` is the ghost position between the last and the first node:
+/// stepping forward from it goes to the first node, backward to the last one.
+pub(crate) fn move_cursor<N: Descend>(root: Option<&N>, position: Option<Path>, forward: bool) -> Option<Path> {
+    let root = root?;
+    match position {
+        None => {
+            let mut path = Path::new();
+            extend_to_end(root, &mut path, forward);
+            Some(path)
+        }
+        Some(mut path) => step(root, &mut path, forward).then_some(path),
+    }
+}
+
+/// The path to the empty slot right after the node `path` leads to, a new node attached there is
+/// its in-order successor.
+pub(crate) fn slot_after<N: Descend>(root: &N, path: &Path) -> Path {
+    let mut slot = path.clone();
+    let node = follow(root, path);
+    if let Some(right) = node.descend(false) {
+        slot.push_back(false);
+        extend_to_end(right, &mut slot, true);
+        slot.push_back(true);
+    } else {
+        slot.push_back(false);
+    }
+    slot
+}
+
+/// The path to the empty slot before the first node.
+pub(crate) fn slot_first<N: Descend>(root: Option<&N>) -> Path {
+    let mut slot = Path::new();
+    if let Some(root) = root {
+        extend_to_end(root, &mut slot, true);
+        slot.push_back(true);
+    }
+    slot
+}
+
+/// Visits the nodes below a root in order, keeping the nodes whose left subtree is being visited on
+/// a stack.
+pub(crate) struct InOrder<'a, N> {
+    stack: Vec<&'a N>,
+}
+
+impl<'a, N: Descend> InOrder<'a, N> {
+    pub(crate) fn new(root: Option<&'a N>) -> InOrder<'a, N> {
+        let mut in_order = InOrder { stack: vec![] };
+        in_order.push_left(root);
+        in_order
+    }
+
+    fn push_left(&mut self, mut node: Option<&'a N>) {
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = n.descend(true);
+        }
+    }
+}
+
+impl<'a, N: Descend> Iterator for InOrder<'a, N> {
+    type Item = &'a N;
+
+    fn next(&mut self) -> Option<&'a N> {
+        let node = self.stack.pop()?;
+        self.push_left(node.descend(false));
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn path(steps: &[bool]) -> Path {
+        steps.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_rotate_left() {
+        //      a                c
+        //     / \              / \
+        //    b   c     =>     a   e
+        //       / \          / \
+        //      d   e        b   d
+        let cases = [
+            (path(&[]), path(&[true])),
+            (path(&[true]), path(&[true, true])),
+            (path(&[false]), path(&[])),
+            (path(&[false, true]), path(&[true, false])),
+            (path(&[false, false]), path(&[false])),
+            (path(&[false, true, true]), path(&[true, false, true])),
+        ];
+
+        for (mut before, after) in cases {
+            rotate(&mut before, true);
+            assert_eq!(before, after);
+        }
+    }
+
+    #[test]
+    fn test_rotate_right() {
+        let mut before = path(&[true, false, false]);
+        rotate(&mut before, false);
+        assert_eq!(before, path(&[false, true, false]));
+
+        let mut before = path(&[]);
+        rotate(&mut before, false);
+        assert_eq!(before, path(&[false]));
+    }
+}
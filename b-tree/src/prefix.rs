@@ -0,0 +1,705 @@
+//!
+//! a B+ tree for string keys with prefix compression and suffix truncation
+//!
+//! https://en.wikipedia.org/wiki/B%2B_tree, and "Prefix B-Trees" by Bayer and Unterauer
+//!
+//! Long hierarchical keys such as paths and URLs share most of their bytes with their neighbours.
+//! Two techniques keep them small:
+//! - every node stores the prefix shared by all its keys once, and only the rest of each key. The
+//!   keys of a node are sorted, so the shared prefix is the one of its first and last key, it is
+//!   recomputed whenever a split, a merge or a borrow changes the keys of a node;
+//! - a separator only has to route a search between two leaves, so a leaf split pushes up the
+//!   shortest prefix of the first key on the right which is still greater than the last key on the
+//!   left, rather than the whole key.
+//!
+//! A separator may stop in the middle of a UTF-8 character, the keys are stored as bytes and only
+//! the whole keys of the leaves are turned back into strings.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::{min, Ordering};
+use core::fmt::{self, Display};
+use core::mem;
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
+
+const MIN_ORDER: usize = 4;
+const DEFAULT_ORDER: usize = 32;
+
+/// The length of the longest common prefix of `a` and `b`.
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// A separator between the leaves ending with `left` and starting with `right`, the shortest
+/// prefix of `right` greater than `left` if `truncate`.
+fn separator(left: &[u8], right: &[u8], truncate: bool) -> Vec<u8> {
+    if truncate {
+        right[..common_prefix(left, right) + 1].to_vec()
+    } else {
+        right.to_vec()
+    }
+}
+
+/// The sorted keys of a node, the bytes they all start with are kept once.
+#[derive(Debug, Default)]
+struct Keys {
+    prefix: Vec<u8>,
+    suffixes: Vec<Box<[u8]>>,
+}
+
+impl Keys {
+    fn len(&self) -> usize {
+        self.suffixes.len()
+    }
+
+    fn key(&self, i: usize) -> Vec<u8> {
+        [&self.prefix[..], &self.suffixes[i]].concat()
+    }
+
+    fn first(&self) -> Vec<u8> {
+        self.key(0)
+    }
+
+    fn last(&self) -> Vec<u8> {
+        self.key(self.len() - 1)
+    }
+
+    /// Same as `binary_search` on the whole keys, `key` is compared with the prefix only once.
+    fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        let n = min(self.prefix.len(), key.len());
+        match key[..n].cmp(&self.prefix[..n]) {
+            Ordering::Less => Err(0),
+            Ordering::Greater => Err(self.len()),
+            // `key` is a proper prefix of the prefix, before all the keys
+            Ordering::Equal if key.len() < self.prefix.len() => Err(0),
+            Ordering::Equal => self.suffixes.binary_search_by(|suffix| (**suffix).cmp(&key[n..])),
+        }
+    }
+
+    /// Inserts `key` at `i`, shortening the prefix if `key` doesn't start with it.
+    fn insert(&mut self, i: usize, key: &[u8]) {
+        if !key.starts_with(&self.prefix) {
+            let moved = self.prefix.split_off(common_prefix(&self.prefix, key));
+            for suffix in &mut self.suffixes {
+                *suffix = [&moved[..], suffix].concat().into_boxed_slice();
+            }
+        }
+        self.suffixes.insert(i, key[self.prefix.len()..].into());
+    }
+
+    fn push(&mut self, key: &[u8]) {
+        self.insert(self.len(), key);
+    }
+
+    fn remove(&mut self, i: usize) -> Vec<u8> {
+        let key = self.key(i);
+        self.suffixes.remove(i);
+        key
+    }
+
+    fn pop(&mut self) -> Vec<u8> {
+        self.remove(self.len() - 1)
+    }
+
+    /// Moves the keys from `at` on to new keys, with the same prefix.
+    fn split_off(&mut self, at: usize) -> Keys {
+        Keys { prefix: self.prefix.clone(), suffixes: self.suffixes.split_off(at) }
+    }
+
+    fn append(&mut self, other: Keys) {
+        for i in 0..other.len() {
+            self.push(&other.key(i));
+        }
+    }
+
+    /// Makes the prefix as long as the one shared by the first and the last key.
+    fn compress(&mut self) {
+        if self.suffixes.is_empty() {
+            return;
+        }
+        let (first, last) = (&self.suffixes[0], &self.suffixes[self.len() - 1]);
+        let extra = common_prefix(first, last);
+        if extra == 0 {
+            return;
+        }
+        self.prefix.extend_from_slice(&first[..extra]);
+        for suffix in &mut self.suffixes {
+            *suffix = suffix[extra..].into();
+        }
+        self.prefix.shrink_to_fit();
+    }
+
+    /// The bytes taken by the keys besides the node itself.
+    fn memory(&self) -> usize {
+        self.prefix.capacity()
+            + self.suffixes.capacity() * mem::size_of::<Box<[u8]>>()
+            + self.suffixes.iter().map(|suffix| suffix.len()).sum::<usize>()
+    }
+
+    fn label(&self) -> String {
+        let suffixes: Vec<String> = self.suffixes.iter().map(|suffix| String::from_utf8_lossy(suffix).into_owned()).collect();
+        if self.prefix.is_empty() {
+            format!("[{}]", suffixes.join(" "))
+        } else {
+            format!("[{}|{}]", String::from_utf8_lossy(&self.prefix), suffixes.join(" "))
+        }
+    }
+}
+
+/// The settings every node operation needs.
+#[derive(Clone, Copy)]
+struct Config {
+    order: usize,
+    compress: bool,
+}
+
+impl Config {
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+}
+
+/// An internal node has one child more than it has keys, the keys of `children[i]` are at least
+/// `keys[i - 1]` and less than `keys[i]`.
+enum Node<V> {
+    Leaf { keys: Keys, values: Vec<V> },
+    Internal { keys: Keys, children: Vec<Node<V>> },
+}
+
+/// The separator and the new right node of a node which split.
+type Split<V> = Option<(Vec<u8>, Node<V>)>;
+
+/// The child of an internal node `key` belongs to.
+fn child_index(keys: &Keys, key: &[u8]) -> usize {
+    match keys.search(key) {
+        Ok(i) => i + 1,
+        Err(i) => i,
+    }
+}
+
+/// The children `i` and `i + 1`.
+fn pair<V>(children: &mut [Node<V>], i: usize) -> (&mut Node<V>, &mut Node<V>) {
+    let (left, right) = children.split_at_mut(i + 1);
+    (&mut left[i], &mut right[0])
+}
+
+impl<V> Node<V> {
+    fn keys(&self) -> &Keys {
+        match self {
+            Node::Leaf { keys, .. } | Node::Internal { keys, .. } => keys,
+        }
+    }
+
+    fn keys_mut(&mut self) -> &mut Keys {
+        match self {
+            Node::Leaf { keys, .. } | Node::Internal { keys, .. } => keys,
+        }
+    }
+
+    fn compress(&mut self, config: Config) {
+        if config.compress {
+            self.keys_mut().compress();
+        }
+    }
+
+    /// Inserts below this node, returns the old value and, if this node had to split, the
+    /// separator and the new node on its right.
+    fn insert(&mut self, key: &[u8], value: V, config: Config) -> (Option<V>, Split<V>) {
+        match self {
+            Node::Leaf { keys, values } => match keys.search(key) {
+                Ok(i) => return (Some(mem::replace(&mut values[i], value)), None),
+                Err(i) => {
+                    keys.insert(i, key);
+                    values.insert(i, value);
+                }
+            },
+            Node::Internal { keys, children } => {
+                let i = child_index(keys, key);
+                let (old, split) = children[i].insert(key, value, config);
+                let Some((separator, right)) = split else { return (old, None) };
+                keys.insert(i, &separator);
+                children.insert(i + 1, right);
+            }
+        }
+        if self.keys().len() <= config.max_keys() {
+            return (None, None);
+        }
+        (None, Some(self.split(config)))
+    }
+
+    /// Moves the upper half of the keys to a new node on the right, returns the separator.
+    fn split(&mut self, config: Config) -> (Vec<u8>, Node<V>) {
+        let at = self.keys().len() / 2;
+        let (separator, mut right) = match self {
+            Node::Leaf { keys, values } => {
+                let right_keys = keys.split_off(at);
+                let separator = separator(&keys.last(), &right_keys.first(), config.compress);
+                (separator, Node::Leaf { keys: right_keys, values: values.split_off(at) })
+            }
+            Node::Internal { keys, children } => {
+                // the middle key moves up
+                let right_keys = keys.split_off(at + 1);
+                let separator = keys.pop();
+                (separator, Node::Internal { keys: right_keys, children: children.split_off(at + 1) })
+            }
+        };
+        self.compress(config);
+        right.compress(config);
+        (separator, right)
+    }
+
+    fn remove(&mut self, key: &[u8], config: Config) -> Option<V> {
+        match self {
+            Node::Leaf { keys, values } => {
+                let i = keys.search(key).ok()?;
+                keys.remove(i);
+                Some(values.remove(i))
+            }
+            Node::Internal { keys, children } => {
+                let i = child_index(keys, key);
+                let value = children[i].remove(key, config)?;
+                if children[i].keys().len() < config.min_keys() {
+                    self.fix(i, config);
+                }
+                Some(value)
+            }
+        }
+    }
+
+    /// Refills the child `i` of this internal node, which has a key too few, from a sibling or
+    /// merges it with one.
+    fn fix(&mut self, i: usize, config: Config) {
+        let Node::Internal { keys, children } = self else { unreachable!("only an internal node has children") };
+        let spare = |child: &Node<V>| child.keys().len() > config.min_keys();
+        if i > 0 && spare(&children[i - 1]) {
+            let (left, child) = pair(children, i - 1);
+            match (left, child) {
+                (Node::Leaf { keys: left_keys, values: left_values }, Node::Leaf { keys: child_keys, values: child_values }) => {
+                    child_keys.insert(0, &left_keys.pop());
+                    child_values.insert(0, left_values.pop().expect("the sibling has a key to spare"));
+                    keys.remove(i - 1);
+                    keys.insert(i - 1, &separator(&left_keys.last(), &child_keys.first(), config.compress));
+                }
+                (Node::Internal { keys: left_keys, children: left_children }, Node::Internal { keys: child_keys, children: child_children }) => {
+                    child_keys.insert(0, &keys.remove(i - 1));
+                    child_children.insert(0, left_children.pop().expect("the sibling has a child to spare"));
+                    keys.insert(i - 1, &left_keys.pop());
+                }
+                _ => unreachable!("all the leaves are at the same depth"),
+            }
+            children[i - 1].compress(config);
+            children[i].compress(config);
+        } else if i + 1 < children.len() && spare(&children[i + 1]) {
+            let (child, right) = pair(children, i);
+            match (child, right) {
+                (Node::Leaf { keys: child_keys, values: child_values }, Node::Leaf { keys: right_keys, values: right_values }) => {
+                    child_keys.push(&right_keys.remove(0));
+                    child_values.push(right_values.remove(0));
+                    keys.remove(i);
+                    keys.insert(i, &separator(&child_keys.last(), &right_keys.first(), config.compress));
+                }
+                (Node::Internal { keys: child_keys, children: child_children }, Node::Internal { keys: right_keys, children: right_children }) => {
+                    child_keys.push(&keys.remove(i));
+                    child_children.push(right_children.remove(0));
+                    keys.insert(i, &right_keys.remove(0));
+                }
+                _ => unreachable!("all the leaves are at the same depth"),
+            }
+            children[i].compress(config);
+            children[i + 1].compress(config);
+        } else {
+            // merge with the sibling on the right, or on the left for the last child
+            let i = if i + 1 < children.len() { i } else { i - 1 };
+            let separator = keys.remove(i);
+            let right = children.remove(i + 1);
+            match (&mut children[i], right) {
+                (Node::Leaf { keys: left_keys, values: left_values }, Node::Leaf { keys: right_keys, values: right_values }) => {
+                    left_keys.append(right_keys);
+                    left_values.extend(right_values);
+                }
+                (Node::Internal { keys: left_keys, children: left_children }, Node::Internal { keys: right_keys, children: right_children }) => {
+                    left_keys.push(&separator);
+                    left_keys.append(right_keys);
+                    left_children.extend(right_children);
+                }
+                _ => unreachable!("all the leaves are at the same depth"),
+            }
+            children[i].compress(config);
+        }
+        self.compress(config);
+    }
+}
+
+/// A B+ tree from strings to `V`, storing the keys of a node behind their common prefix.
+pub struct PrefixBTree<V> {
+    root: Node<V>,
+    config: Config,
+    len: usize,
+}
+
+impl<V> Default for PrefixBTree<V> {
+    fn default() -> Self {
+        PrefixBTree::new()
+    }
+}
+
+impl<V> PrefixBTree<V> {
+    pub fn new() -> PrefixBTree<V> {
+        PrefixBTree::with_order(DEFAULT_ORDER)
+    }
+
+    /// An empty tree whose nodes have at most `order` children.
+    pub fn with_order(order: usize) -> PrefixBTree<V> {
+        PrefixBTree::with_config(Config { order, compress: true })
+    }
+
+    /// An empty tree storing whole keys everywhere, to compare with.
+    pub fn uncompressed(order: usize) -> PrefixBTree<V> {
+        PrefixBTree::with_config(Config { order, compress: false })
+    }
+
+    fn with_config(config: Config) -> PrefixBTree<V> {
+        assert!(config.order >= MIN_ORDER, "the order must be at least {}", MIN_ORDER);
+        PrefixBTree { root: Node::Leaf { keys: Keys::default(), values: vec![] }, config, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        let key = key.as_bytes();
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf { keys, values } => return keys.search(key).ok().map(|i| &values[i]),
+                Node::Internal { keys, children } => node = &children[child_index(keys, key)],
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let key = key.as_bytes();
+        let mut node = &mut self.root;
+        loop {
+            match node {
+                Node::Leaf { keys, values } => return keys.search(key).ok().map(|i| &mut values[i]),
+                Node::Internal { keys, children } => node = &mut children[child_index(keys, key)],
+            }
+        }
+    }
+
+    /// Inserts `key` with `value`, returns the old value if `key` was present.
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
+        let (old, split) = self.root.insert(key.as_bytes(), value, self.config);
+        if let Some((separator, right)) = split {
+            let left = mem::replace(&mut self.root, Node::Leaf { keys: Keys::default(), values: vec![] });
+            let mut keys = Keys::default();
+            keys.push(&separator);
+            self.root = Node::Internal { keys, children: vec![left, right] };
+        }
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Removes `key`, returns its value if it was present.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let value = self.root.remove(key.as_bytes(), self.config)?;
+        self.len -= 1;
+        if let Node::Internal { keys, children } = &mut self.root {
+            if keys.len() == 0 {
+                self.root = children.pop().expect("the root has a single child");
+            }
+        }
+        Some(value)
+    }
+
+    /// Visits the keys and their values in order.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter { nodes: vec![(&self.root, 0)] }
+    }
+
+    /// Checks the order of the keys, the separators, the fill of the nodes and the depth of the
+    /// leaves.
+    pub fn validate(&self) -> Result<(), String> {
+        let (_, count) = self.validate_node(&self.root, true, None, None)?;
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, count));
+        }
+        Ok(())
+    }
+
+    /// Returns the depth of the leaves below `node` and the number of keys in them. The keys are
+    /// at least `low` and less than `high`.
+    fn validate_node(&self, node: &Node<V>, is_root: bool, low: Option<&[u8]>, high: Option<&[u8]>) -> Result<(usize, usize), String> {
+        let keys = node.keys();
+        let all: Vec<Vec<u8>> = (0..keys.len()).map(|i| keys.key(i)).collect();
+        if all.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("the keys of a node are out of order".to_string());
+        }
+        if all.iter().any(|key| low.is_some_and(|low| key.as_slice() < low) || high.is_some_and(|high| key.as_slice() >= high)) {
+            return Err("a key is out of the range of its separators".to_string());
+        }
+        if keys.len() > self.config.max_keys() || (!is_root && keys.len() < self.config.min_keys()) {
+            return Err(format!("a node has {} keys", keys.len()));
+        }
+        match node {
+            Node::Leaf { values, .. } => {
+                if values.len() != keys.len() {
+                    return Err(format!("a leaf has {} keys and {} values", keys.len(), values.len()));
+                }
+                Ok((0, keys.len()))
+            }
+            Node::Internal { children, .. } => {
+                if children.len() != keys.len() + 1 {
+                    return Err(format!("a node has {} keys and {} children", keys.len(), children.len()));
+                }
+                let mut depth = None;
+                let mut count = 0;
+                for (i, child) in children.iter().enumerate() {
+                    let low = if i == 0 { low } else { Some(all[i - 1].as_slice()) };
+                    let high = all.get(i).map(Vec::as_slice).or(high);
+                    let (child_depth, child_count) = self.validate_node(child, false, low, high)?;
+                    if depth.is_some_and(|depth| depth != child_depth) {
+                        return Err("the leaves are at different depths".to_string());
+                    }
+                    depth = Some(child_depth);
+                    count += child_count;
+                }
+                Ok((depth.unwrap_or(0) + 1, count))
+            }
+        }
+    }
+
+    /// The shape of the tree, the nodes are counted rather than the keys. The memory includes the
+    /// capacity of the vectors of every node and the bytes of the keys.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut nodes = vec![(&self.root, 0)];
+        while let Some((node, depth)) = nodes.pop() {
+            stats.memory += node.keys().memory();
+            match node {
+                Node::Internal { children, .. } => {
+                    stats.visit(depth, false);
+                    stats.memory += children.capacity() * mem::size_of::<Node<V>>();
+                    nodes.extend(children.iter().rev().map(|child| (child, depth + 1)));
+                }
+                Node::Leaf { values, .. } => {
+                    stats.visit(depth, true);
+                    stats.memory += values.capacity() * mem::size_of::<V>();
+                }
+            }
+        }
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// The shape of the tree, a node with a prefix is labeled `[prefix|suffix suffix]`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<V>(node: &Node<V>) -> Sketch {
+            let children = match node {
+                Node::Internal { children, .. } => children.iter().map(|child| Some(sketch(child))).collect(),
+                Node::Leaf { .. } => vec![],
+            };
+            Sketch::new(node.keys().label(), children)
+        }
+
+        if self.is_empty() {
+            return None;
+        }
+        Some(sketch(&self.root))
+    }
+}
+
+impl<V> Display for PrefixBTree<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+/// The nodes on the way down to the next key, each with the index of its next key or child.
+pub struct Iter<'a, V> {
+    nodes: Vec<(&'a Node<V>, usize)>,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (String, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &(node, i) = self.nodes.last()?;
+            match node {
+                Node::Leaf { keys, values } if i < keys.len() => {
+                    self.nodes.last_mut().expect("the leaf is on the stack").1 += 1;
+                    let key = String::from_utf8(keys.key(i)).expect("the leaves hold whole keys");
+                    return Some((key, &values[i]));
+                }
+                Node::Internal { children, .. } if i < children.len() => {
+                    self.nodes.last_mut().expect("the node is on the stack").1 += 1;
+                    self.nodes.push((&children[i], 0));
+                }
+                _ => {
+                    self.nodes.pop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    #[cfg(feature = "std")]
+    use std::time::Instant;
+    use crate::testing::Random;
+    use super::*;
+
+    fn url(i: u64) -> String {
+        format!("https://git.example.com/api/v3/repositories/{:05}/issues/{}/comments", i / 16, i % 16)
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut tree = PrefixBTree::with_order(4);
+        for (i, key) in ["usr/bin/ls", "usr/bin/cat", "usr/lib/libc.so", "etc/hosts", "usr/bin/cp", "var/log/syslog", "etc/passwd"].iter().enumerate() {
+            assert_eq!(tree.insert(key, i), None);
+            assert!(tree.validate().is_ok());
+        }
+        assert_eq!(tree.insert("usr/bin/cp", 10), Some(4));
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.get("usr/bin/cp"), Some(&10));
+        *tree.get_mut("etc/hosts").unwrap() += 1;
+        assert_eq!(tree.get("etc/hosts"), Some(&4));
+        assert_eq!((tree.get("usr/bin"), tree.get("usr/bin/lsof"), tree.get("")), (None, None, None));
+
+        assert_eq!(tree.remove("usr/bin/ls"), Some(0));
+        assert_eq!(tree.remove("usr/bin/ls"), None);
+        assert!(tree.validate().is_ok());
+        let keys: Vec<String> = tree.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["etc/hosts", "etc/passwd", "usr/bin/cat", "usr/bin/cp", "usr/lib/libc.so", "var/log/syslog"]);
+    }
+
+    #[test]
+    fn test_prefix_and_truncated_separators() {
+        let mut tree = PrefixBTree::with_order(4);
+        for key in ["home/alice/notes", "home/alice/todo", "home/bob/notes", "home/bob/todo"] {
+            tree.insert(key, ());
+        }
+        // the leaves keep "home/alice/" and "home/bob/" once, "home/b" is enough to separate them
+        let sketch = tree.sketch().unwrap();
+        assert_eq!(sketch.label, "[home/b]");
+        let leaves: Vec<&str> = sketch.children.iter().map(|child| child.as_ref().unwrap().label.as_str()).collect();
+        assert_eq!(leaves, ["[home/alice/|notes todo]", "[home/bob/|notes todo]"]);
+
+        let mut tree = PrefixBTree::uncompressed(4);
+        for key in ["home/alice/notes", "home/alice/todo", "home/bob/notes", "home/bob/todo"] {
+            tree.insert(key, ());
+        }
+        assert_eq!(tree.sketch().unwrap().label, "[home/bob/notes]");
+    }
+
+    #[test]
+    fn test_unicode_keys() {
+        // "é" and "ж" differ in their first byte, the separators cut characters in two
+        let mut tree = PrefixBTree::with_order(4);
+        let keys: Vec<String> = (0..200).map(|i| format!("{}{}", if i % 2 == 0 { "é" } else { "ж" }, i)).collect();
+        for key in &keys {
+            tree.insert(key, key.len());
+        }
+        assert!(tree.validate().is_ok());
+        let mut expected = keys.clone();
+        expected.sort();
+        assert!(tree.iter().map(|(key, _)| key).eq(expected));
+        assert!(keys.iter().all(|key| tree.get(key) == Some(&key.len())));
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        for (order, compress) in [(4, true), (5, true), (7, false), (32, true)] {
+            let mut tree = if compress { PrefixBTree::with_order(order) } else { PrefixBTree::uncompressed(order) };
+            let mut expected = BTreeMap::new();
+            let mut random = Random(0x2545_f491_4f6c_dd1d);
+            for i in 0..6000 {
+                let key = url(random.next_u64() % 800);
+                if random.next_u64().is_multiple_of(3) {
+                    assert_eq!(tree.remove(&key), expected.remove(&key));
+                } else {
+                    assert_eq!(tree.insert(&key, i), expected.insert(key, i));
+                }
+                if i % 500 == 0 {
+                    assert_eq!(tree.validate(), Ok(()), "order {}", order);
+                }
+            }
+            assert_eq!(tree.validate(), Ok(()));
+            assert!(tree.iter().map(|(key, &value)| (key, value)).eq(expected.clone()));
+            for key in expected.keys() {
+                tree.remove(key);
+            }
+            assert!(tree.is_empty());
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut compressed = PrefixBTree::new();
+        let mut uncompressed = PrefixBTree::uncompressed(DEFAULT_ORDER);
+        for i in 0..10_000 {
+            compressed.insert(&url(i), i);
+            uncompressed.insert(&url(i), i);
+        }
+        let (small, large) = (compressed.stats().memory, uncompressed.stats().memory);
+        // the keys differ in their tails as well, a third of the memory is saved
+        assert!(3 * small < 2 * large, "{} bytes compressed, {} uncompressed", small, large);
+        assert_eq!(compressed.stats().nodes, uncompressed.stats().nodes);
+    }
+
+    /// Compares the memory and the lookups of compressed and uncompressed nodes, run it with
+    /// `cargo test --release -- --ignored --nocapture bench_prefix_compression`.
+    #[cfg(feature = "std")]
+    #[test]
+    #[ignore]
+    fn bench_prefix_compression() {
+        const N: u64 = 200_000;
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        let keys: Vec<String> = (0..N).map(|_| url(random.next_u64() % (4 * N))).collect();
+        let average = keys.iter().map(String::len).sum::<usize>() / keys.len();
+        println!("{} random keys of {} bytes on average", N, average);
+        for order in [16, 32, 64, 128] {
+            let mut figures = vec![];
+            for compress in [false, true] {
+                let mut tree = if compress { PrefixBTree::with_order(order) } else { PrefixBTree::uncompressed(order) };
+                for (i, key) in keys.iter().enumerate() {
+                    tree.insert(key, i);
+                }
+                let start = Instant::now();
+                assert!(keys.iter().all(|key| tree.get(key).is_some()));
+                figures.push((tree.stats().memory, start.elapsed()));
+            }
+            let [(full, full_time), (small, small_time)] = figures[..] else { unreachable!() };
+            println!(
+                "order {:>3}: {:>10} bytes uncompressed, {:>10} compressed ({:.0}%), lookups {:?} vs {:?}",
+                order,
+                full,
+                small,
+                100.0 * small as f64 / full as f64,
+                full_time,
+                small_time
+            );
+        }
+    }
+}
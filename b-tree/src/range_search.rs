@@ -1,12 +1,22 @@
 //test range query in a binary tree
-struct RangeQueryTree {
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::mem;
+use crate::path::{Descend, InOrder};
+use crate::stats::{self, Stats};
+
+pub struct RangeQueryTree {
     val: i32,
     left: Option<Box<RangeQueryTree>>,
     right: Option<Box<RangeQueryTree>>,
 }
 
 impl RangeQueryTree {
-    fn new(new_val: i32, left: Option<RangeQueryTree>, right: Option<RangeQueryTree>) -> RangeQueryTree {
+    #[allow(clippy::manual_map)]
+    pub fn new(new_val: i32, left: Option<RangeQueryTree>, right: Option<RangeQueryTree>) -> RangeQueryTree {
         let left = match left {
             None => None,
             Some(data) => Some(Box::new(data))
@@ -23,42 +33,72 @@ impl RangeQueryTree {
         }
     }
 
+    /// Adds `new_val` as a leaf where a search for it ends, a value already there is left alone.
+    pub fn insert(&mut self, new_val: i32) {
+        let mut node = self;
+        loop {
+            let child = match new_val.cmp(&node.val) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => return,
+            };
+            match child {
+                Some(next) => node = next,
+                None => {
+                    *child = Some(Box::new(RangeQueryTree::new(new_val, None, None)));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Checks that the values are in order.
+    pub fn validate(&self) -> Result<(), String> {
+        let values: Vec<i32> = InOrder::new(Some(self)).map(|node| node.val).collect();
+        match values.windows(2).position(|pair| pair[0] >= pair[1]) {
+            Some(i) => Err(format!("the values at {} and {} are out of order", i, i + 1)),
+            None => Ok(()),
+        }
+    }
+
+    /// The values within `start..=end` in pre-order. The subtrees still to visit are kept on a stack,
+    /// so a degenerate tree doesn't overflow the call stack.
     pub fn range_query(&self, start: i32, end: i32) -> Vec<i32> {
         let mut v = vec![];
-        Self::inner_range_query(self, start, end, &mut v);
+        let mut nodes = vec![self];
+        while let Some(node) = nodes.pop() {
+            if start <= node.val && node.val <= end {
+                v.push(node.val);
+            }
+            // the right subtree goes first, so it is visited after the left one
+            if node.val < end {
+                nodes.extend(node.right.as_deref());
+            }
+            if node.val > start {
+                nodes.extend(node.left.as_deref());
+            }
+        }
         v
     }
 
-    fn inner_range_query(root: &RangeQueryTree, start: i32, end: i32, v: &mut Vec<i32>) {
-        if start <= root.val && root.val <= end {
-            v.push(root.val);
-            let left = match &root.left {
-                None => return,
-                Some(v) => v.as_ref(),
-            };
-            Self::inner_range_query(left, start, end, v);
-
-            let right = match &root.right {
-                None => return,
-                Some(v) => v.as_ref(),
-            };
-            Self::inner_range_query(right, start, end, v);
-        }
+    pub fn stats(&self) -> Stats {
+        stats::binary(Some(self), mem::size_of::<Self>(), |_| ())
+    }
+}
 
-        if root.val < start {
-            let right = match &root.right {
-                None => return,
-                Some(v) => v.as_ref(),
-            };
-            Self::inner_range_query(right, start, end, v);
-        }
+impl Descend for RangeQueryTree {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
 
-        if root.val > end {
-            let left = match &root.left {
-                None => return,
-                Some(v) => v.as_ref(),
-            };
-            Self::inner_range_query(left, start, end, v);
+/// Frees the nodes one by one instead of recursing once per level.
+impl Drop for RangeQueryTree {
+    fn drop(&mut self) {
+        let mut nodes: Vec<Box<RangeQueryTree>> = self.left.take().into_iter().chain(self.right.take()).collect();
+        while let Some(mut node) = nodes.pop() {
+            nodes.extend(node.left.take());
+            nodes.extend(node.right.take());
         }
     }
 }
@@ -98,4 +138,40 @@ pub mod tests {
         let vec = root.range_query(0, 100);
         assert_eq!(vec, vec![4, 2, 1, 3, 6, 5, 7]);
     }
+
+    #[test]
+    fn test_missing_left_child() {
+        //     2
+        //      \
+        //       3
+        let root = RangeQueryTree::new(2, None, Some(RangeQueryTree::new(3, None, None)));
+        assert_eq!(root.range_query(0, 10), vec![2, 3]);
+        assert_eq!(root.range_query(3, 3), vec![3]);
+    }
+
+    #[test]
+    fn test_degenerate() {
+        // a chain of a million nodes going right, as sorted insertions would make it
+        let mut root = RangeQueryTree::new(1_000_000, None, None);
+        for val in (0..1_000_000).rev() {
+            root = RangeQueryTree::new(val, None, Some(root));
+        }
+        assert_eq!(root.range_query(999_998, 2_000_000), vec![999_998, 999_999, 1_000_000]);
+        assert_eq!(root.range_query(0, 1_000_000).len(), 1_000_001);
+        assert_eq!(root.stats().height, 1_000_001);
+    }
+
+    #[test]
+    fn test_insert_and_validate() {
+        let mut root = RangeQueryTree::new(4, None, None);
+        for val in [2, 6, 1, 3, 5, 7, 3] {
+            root.insert(val);
+        }
+        assert_eq!(root.range_query(0, 100), vec![4, 2, 1, 3, 6, 5, 7]);
+        assert!(root.validate().is_ok());
+
+        // 5 is on the left of 4
+        let root = RangeQueryTree::new(4, Some(RangeQueryTree::new(5, None, None)), None);
+        assert_eq!(root.validate(), Err("the values at 0 and 1 are out of order".to_string()));
+    }
 }
\ No newline at end of file
@@ -1,4 +1,3 @@
-#![allow(dead_code, unused_variables, unused_mut, unused_assignments, unused_imports)]
 //!
 //! an implementation for Red-Black Tree
 //!
@@ -12,37 +11,62 @@
 //! 3. A red node does not have a red child.
 //! 4. Every path from a given node to any of its descendant NIL nodes goes through the same number of **black nodes**.
 //! 5. (Conclusion) If a node N has exactly one child, the child must be red(and the node N itself must be black, because <3>), because if it were black, its NIL descendants
-//! would sit at a different black depth than N's NIL child, violating requirement 4.
+//!    would sit at a different black depth than N's NIL child, violating requirement 4.
 //!
 
-use std::cmp::Ordering;
-use std::fmt;
-use std::mem::swap;
-use std::ops::DerefMut;
-use std::ptr::replace;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+#[cfg(any(test, feature = "std"))]
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::fmt;
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::error::TreeError;
+use crate::stats::{self, Stats};
+use crate::map::OrderedMap;
+use crate::path::{self, Descend, Path};
+use crate::render::{self, Layout, Sketch};
 use crate::rbt::Rbt::Leaf;
 
-#[derive(Debug, PartialEq, Clone)]
-enum Rbt<T: Ord + fmt::Display + fmt::Debug> {
+#[cfg(any(test, feature = "std"))]
+thread_local! {
+    /// the rotations made on this thread, a tree is a bare node with no room for a counter
+    static ROTATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The number of rotations made by all the trees of the current thread so far, only counted with
+/// `std` which has the thread locals.
+#[cfg(any(test, feature = "std"))]
+pub fn rotations() -> usize {
+    ROTATIONS.with(Cell::get)
+}
+
+/// `data` is the value associated with `val` when the tree is used as a map, it is `()` for a set.
+/// Its own operations use the `Ord` of the keys, `RbTree` holds a root with a comparator.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Rbt<T, V = ()> {
     Node {
         is_red: bool, // represent the color
         val: T,
-        left: Box<Rbt<T>>,
-        right: Box<Rbt<T>>,
+        data: V,
+        left: Box<Rbt<T, V>>,
+        right: Box<Rbt<T, V>>,
     },
+    #[default]
     Leaf,
 }
 
-impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
-    pub fn new() -> Rbt<T> {
-        Leaf
-    }
-
+impl<T: Ord> Rbt<T> {
     pub fn insert(&mut self, new_val: T) {
-        self.internal_insert(new_val);
+        self.internal_insert(new_val, ());
         self.update_colors(false);
     }
+}
 
+impl<T: Ord, V> Rbt<T, V> {
     pub fn search(&mut self, data: T) -> bool {
         match self {
             Rbt::Node {
@@ -61,64 +85,275 @@ impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
         }
     }
 
-    pub fn is_red(&self) -> bool {
-        match self {
-            Rbt::Node {
-                is_red,
-                ..
-            } => *is_red,
-            Leaf => false
-        }
-    }
-
-    fn internal_insert(&mut self, new_val: T) {
-        use std::mem::swap as node_swap;
-
+    #[allow(unused_variables)]
+    fn internal_insert(&mut self, new_val: T, new_data: V) {
         match self {
             Rbt::Node {
                 is_red,
                 ref val,
                 ref mut left,
-                ref mut right
+                ref mut right,
+                ..
             } => {
                 let cmp_value = new_val.cmp(val);
                 if cmp_value == Ordering::Less {
-                    left.internal_insert(new_val);
+                    left.internal_insert(new_val, new_data);
                 } else if cmp_value == Ordering::Greater {
-                    right.internal_insert(new_val);
+                    right.internal_insert(new_val, new_data);
                 } else {
                     return;
                 }
 
-                if self.right().is_red() && !self.left().is_red() {
-                    let mut tmp = Leaf;
-                    node_swap(&mut tmp, self);
-                    tmp = Self::rotate(tmp, true);
-                    node_swap(&mut tmp, self);
-                }
+                self.balance(&mut Path::new());
+            }
+            Leaf => {
+                *self = Self::new_node(new_val, new_data)
+            }
+        }
+    }
+}
 
-                if self.left().is_red() && self.left().child(true).is_red() {
-                    let mut tmp = Leaf;
-                    node_swap(&mut tmp, self);
-                    tmp = Self::rotate(tmp, false);
-                    node_swap(&mut tmp, self);
-                }
+impl<T, V> Rbt<T, V> {
+    pub fn new() -> Rbt<T, V> {
+        Leaf
+    }
+
+    /// A tree of `entries`, which are sorted and unique, see `RbTree::from_sorted`.
+    ///
+    /// The entries are spread over a 2-3 tree with all its leaves at the same depth, as few levels
+    /// as there are full levels of 2-nodes. A 2-node becomes a black node, a 3-node a black node with
+    /// a red left child.
+    fn from_sorted(entries: Vec<(T, V)>) -> Rbt<T, V> {
+        fn build<T, V>(entries: &mut vec::IntoIter<(T, V)>, len: usize, levels: u32) -> Rbt<T, V> {
+            if levels == 0 {
+                return Leaf;
+            }
+            // a subtree one level lower holds from 2^(levels - 1) - 1 to 3^(levels - 1) - 1 entries
+            let most = 3usize.saturating_pow(levels - 1) - 1;
+            if len - 1 <= most.saturating_mul(2) {
+                let left = build(entries, (len - 1) / 2, levels - 1);
+                let (val, data) = entries.next().expect("there are `len` entries");
+                let right = build(entries, len - 1 - (len - 1) / 2, levels - 1);
+                return Rbt::Node { is_red: false, val, data, left: Box::new(left), right: Box::new(right) };
+            }
+            let rest = len - 2;
+            let left = build(entries, rest / 3, levels - 1);
+            let (val, data) = entries.next().expect("there are `len` entries");
+            let middle = build(entries, (rest + 1) / 3, levels - 1);
+            let red = Rbt::Node { is_red: true, val, data, left: Box::new(left), right: Box::new(middle) };
+            let (val, data) = entries.next().expect("there are `len` entries");
+            let right = build(entries, rest.div_ceil(3), levels - 1);
+            Rbt::Node { is_red: false, val, data, left: Box::new(red), right: Box::new(right) }
+        }
+
+        let len = entries.len();
+        build(&mut entries.into_iter(), len, (len + 1).ilog2())
+    }
+
+    /// The entries in order, the nodes are taken apart on the way.
+    fn into_sorted(self) -> Vec<(T, V)> {
+        let mut entries = Vec::new();
+        let mut stack = Vec::new();
+        let mut node = self;
+        loop {
+            while let Rbt::Node { val, data, left, right, .. } = node {
+                stack.push((val, data, right));
+                node = *left;
+            }
+            let Some((val, data, right)) = stack.pop() else { return entries };
+            entries.push((val, data));
+            node = *right;
+        }
+    }
 
-                if self.left().is_red() && self.right().is_red() {
-                    self.update_colors(true);
-                    self.left().update_colors(false);
-                    self.right().update_colors(false);
+    pub fn is_red(&self) -> bool {
+        match self {
+            Rbt::Node {
+                is_red,
+                ..
+            } => *is_red,
+            Leaf => false
+        }
+    }
+
+    /// Restores the left-leaning shape of this node after one of its subtrees changed, `path`
+    /// starts at this node and is updated to lead to the same node afterwards.
+    fn balance(&mut self, path: &mut Path) {
+        if self.right().is_red() && !self.left().is_red() {
+            self.rotate_mut(true);
+            path::rotate(path, true);
+        }
+
+        if self.left().is_red() && self.left().child(true).is_red() {
+            self.rotate_mut(false);
+            path::rotate(path, false);
+        }
+
+        if self.left().is_red() && self.right().is_red() {
+            self.update_colors(true);
+            self.left().update_colors(false);
+            self.right().update_colors(false);
+        }
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { nodes: path::InOrder::new(self.node()) }
+    }
+
+    /// This node, `None` for a leaf.
+    fn node(&self) -> Option<&Rbt<T, V>> {
+        match self {
+            Rbt::Node { .. } => Some(self),
+            Leaf => None,
+        }
+    }
+
+    fn descendant(&self, path: &Path) -> &Rbt<T, V> {
+        let mut node = self;
+        for &go_left in path {
+            node = node.try_child(go_left).expect("Attempted to get child of leaf");
+        }
+        node
+    }
+
+    fn descendant_mut(&mut self, path: &Path) -> &mut Rbt<T, V> {
+        let mut node = self;
+        for &go_left in path {
+            node = node.child(go_left);
+        }
+        node
+    }
+
+    /// Adds a new node at the leaf `path` leads to and rebalances the nodes on the way back up, on
+    /// return `path` leads to the new node again.
+    fn insert_at(&mut self, path: &mut Path, new_val: T, new_data: V) {
+        match self {
+            Rbt::Node { .. } => {
+                let go_left = path.pop_front().expect("the path ends at a leaf");
+                self.child(go_left).insert_at(path, new_val, new_data);
+                path.push_front(go_left);
+                self.balance(path);
+            }
+            Leaf => *self = Self::new_node(new_val, new_data),
+        }
+    }
+
+    /// Removes the node `path` leads to by the top-down deletion of the left-leaning red-black
+    /// tree: on the way down the current node is kept red or with a red left child, so the node
+    /// finally removed is red. The path is updated through the rotations, no key is compared.
+    fn remove_at(&mut self, mut path: Path) -> (T, V) {
+        if !self.left().is_red() && !self.right().is_red() {
+            self.update_colors(true);
+        }
+        let removed = self.delete_at(&mut path);
+        self.update_colors(false);
+        removed
+    }
+
+    fn delete_at(&mut self, path: &mut Path) -> (T, V) {
+        let removed = if path.front() == Some(&true) {
+            if !self.left().is_red() && !self.left().child_is_red(true) {
+                self.move_red_left(path);
+            }
+            path.pop_front();
+            self.left().delete_at(path)
+        } else {
+            if self.left().is_red() {
+                self.rotate_mut(false);
+                path::rotate(path, false);
+            }
+            if path.is_empty() && self.right().is_nil() {
+                return self.take();
+            }
+            if !self.right().is_red() && !self.right().child_is_red(true) {
+                self.move_red_right(path);
+            }
+            if path.is_empty() {
+                // replace the node by its successor, the smallest node of the right subtree
+                let (min_val, min_data) = self.right().delete_min();
+                match self {
+                    Rbt::Node { val, data, .. } => (mem::replace(val, min_val), mem::replace(data, min_data)),
+                    Leaf => unreachable!("the path leads to a node"),
                 }
+            } else {
+                path.pop_front();
+                self.right().delete_at(path)
             }
-            Leaf => {
-                *self = Self::new_node(new_val)
+        };
+
+        self.balance(&mut Path::new());
+        removed
+    }
+
+    fn delete_min(&mut self) -> (T, V) {
+        if self.left().is_nil() {
+            return self.take();
+        }
+        if !self.left().is_red() && !self.left().child_is_red(true) {
+            self.move_red_left(&mut Path::new());
+        }
+        let removed = self.left().delete_min();
+        self.balance(&mut Path::new());
+        removed
+    }
+
+    /// Makes the left child or one of its children red by borrowing from the right sibling.
+    fn move_red_left(&mut self, path: &mut Path) {
+        self.flip_colors();
+        if self.right().child_is_red(true) {
+            self.right().rotate_mut(false);
+            path::rotate_child(path, false, false);
+            self.rotate_mut(true);
+            path::rotate(path, true);
+            self.flip_colors();
+        }
+    }
+
+    /// Makes the right child or one of its children red by borrowing from the left sibling.
+    fn move_red_right(&mut self, path: &mut Path) {
+        self.flip_colors();
+        if self.left().child_is_red(true) {
+            self.rotate_mut(false);
+            path::rotate(path, false);
+            self.flip_colors();
+        }
+    }
+
+    fn flip_colors(&mut self) {
+        let is_red = self.is_red();
+        self.update_colors(!is_red);
+        self.left().update_colors(is_red);
+        self.right().update_colors(is_red);
+    }
+
+    fn rotate_mut(&mut self, left: bool) {
+        let root = mem::replace(self, Leaf);
+        *self = Self::rotate(root, left);
+    }
+
+    fn child_is_red(&self, left: bool) -> bool {
+        match self {
+            Rbt::Node { left: child_left, right: child_right, .. } => {
+                if left { child_left.is_red() } else { child_right.is_red() }
             }
+            Leaf => false,
+        }
+    }
+
+    /// Replaces this node by a leaf and returns its content.
+    fn take(&mut self) -> (T, V) {
+        match mem::replace(self, Leaf) {
+            Rbt::Node { val, data, .. } => (val, data),
+            Leaf => panic!("Attempted to take leaf"),
         }
     }
 
-    fn rotate(mut root: Rbt<T>, left: bool) -> Rbt<T> {
-        use std::mem::swap as node_swap;
+    fn rotate(mut root: Rbt<T, V>, left: bool) -> Rbt<T, V> {
+        use core::mem::swap as node_swap;
 
+        #[cfg(any(test, feature = "std"))]
+        ROTATIONS.with(|rotations| rotations.set(rotations.get() + 1));
         let root_color = root.is_red();
 
         let mut tmp = Leaf;
@@ -137,11 +372,12 @@ impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
         tmp
     }
 
-    fn new_node(data: T) -> Rbt<T> {
+    fn new_node(val: T, data: V) -> Rbt<T, V> {
         Rbt::Node {
             // every new node is red
             is_red: true,
-            val: data,
+            val,
+            data,
             left: Box::new(Leaf),
             right: Box::new(Leaf),
         }
@@ -154,39 +390,49 @@ impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
         }
     }
 
-    fn value(&mut self) -> &T {
+    /// The key of this node, `Empty` for a leaf.
+    pub fn try_value(&self) -> Result<&T, TreeError> {
         match self {
-            Rbt::Node {
-                ref val,
-                ..
-            } => val,
-            Leaf => panic!("Attempted to get value of leaf"),
+            Rbt::Node { val, .. } => Ok(val),
+            Leaf => Err(TreeError::Empty),
+        }
+    }
+
+    /// The left (`left == true`) or the right child of this node, `Empty` for a leaf.
+    pub fn try_child(&self, left: bool) -> Result<&Rbt<T, V>, TreeError> {
+        match self {
+            Rbt::Node { left: child_left, right: child_right, .. } => Ok(if left { child_left } else { child_right }),
+            Leaf => Err(TreeError::Empty),
         }
     }
 
-    fn left(&mut self) -> &mut Rbt<T> {
+    /// Same as `try_child`, the child can be changed.
+    pub fn try_child_mut(&mut self, left: bool) -> Result<&mut Rbt<T, V>, TreeError> {
+        match self {
+            Rbt::Node { left: child_left, right: child_right, .. } => Ok(if left { child_left } else { child_right }),
+            Leaf => Err(TreeError::Empty),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn value(&mut self) -> &T {
+        self.value_ref()
+    }
+
+    fn value_ref(&self) -> &T {
+        self.try_value().expect("Attempted to get value of leaf")
+    }
+
+    fn left(&mut self) -> &mut Rbt<T, V> {
         self.child(true)
     }
 
-    fn right(&mut self) -> &mut Rbt<T> {
+    fn right(&mut self) -> &mut Rbt<T, V> {
         self.child(false)
     }
 
-    fn child(&mut self, left: bool) -> &mut Rbt<T> {
-        match self {
-            Rbt::Node {
-                left: child_left,
-                right: child_right,
-                ..
-            } => {
-                if left {
-                    child_left.deref_mut()
-                } else {
-                    child_right.deref_mut()
-                }
-            }
-            Leaf => panic!("Attempted to get child of leaf"),
-        }
+    fn child(&mut self, left: bool) -> &mut Rbt<T, V> {
+        self.try_child_mut(left).expect("Attempted to get child of leaf")
     }
 
     fn update_colors(&mut self, new_is_red: bool) {
@@ -199,9 +445,29 @@ impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
         }
     }
 
-    fn output(&self) {
+    /// The shape of the tree with the color of every node, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch>
+    where
+        T: fmt::Display,
+    {
+        match self {
+            Rbt::Node { is_red, val, left, right, .. } => {
+                let children = vec![left.sketch(), right.sketch()];
+                Some(Sketch { red: *is_red, ..Sketch::new(val.to_string(), children) })
+            }
+            Leaf => None,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[allow(dead_code)]
+    fn output(&self)
+    where
+        T: fmt::Display + fmt::Debug,
+        V: fmt::Debug,
+    {
         match &self {
-            Rbt::Node { is_red, val, left, right } => {
+            Rbt::Node { is_red, val, left, right, .. } => {
                 println!("is_red = {is_red}, val = {val},\n\tleft = {:?},\n\tright = {:?}", left, right)
             }
             Leaf => println!("leaf")
@@ -209,11 +475,549 @@ impl<T: Ord + fmt::Display + fmt::Debug> Rbt<T> {
     }
 }
 
+impl<T: fmt::Display, V> fmt::Display for Rbt<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V> Descend for Rbt<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        match self {
+            Rbt::Node { left: child_left, right: child_right, .. } => {
+                if left { child_left.node() } else { child_right.node() }
+            }
+            Leaf => None,
+        }
+    }
+}
+
+/// A left-leaning red-black tree owning its root `Rbt` and the comparator which orders it.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The keys are ordered by
+/// the comparator `C`, their natural order by default.
+pub struct RbTree<T, V = (), C = Natural> {
+    root: Rbt<T, V>,
+    len: usize,
+    cmp: C,
+}
+
+impl<T, V, C: Default> Default for RbTree<T, V, C> {
+    fn default() -> Self {
+        RbTree { root: Leaf, len: 0, cmp: C::default() }
+    }
+}
+
+impl<T: Ord, V> RbTree<T, V> {
+    pub fn new() -> RbTree<T, V> {
+        RbTree::with_comparator(Natural)
+    }
+
+    /// A tree of `entries`, which are sorted and unique, built in linear time. `validate` reports
+    /// entries out of order.
+    pub fn from_sorted(entries: Vec<(T, V)>) -> RbTree<T, V> {
+        RbTree::from_sorted_with_comparator(entries, Natural)
+    }
+}
+
+impl<T, V, C: Compare<T>> RbTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> RbTree<T, V, C> {
+        RbTree { root: Leaf, len: 0, cmp }
+    }
+
+    /// Same as `from_sorted`, `entries` are sorted by `cmp`.
+    pub fn from_sorted_with_comparator(entries: Vec<(T, V)>, cmp: C) -> RbTree<T, V, C> {
+        let len = entries.len();
+        RbTree { root: Rbt::from_sorted(entries), len, cmp }
+    }
+
+    /// Keeps the entries `f` accepts and rebuilds the tree with `from_sorted`, in linear time.
+    pub fn retain<F: FnMut(&T, &mut V) -> bool>(&mut self, mut f: F) {
+        let mut entries = mem::take(&mut self.root).into_sorted();
+        entries.retain_mut(|(key, data)| f(key, data));
+        self.len = entries.len();
+        self.root = Rbt::from_sorted(entries);
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn search(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Rbt::Node { val, data, left, right, .. } = node {
+            node = match self.cmp.compare(key, val) {
+                Ordering::Equal => return Some(data),
+                Ordering::Less => left,
+                Ordering::Greater => right,
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        match self.entry_path(key) {
+            (path, true) => match self.root.descendant_mut(&path) {
+                Rbt::Node { data, .. } => Some(data),
+                Leaf => unreachable!("the path leads to a node"),
+            },
+            (_, false) => None,
+        }
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(data)),
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        match self.entry_path(key) {
+            (path, true) => Some(self.remove_at(path).1),
+            (_, false) => None,
+        }
+    }
+
+    /// Inserts `key` unless it is there already, unlike `insert` which replaces its data.
+    pub fn try_insert(&mut self, key: T, data: V) -> Result<&mut V, TreeError> {
+        match self.entry(key) {
+            Entry::Occupied(_) => Err(TreeError::Duplicate),
+            Entry::Vacant(entry) => Ok(entry.insert(data)),
+        }
+    }
+
+    /// Gets the entry of `key` for in-place manipulation, the tree is walked down only once.
+    pub fn entry(&mut self, key: T) -> Entry<'_, T, V, C> {
+        match self.entry_path(&key) {
+            (path, true) => Entry::Occupied(OccupiedEntry { tree: self, path }),
+            (path, false) => Entry::Vacant(VacantEntry { tree: self, path, key }),
+        }
+    }
+
+    /// A cursor at the ghost position, which is between the last and the first node.
+    pub fn cursor(&self) -> Cursor<'_, T, V, C> {
+        Cursor { tree: self, path: None }
+    }
+
+    /// A cursor at the ghost position, which is between the last and the first node.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, V, C> {
+        CursorMut { tree: self, path: None }
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        self.root.iter()
+    }
+
+    /// Checks the order of the keys, the length and the properties of a left-leaning red-black
+    /// tree: the root is black, red links lean left, no red node has a red child and every path
+    /// from the root to a leaf has the same number of black nodes.
+    pub fn validate(&self) -> Result<(), String> {
+        fn black_height<T, V>(node: &Rbt<T, V>) -> Result<usize, String> {
+            match node {
+                Rbt::Node { is_red, left, right, .. } => {
+                    if right.is_red() {
+                        return Err("a red link leans right".to_string());
+                    }
+                    if *is_red && left.is_red() {
+                        return Err("a red node has a red child".to_string());
+                    }
+                    let (left_height, right_height) = (black_height(left)?, black_height(right)?);
+                    if left_height != right_height {
+                        return Err(format!("a node has subtrees of the black heights {} and {}", left_height, right_height));
+                    }
+                    Ok(left_height + if *is_red { 0 } else { 1 })
+                }
+                Leaf => Ok(0),
+            }
+        }
+
+        if self.root.is_red() {
+            return Err("the root is red".to_string());
+        }
+        black_height(&self.root)?;
+        let keys: Vec<&T> = self.iter().map(|(key, _)| key).collect();
+        if let Some(i) = keys.windows(2).position(|pair| self.cmp.compare(pair[0], pair[1]) != Ordering::Less) {
+            return Err(format!("the keys at {} and {} are out of order", i, i + 1));
+        }
+        if keys.len() != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, keys.len()));
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree with its black height and its red nodes. Every node owns the boxes of
+    /// its two children, empty or not.
+    pub fn stats(&self) -> Stats {
+        let mut red = 0;
+        let mut stats = stats::binary(self.root.node(), 2 * mem::size_of::<Rbt<T, V>>(), |node| {
+            if let Rbt::Node { is_red: true, .. } = node {
+                red += 1;
+            }
+        });
+        let mut black_height = 0;
+        let mut node = self.root.node();
+        while let Some(current) = node {
+            if let Rbt::Node { is_red: false, .. } = current {
+                black_height += 1;
+            }
+            node = current.descend(true);
+        }
+        stats.black_height = Some(black_height);
+        stats.red_nodes = Some(red);
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// The path to the smallest node not less than `key`, or greater than `key` if not `inclusive`.
+    fn bound_path(&self, key: &T, inclusive: bool) -> Option<Path> {
+        let mut path = Path::new();
+        let mut found = None;
+        let mut node = &self.root;
+        while let Rbt::Node { val, left, right, .. } = node {
+            let go_left = match self.cmp.compare(val, key) {
+                Ordering::Equal if inclusive => return Some(path),
+                Ordering::Greater => true,
+                _ => false,
+            };
+            if go_left {
+                found = Some(path.len());
+            }
+            path.push_back(go_left);
+            node = if go_left { left } else { right };
+        }
+        found.map(|len| {
+            path.truncate(len);
+            path
+        })
+    }
+
+    /// The path to the node holding `key`, or to the leaf where it belongs.
+    fn entry_path(&self, key: &T) -> (Path, bool) {
+        let mut path = Path::new();
+        let mut node = &self.root;
+        while let Rbt::Node { val, left, right, .. } = node {
+            let go_left = match self.cmp.compare(key, val) {
+                Ordering::Equal => return (path, true),
+                Ordering::Less => true,
+                Ordering::Greater => false,
+            };
+            path.push_back(go_left);
+            node = if go_left { left } else { right };
+        }
+        (path, false)
+    }
+
+    /// Adds a new node at the leaf `path` leads to, on return `path` leads to the new node.
+    fn insert_at(&mut self, path: &mut Path, key: T, data: V) {
+        self.root.insert_at(path, key, data);
+        self.root.update_colors(false);
+        self.len += 1;
+    }
+
+    fn remove_at(&mut self, path: Path) -> (T, V) {
+        self.len -= 1;
+        self.root.remove_at(path)
+    }
+}
+
+impl<T: fmt::Display, V, C> RbTree<T, V, C> {
+    /// The shape of the tree with the color of every node, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        self.root.sketch()
+    }
+}
+
+impl<T: fmt::Display, V, C> fmt::Display for RbTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for RbTree<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        RbTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        RbTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        RbTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        RbTree::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        self.entry(key).or_insert_with(default)
+    }
+
+    fn iter(&self) -> Iter<'_, T, V> {
+        RbTree::iter(self)
+    }
+}
+
+/// An in-order iterator over the keys of a `Rbt` and their data.
+pub struct Iter<'a, T, V> {
+    nodes: path::InOrder<'a, Rbt<T, V>>,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.nodes.next()? {
+            Rbt::Node { val, data, .. } => Some((val, data)),
+            Leaf => unreachable!("only nodes are visited"),
+        }
+    }
+}
+
+/// A cursor over a `RbTree`, it moves forward and backward in order from any position.
+///
+/// The cursor remembers the path from the root, every step follows it down again but doesn't
+/// compare keys. `None` is the ghost position between the last and the first node.
+pub struct Cursor<'a, T, V, C = Natural> {
+    tree: &'a RbTree<T, V, C>,
+    path: Option<Path>,
+}
+
+impl<'a, T, V, C: Compare<T>> Cursor<'a, T, V, C> {
+    pub fn current(&self) -> Option<(&'a T, &'a V)> {
+        match path::follow(self.tree.root.node()?, self.path.as_ref()?) {
+            Rbt::Node { val, data, .. } => Some((val, data)),
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    /// Moves to the smallest node not less than `key`, or to the ghost position if there is none.
+    pub fn seek(&mut self, key: &T) {
+        self.path = self.tree.bound_path(key, true);
+    }
+
+    pub fn next(&mut self) {
+        self.path = path::move_cursor(self.tree.root.node(), self.path.take(), true);
+    }
+
+    pub fn prev(&mut self) {
+        self.path = path::move_cursor(self.tree.root.node(), self.path.take(), false);
+    }
+}
+
+/// A cursor which can also change the data in place, and remove or insert nodes next to its
+/// position. The tree is rebalanced as usual.
+pub struct CursorMut<'a, T, V, C = Natural> {
+    tree: &'a mut RbTree<T, V, C>,
+    path: Option<Path>,
+}
+
+impl<'a, T, V, C: Compare<T>> CursorMut<'a, T, V, C> {
+    pub fn current(&mut self) -> Option<(&T, &mut V)> {
+        let path = self.path.as_ref()?;
+        match self.tree.root.descendant_mut(path) {
+            Rbt::Node { val, data, .. } => Some((val, data)),
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    /// Moves to the smallest node not less than `key`, or to the ghost position if there is none.
+    pub fn seek(&mut self, key: &T) {
+        self.path = self.tree.bound_path(key, true);
+    }
+
+    pub fn next(&mut self) {
+        self.path = path::move_cursor(self.tree.root.node(), self.path.take(), true);
+    }
+
+    pub fn prev(&mut self) {
+        self.path = path::move_cursor(self.tree.root.node(), self.path.take(), false);
+    }
+
+    /// Removes the current node and moves to the next one.
+    pub fn remove_current(&mut self) -> Option<(T, V)> {
+        let path = self.path.take()?;
+        let removed = self.tree.remove_at(path);
+        // the rotations have moved the nodes around, find the next one by its key
+        self.path = self.tree.bound_path(&removed.0, false);
+        Some(removed)
+    }
+
+    /// Inserts a node right after the current one, or before the first node at the ghost position.
+    /// The cursor doesn't move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` doesn't sort between the current and the next node.
+    pub fn insert_after(&mut self, key: T, data: V) {
+        let root = self.tree.root.node();
+        if let Some(current) = self.path.as_ref() {
+            let current = path::follow(root.unwrap(), current).value_ref();
+            let ordered = self.tree.cmp.compare(current, &key) == Ordering::Less;
+            assert!(ordered, "the key must be greater than the current one");
+        }
+        if let Some(next) = path::move_cursor(root, self.path.clone(), true) {
+            let next = path::follow(root.unwrap(), &next).value_ref();
+            let ordered = self.tree.cmp.compare(&key, next) == Ordering::Less;
+            assert!(ordered, "the key must be less than the next one");
+        }
+
+        let mut slot = match (root, self.path.as_ref()) {
+            (Some(root), Some(current)) => path::slot_after(root, current),
+            (root, _) => path::slot_first(root),
+        };
+        self.tree.insert_at(&mut slot, key, data);
+
+        // the current node is right before the new one, wherever the rotations have moved them
+        if self.path.is_some() {
+            path::step(&self.tree.root, &mut slot, false);
+            self.path = Some(slot);
+        }
+    }
+}
+
+/// A view into a single entry of a `RbTree`, which may either be vacant or occupied.
+pub enum Entry<'a, T, V, C = Natural> {
+    Occupied(OccupiedEntry<'a, T, V, C>),
+    Vacant(VacantEntry<'a, T, V, C>),
+}
+
+pub struct OccupiedEntry<'a, T, V, C = Natural> {
+    tree: &'a mut RbTree<T, V, C>,
+    path: Path,
+}
+
+pub struct VacantEntry<'a, T, V, C = Natural> {
+    tree: &'a mut RbTree<T, V, C>,
+    path: Path,
+    key: T,
+}
+
+impl<'a, T, V, C: Compare<T>> Entry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, T, V, C: Compare<T>> OccupiedEntry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        match self.tree.root.descendant(&self.path) {
+            Rbt::Node { val, .. } => val,
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        match self.tree.root.descendant(&self.path) {
+            Rbt::Node { data, .. } => data,
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        match self.tree.root.descendant_mut(&self.path) {
+            Rbt::Node { data, .. } => data,
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        match self.tree.root.descendant_mut(&self.path) {
+            Rbt::Node { data, .. } => data,
+            Leaf => unreachable!("the path leads to a node"),
+        }
+    }
+
+    pub fn insert(&mut self, data: V) -> V {
+        mem::replace(self.get_mut(), data)
+    }
+
+    pub fn remove_entry(self) -> (T, V) {
+        self.tree.remove_at(self.path)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, T, V, C: Compare<T>> VacantEntry<'a, T, V, C> {
+    pub fn key(&self) -> &T {
+        &self.key
+    }
+
+    pub fn into_key(self) -> T {
+        self.key
+    }
+
+    /// Adds the new node where the search ended and rebalances the nodes above it.
+    pub fn insert(self, data: V) -> &'a mut V {
+        let VacantEntry { tree, mut path, key } = self;
+        tree.insert_at(&mut path, key, data);
+        match tree.root.descendant_mut(&path) {
+            Rbt::Node { data, .. } => data,
+            Leaf => unreachable!("the path leads to the new node"),
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::compare::Reverse;
+    use crate::testing::{check_ordered_map, check_reverse_order};
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_insert_and_search() {
         let mut tree = Rbt::new();
 
@@ -235,6 +1039,7 @@ pub mod tests {
     }
 
     #[test]
+    #[allow(unused_mut)]
     fn test_left_rotate() {
         //      1(b)
         //     / \
@@ -244,24 +1049,29 @@ pub mod tests {
         let mut right_side_tree = Rbt::Node {
             is_red: false,
             val: 1,
+            data: (),
             left: Box::new(Rbt::Node {
                 is_red: false,
                 val: 0,
+                data: (),
                 left: Box::new(Rbt::Leaf),
                 right: Box::new(Rbt::Leaf),
             }),
             right: Box::new(Rbt::Node {
                 is_red: true,
                 val: 3,
+                data: (),
                 left: Box::new(Rbt::Node {
                     is_red: false,
                     val: 2,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
                 right: Box::new(Rbt::Node {
                     is_red: false,
                     val: 4,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
@@ -276,18 +1086,22 @@ pub mod tests {
         let mut left_side_tree = Rbt::Node {
             is_red: false,
             val: 3,
+            data: (),
             left: Box::new(Rbt::Node {
                 is_red: true,
                 val: 1,
+                data: (),
                 left: Box::new(Rbt::Node {
                     is_red: false,
                     val: 0,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
                 right: Box::new(Rbt::Node {
                     is_red: false,
                     val: 2,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
@@ -295,6 +1109,7 @@ pub mod tests {
             right: Box::new(Rbt::Node {
                 is_red: false,
                 val: 4,
+                data: (),
                 left: Box::new(Rbt::Leaf),
                 right: Box::new(Rbt::Leaf),
             }),
@@ -315,9 +1130,11 @@ pub mod tests {
         let target = Rbt::Node {
             is_red: false,
             val: 2,
+            data: (),
             left: Box::new(Rbt::Node {
                 is_red: true,
                 val: 1,
+                data: (),
                 left: Box::new(Leaf),
                 right: Box::new(Leaf),
             }),
@@ -334,9 +1151,11 @@ pub mod tests {
         let target = Rbt::Node {
             is_red: false,
             val: 3,
+            data: (),
             left: Box::new(Rbt::Node {
                 is_red: true,
                 val: 2,
+                data: (),
                 left: Box::new(Leaf),
                 right: Box::new(Leaf),
             }),
@@ -357,12 +1176,15 @@ pub mod tests {
         let target = Rbt::Node {
             is_red: false,
             val: 3,
+            data: (),
             left: Box::new(Rbt::Node {
                 is_red: false,
                 val: 2,
+                data: (),
                 left: Box::new(Rbt::Node {
                     is_red: true,
                     val: 1,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
@@ -371,9 +1193,11 @@ pub mod tests {
             right: Box::new(Rbt::Node {
                 is_red: false,
                 val: 5,
+                data: (),
                 left: Box::new(Rbt::Node {
                     is_red: true,
                     val: 4,
+                    data: (),
                     left: Box::new(Rbt::Leaf),
                     right: Box::new(Rbt::Leaf),
                 }),
@@ -420,4 +1244,317 @@ pub mod tests {
         assert_eq!(9, *root.right().right().value());
     }
 
+    /// Checks the order and the left-leaning red-black rules, returns the black height.
+    fn check_rbt<V>(node: &Rbt<i32, V>, low: Option<i32>, high: Option<i32>) -> usize {
+        match node {
+            Rbt::Node { is_red, val, left, right, .. } => {
+                assert!(low.is_none_or(|low| *val > low));
+                assert!(high.is_none_or(|high| *val < high));
+                assert!(!right.is_red(), "right leaning red link at {}", val);
+                assert!(!(*is_red && left.is_red()), "double red at {}", val);
+                let black_height = check_rbt(left, low, Some(*val));
+                assert_eq!(black_height, check_rbt(right, Some(*val), high));
+                black_height + if *is_red { 0 } else { 1 }
+            }
+            Leaf => 0,
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut tree = RbTree::new();
+        for word in "a b c a b a d".split(' ') {
+            *tree.entry(word.to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(tree.get(&"a".to_string()), Some(&3));
+        assert_eq!(tree.get(&"b".to_string()), Some(&2));
+        assert_eq!(tree.get(&"d".to_string()), Some(&1));
+
+        tree.entry("c".to_string()).and_modify(|n| *n += 10).or_insert(0);
+        tree.entry("e".to_string()).and_modify(|n| *n += 10).or_insert_with(|| 7);
+        assert_eq!(tree.get(&"c".to_string()), Some(&11));
+        assert_eq!(tree.get(&"e".to_string()), Some(&7));
+
+        match tree.entry("a".to_string()) {
+            Entry::Occupied(entry) => {
+                assert_eq!(entry.key(), "a");
+                assert_eq!(entry.remove_entry(), ("a".to_string(), 3));
+            }
+            Entry::Vacant(_) => panic!("a is in the tree"),
+        }
+        assert_eq!(tree.get(&"a".to_string()), None);
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_entry_after_rotation() {
+        // inserting 2 rotates twice and moves the new node to the root
+        let mut tree = RbTree::new();
+        tree.insert(1, 'a');
+        tree.insert(3, 'c');
+        let data = tree.entry(2).or_insert('x');
+        assert_eq!(*data, 'x');
+        *data = 'b';
+
+        assert_eq!(2, *tree.root.value());
+        assert_eq!(tree.get(&1), Some(&'a'));
+        assert_eq!(tree.get(&2), Some(&'b'));
+        assert_eq!(tree.get(&3), Some(&'c'));
+        check_rbt(&tree.root, None, None);
+    }
+
+    #[test]
+    fn test_remove_against_btree_map() {
+        let mut tree = RbTree::new();
+        let mut expected = std::collections::BTreeMap::new();
+
+        for i in 0..500 {
+            let key = (i * 37) % 101;
+            if i % 3 == 0 {
+                assert_eq!(tree.remove(&key), expected.remove(&key));
+            } else {
+                assert_eq!(tree.insert(key, i), expected.insert(key, i));
+            }
+            check_rbt(&tree.root, None, None);
+            assert!(tree.validate().is_ok());
+        }
+
+        for key in 0..101 {
+            assert_eq!(tree.get(&key), expected.get(&key));
+            if let Some(data) = tree.get_mut(&key) {
+                *data += 1;
+            }
+        }
+        while let Some(&key) = expected.keys().next() {
+            assert_eq!(tree.remove(&key), expected.remove(&key).map(|data| data + 1));
+            check_rbt(&tree.root, None, None);
+        }
+        assert_eq!(tree.root, Leaf);
+        assert!(tree.is_empty());
+    }
+
+    fn even_tree() -> RbTree<i32, i32> {
+        let mut tree = RbTree::new();
+        for i in (0..20).step_by(2) {
+            tree.insert(i, i * 10);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_cursor() {
+        let tree = even_tree();
+        let mut cursor = tree.cursor();
+        assert_eq!(cursor.current(), None);
+
+        cursor.seek(&5);
+        assert_eq!(cursor.current(), Some((&6, &60)));
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&8, &80)));
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.current(), Some((&4, &40)));
+        cursor.seek(&18);
+        cursor.next();
+        assert_eq!(cursor.current(), None);
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&0, &0)));
+        cursor.prev();
+        cursor.prev();
+        assert_eq!(cursor.current(), Some((&18, &180)));
+
+        let mut forward = vec![];
+        let mut cursor = tree.cursor();
+        cursor.next();
+        while let Some((val, _)) = cursor.current() {
+            forward.push(*val);
+            cursor.next();
+        }
+        assert_eq!(forward, (0..20).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cursor_mut() {
+        let mut tree = even_tree();
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&6);
+        *cursor.current().unwrap().1 += 1;
+        assert_eq!(cursor.remove_current(), Some((6, 61)));
+        assert_eq!(cursor.current(), Some((&8, &mut 80)));
+
+        cursor.insert_after(9, 90);
+        assert_eq!(cursor.current(), Some((&8, &mut 80)));
+        cursor.next();
+        assert_eq!(cursor.current(), Some((&9, &mut 90)));
+
+        cursor.seek(&100);
+        cursor.insert_after(-1, -10);
+        assert_eq!(cursor.current(), None);
+        cursor.seek(&18);
+        assert_eq!(cursor.remove_current(), Some((18, 180)));
+        assert_eq!(cursor.current(), None);
+
+        check_rbt(&tree.root, None, None);
+        assert_eq!(tree.get(&-1), Some(&-10));
+        assert_eq!(tree.get(&6), None);
+        assert_eq!(tree.get(&9), Some(&90));
+    }
+
+    #[test]
+    fn test_cursor_mut_rebalance() {
+        let mut tree = RbTree::new();
+        let mut expected = std::collections::BTreeMap::new();
+        let mut cursor = tree.cursor_mut();
+        for i in 0..200 {
+            cursor.insert_after(i, i);
+            cursor.next();
+            expected.insert(i, i);
+        }
+        check_rbt(&tree.root, None, None);
+
+        // remove every third node while walking forward
+        let mut cursor = tree.cursor_mut();
+        cursor.next();
+        let mut i = 0;
+        while let Some((&val, _)) = cursor.current() {
+            if i % 3 == 0 {
+                assert_eq!(cursor.remove_current(), expected.remove_entry(&val));
+            } else {
+                cursor.next();
+            }
+            i += 1;
+        }
+        check_rbt(&tree.root, None, None);
+        assert!(tree.validate().is_ok());
+        for (val, data) in expected {
+            assert_eq!(tree.get(&val), Some(&data));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cursor_insert_out_of_order() {
+        let mut tree = even_tree();
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&6);
+        cursor.insert_after(5, 0);
+    }
+
+    #[test]
+    fn test_comparator() {
+        let mut tree = RbTree::with_comparator(Reverse(Natural));
+        let mut expected = std::collections::BTreeMap::new();
+        for i in 0..100 {
+            let key = (i * 37) % 101;
+            assert_eq!(tree.insert(key, i), expected.insert(key, i));
+        }
+        for key in (0..101).step_by(3) {
+            assert_eq!(tree.remove(&key), expected.remove(&key));
+        }
+        // in reverse order the lower bounds are the upper bounds of the natural order
+        assert!(tree.iter().map(|(&key, &data)| (key, data)).eq(expected.clone().into_iter().rev()));
+        assert_eq!(tree.get(&37), expected.get(&37));
+        assert!(tree.validate().is_ok());
+
+        let mut cursor = tree.cursor_mut();
+        cursor.seek(&50);
+        let (&key, _) = cursor.current().unwrap();
+        assert_eq!(Some(&key), expected.range(..=50).next_back().map(|(key, _)| key));
+        cursor.remove_current();
+        let (&next, _) = cursor.current().unwrap();
+        assert!(next < key);
+        assert_eq!(tree.len(), expected.len() - 1);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut tree = Rbt::new();
+        for i in [2, 1, 3, 0] {
+            tree.insert(i);
+        }
+        assert_eq!(tree.to_string(), "   2\n  ┌┴─┐\n  1  3\n ┌┘\n0*\n");
+
+        let mut tree = RbTree::new();
+        for i in [2, 1, 3, 0] {
+            tree.insert(i, ());
+        }
+        assert_eq!(tree.to_string(), "   2\n  ┌┴─┐\n  1  3\n ┌┘\n0*\n");
+    }
+
+    #[test]
+    fn test_million_sorted_keys() {
+        let mut tree = RbTree::new();
+        for i in 0..1_000_000 {
+            tree.insert(i, ());
+        }
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.get(&999_999), Some(&()));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut tree = RbTree::new();
+        for i in [2, 1, 3, 0] {
+            tree.insert(i, ());
+        }
+        let stats = tree.stats();
+        assert_eq!((stats.nodes, stats.height), (4, 3));
+        assert_eq!(stats.black_height, Some(2));
+        assert_eq!(stats.red_nodes, Some(1));
+        assert_eq!(stats.red_ratio(), Some(0.25));
+        assert_eq!(stats.balance, None);
+
+        for i in 4..1000 {
+            tree.insert(i, ());
+        }
+        let stats = tree.stats();
+        // a red-black tree is at most twice as high as the optimal height
+        assert!(stats.height <= 2 * stats.optimal_height(), "{}", stats);
+        assert!(stats.min_leaf_depth + 1 >= stats.black_height.unwrap());
+    }
+
+    #[test]
+    fn test_try() {
+        let mut tree = RbTree::new();
+        assert_eq!(tree.root.try_value(), Err(TreeError::Empty));
+        assert_eq!(tree.root.try_child(true), Err(TreeError::Empty));
+        assert_eq!(tree.try_insert(2, "two").map(|data| *data), Ok("two"));
+        assert_eq!(tree.try_insert(1, "one").map(|data| *data), Ok("one"));
+        assert_eq!(tree.try_insert(2, "deux"), Err(TreeError::Duplicate));
+        assert_eq!(tree.get(&2), Some(&"two"));
+
+        // 1 is the red left child of 2
+        assert_eq!(tree.root.try_value(), Ok(&2));
+        assert_eq!(tree.root.try_child(true).and_then(Rbt::try_value), Ok(&1));
+        assert_eq!(tree.root.try_child(false).and_then(Rbt::try_value), Err(TreeError::Empty));
+        if let Ok(Rbt::Node { data, .. }) = tree.root.try_child_mut(true) {
+            *data = "un";
+        }
+        assert_eq!(tree.get(&1), Some(&"un"));
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_from_sorted_and_retain() {
+        for len in 0..300_usize {
+            let mut tree = RbTree::from_sorted((0..len).map(|key| (key, key * 2)).collect());
+            assert!(tree.validate().is_ok(), "{}", len);
+            assert!(tree.iter().map(|(&key, &data)| (key, data)).eq((0..len).map(|key| (key, key * 2))));
+            // the 2-3 tree has as many levels as the full levels of a binary tree
+            assert_eq!(tree.stats().black_height.unwrap_or(0), (len + 1).ilog2() as usize);
+
+            tree.retain(|key, data| {
+                *data += 1;
+                key % 3 == 0
+            });
+            assert!(tree.validate().is_ok(), "{}", len);
+            assert!(tree.iter().map(|(&key, &data)| (key, data)).eq((0..len).step_by(3).map(|key| (key, key * 2 + 1))));
+        }
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<RbTree<i32, i32>>().validate().is_ok());
+        assert!(check_reverse_order::<RbTree<i32, (), Reverse>>().validate().is_ok());
+    }
 }
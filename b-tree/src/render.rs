@@ -0,0 +1,373 @@
+//!
+//! drawings of trees for the terminal
+//!
+//! Every tree can make a `Sketch` of itself, a plain copy of its shape with a label for each node,
+//! so the same drawing routines serve the binary trees and the B+ tree alike:
+//!
+//! - `ascii` draws an outline, one node per line, which stays readable for any size of tree;
+//! - `draw` lays the tree out top-down with box-drawing characters, every node centered above
+//!   its children.
+//!
+
+use alloc::format;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+/// The shape of a tree. A binary node has two children, either of which may be missing, a node of a
+/// B+ tree has as many children as it has keys plus one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sketch {
+    pub label: String,
+    pub children: Vec<Option<Sketch>>,
+    /// the node is red in a red-black tree
+    pub red: bool,
+    /// the height of the node, if the tree keeps track of it
+    pub height: Option<i32>,
+}
+
+impl Sketch {
+    pub fn new(label: String, children: Vec<Option<Sketch>>) -> Sketch {
+        Sketch { label, children, red: false, height: None }
+    }
+
+    /// The number of nodes of this subtree.
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().flatten().map(Sketch::size).sum::<usize>()
+    }
+}
+
+/// Draws a tree as an outline, every node on its own line below its parent:
+///
+/// ```text
+/// 3
+/// +-- 1
+/// `-- 5
+///     +-- .
+///     `-- 6
+/// ```
+///
+/// A missing child is drawn as `.` unless all children of the node are missing.
+pub fn ascii(root: Option<&Sketch>) -> String {
+    let mut drawing = String::new();
+    match root {
+        None => drawing.push_str("(empty)\n"),
+        Some(root) => outline(root, "", &mut drawing),
+    }
+    drawing
+}
+
+fn outline(node: &Sketch, indent: &str, drawing: &mut String) {
+    drawing.push_str(&node.label);
+    drawing.push_str(if node.red { " (red)\n" } else { "\n" });
+    if node.children.iter().all(Option::is_none) {
+        return;
+    }
+
+    for (i, child) in node.children.iter().enumerate() {
+        let last = i + 1 == node.children.len();
+        drawing.push_str(indent);
+        drawing.push_str(if last { "`-- " } else { "+-- " });
+        match child {
+            Some(child) => {
+                let indent = format!("{}{}", indent, if last { "    " } else { "|   " });
+                outline(child, &indent, drawing);
+            }
+            None => drawing.push_str(".\n"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Style {
+    /// `┌─┴─┐`
+    Unicode,
+    /// `+-+-+`
+    Ascii,
+}
+
+/// How `draw` lays out a tree.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub style: Style,
+    /// show the height after the label of a node, as in `5 h2`
+    pub heights: bool,
+    /// draw red nodes in red with ANSI escapes, otherwise they are marked with a `*`
+    pub color: bool,
+    /// labels longer than this are cut short
+    pub max_label: Option<usize>,
+    /// the nodes below this depth are collapsed into one node per subtree telling their number,
+    /// the root has the depth 0
+    pub max_depth: Option<usize>,
+    /// a drawing wider than this is cut into strips drawn one below the other
+    pub max_width: Option<usize>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout { style: Style::Unicode, heights: false, color: false, max_label: None, max_depth: None, max_width: None }
+    }
+}
+
+/// Draws a tree top-down, every node is centered above its children:
+///
+/// ```text
+///    3
+///  ┌─┴─┐
+///  1   5
+/// ┌┴┐  └┐
+/// 0 2   6
+/// ```
+pub fn draw<W: fmt::Write>(root: Option<&Sketch>, layout: &Layout, out: &mut W) -> fmt::Result {
+    let Some(root) = root else { return out.write_str("(empty)\n") };
+    let block = Block::of(root, 0, layout);
+    let strip = layout.max_width.unwrap_or(block.width).max(1);
+    for (i, start) in (0..block.width).step_by(strip).enumerate() {
+        if i > 0 {
+            out.write_char('\n')?;
+        }
+        for row in &block.rows {
+            let end = row.len().min(start + strip);
+            let cells = row.get(start..end).unwrap_or_default();
+            write_cells(cells, layout, out)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    ch: char,
+    red: bool,
+}
+
+impl Cell {
+    const BLANK: Cell = Cell { ch: ' ', red: false };
+}
+
+fn write_cells<W: fmt::Write>(cells: &[Cell], layout: &Layout, out: &mut W) -> fmt::Result {
+    let end = cells.iter().rposition(|cell| cell.ch != ' ').map_or(0, |i| i + 1);
+    let mut red = false;
+    for cell in &cells[..end] {
+        if layout.color && cell.red != red {
+            out.write_str(if cell.red { "\x1b[31m" } else { "\x1b[0m" })?;
+            red = cell.red;
+        }
+        out.write_char(cell.ch)?;
+    }
+    if red {
+        out.write_str("\x1b[0m")?;
+    }
+    out.write_char('\n')
+}
+
+/// A subtree laid out on a grid, `anchor` is the column of the middle of its root label.
+struct Block {
+    rows: Vec<Vec<Cell>>,
+    width: usize,
+    anchor: usize,
+}
+
+impl Block {
+    fn of(node: &Sketch, depth: usize, layout: &Layout) -> Block {
+        let label = Block::label(node, layout);
+        if node.children.iter().all(Option::is_none) {
+            return Block::single(label);
+        }
+
+        let collapsed;
+        let children: Vec<Option<Block>> = if layout.max_depth.is_some_and(|max| depth >= max) {
+            let hidden = node.size() - 1;
+            collapsed = Sketch::new(format!("{} more", hidden), vec![]);
+            vec![Some(Block::of(&collapsed, depth + 1, layout))]
+        } else {
+            node.children.iter().map(|child| child.as_ref().map(|child| Block::of(child, depth + 1, layout))).collect()
+        };
+
+        // the children side by side, a missing one takes a single column
+        let mut anchors = vec![];
+        let mut x = 0;
+        for child in &children {
+            match child {
+                Some(child) => {
+                    anchors.push((x + child.anchor, true));
+                    x += child.width + 1;
+                }
+                None => {
+                    anchors.push((x, false));
+                    x += 2;
+                }
+            }
+        }
+        let children_width = x - 1;
+        let parent = (anchors[0].0 + anchors[anchors.len() - 1].0) / 2;
+
+        // the label may stick out on either side of the children
+        let start = parent as isize - (label.len() / 2) as isize;
+        let shift = (-start).max(0) as usize;
+        let label_start = (start + shift as isize) as usize;
+        let width = (children_width + shift).max(label_start + label.len());
+
+        let mut rows = vec![vec![Cell::BLANK; width]; 2];
+        rows[0][label_start..label_start + label.len()].copy_from_slice(&label);
+
+        let parent = parent + shift;
+        let present: Vec<usize> = anchors.iter().filter(|(_, present)| *present).map(|(anchor, _)| anchor + shift).collect();
+        let low = present.iter().copied().chain([parent]).min().unwrap();
+        let high = present.iter().copied().chain([parent]).max().unwrap();
+        for (column, cell) in rows[1].iter_mut().enumerate().take(high + 1).skip(low) {
+            let ch = junction(layout.style, column == parent, present.contains(&column), column > low, column < high);
+            *cell = Cell { ch, red: false };
+        }
+
+        let height = children.iter().flatten().map(|child| child.rows.len()).max().unwrap_or(0);
+        for r in 0..height {
+            let mut row = vec![Cell::BLANK; shift];
+            for child in &children {
+                match child {
+                    Some(child) => {
+                        let cells = child.rows.get(r).map_or(&[][..], |row| &row[..]);
+                        row.extend_from_slice(cells);
+                        row.resize(row.len() + child.width - cells.len() + 1, Cell::BLANK);
+                    }
+                    None => row.extend_from_slice(&[Cell::BLANK; 2]),
+                }
+            }
+            row.resize(width, Cell::BLANK);
+            rows.push(row);
+        }
+
+        Block { rows, width, anchor: parent }
+    }
+
+    fn single(label: Vec<Cell>) -> Block {
+        Block { width: label.len(), anchor: label.len() / 2, rows: vec![label] }
+    }
+
+    fn label(node: &Sketch, layout: &Layout) -> Vec<Cell> {
+        let mut text: String = node.label.clone();
+        if let Some(max) = layout.max_label.filter(|&max| text.chars().count() > max) {
+            let ellipsis = if layout.style == Style::Unicode { '…' } else { '~' };
+            text = text.chars().take(max.saturating_sub(1)).chain([ellipsis]).collect();
+        }
+        if node.red && !layout.color {
+            text.push('*');
+        }
+        if let Some(height) = node.height.filter(|_| layout.heights) {
+            text.push_str(&format!(" h{}", height));
+        }
+        text.chars().map(|ch| Cell { ch, red: node.red }).collect()
+    }
+}
+
+/// The character where lines going up, down, left or right meet.
+fn junction(style: Style, up: bool, down: bool, left: bool, right: bool) -> char {
+    if style == Style::Ascii {
+        return match (up || down, left || right) {
+            (true, true) => '+',
+            (true, false) => '|',
+            _ => '-',
+        };
+    }
+    match (up, down, left, right) {
+        (true, true, true, true) => '┼',
+        (true, true, true, false) => '┤',
+        (true, true, false, true) => '├',
+        (true, false, true, true) => '┴',
+        (true, false, true, false) => '┘',
+        (true, false, false, true) => '└',
+        (false, true, true, true) => '┬',
+        (false, true, true, false) => '┐',
+        (false, true, false, true) => '┌',
+        (false, false, _, _) => '─',
+        _ => '│',
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn leaf(label: &str) -> Option<Sketch> {
+        Some(Sketch::new(label.to_string(), vec![None, None]))
+    }
+
+    fn small_tree() -> Sketch {
+        let five = Sketch::new("5".to_string(), vec![None, leaf("6")]);
+        Sketch::new("3".to_string(), vec![
+            Some(Sketch::new("1".to_string(), vec![leaf("0"), leaf("2")])),
+            Some(five),
+        ])
+    }
+
+    fn drawing(root: &Sketch, layout: &Layout) -> String {
+        let mut out = String::new();
+        draw(Some(root), layout, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_ascii() {
+        assert_eq!(ascii(None), "(empty)\n");
+        let expected = "\
+3
++-- 1
+|   +-- 0
+|   `-- 2
+`-- 5
+    +-- .
+    `-- 6
+";
+        assert_eq!(ascii(Some(&small_tree())), expected);
+    }
+
+    #[test]
+    fn test_draw() {
+        let expected = "   3
+ ┌─┴─┐
+ 1   5
+┌┴┐  └┐
+0 2   6
+";
+        assert_eq!(drawing(&small_tree(), &Layout::default()), expected);
+    }
+
+    #[test]
+    fn test_draw_marks() {
+        let mut root = small_tree();
+        root.height = Some(2);
+        root.children[1].as_mut().unwrap().red = true;
+        let layout = Layout { style: Style::Ascii, heights: true, ..Layout::default() };
+        assert_eq!(drawing(&root, &layout).lines().next(), Some(" 3 h2"));
+        assert!(drawing(&root, &layout).contains("5*"));
+
+        let layout = Layout { color: true, ..Layout::default() };
+        assert!(drawing(&root, &layout).contains("\x1b[31m5\x1b[0m"));
+    }
+
+    #[test]
+    fn test_draw_wide_and_deep() {
+        let long = Sketch::new("a rather long label".to_string(), vec![leaf("x"), leaf("y")]);
+        let layout = Layout { max_label: Some(6), ..Layout::default() };
+        assert!(drawing(&long, &layout).starts_with("a rat…\n"));
+        // the label is wider than its children, they are centered below it
+        for line in drawing(&long, &Layout::default()).lines().skip(1) {
+            assert!(line.starts_with("        "), "{:?}", line);
+        }
+
+        // a chain of a hundred nodes
+        let mut chain = Sketch::new("99".to_string(), vec![]);
+        for i in (0..99).rev() {
+            chain = Sketch::new(i.to_string(), vec![None, Some(chain)]);
+        }
+        let layout = Layout { max_depth: Some(2), ..Layout::default() };
+        let out = drawing(&chain, &layout);
+        assert_eq!(out.lines().count(), 7);
+        assert!(out.ends_with("97 more\n"));
+
+        let layout = Layout { max_width: Some(20), ..Layout::default() };
+        let out = drawing(&chain, &layout);
+        assert!(out.lines().all(|line| line.chars().count() <= 20));
+        assert!(out.contains("99"));
+    }
+}
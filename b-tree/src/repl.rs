@@ -0,0 +1,520 @@
+//!
+//! an interactive playground for the trees of this crate
+//!
+//! Pick a kind of tree, change it with `insert` and `delete` and watch its shape after every step.
+//! The commands which were run can be saved as a script and loaded again, which turns a transcript
+//! of a bug report into something to replay.
+//!
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use crate::avl::AvlTree;
+use crate::bst::rs_bst::RsNode;
+use crate::concurrent::ConcurrentBTree;
+use crate::rbt::RbTree;
+use crate::render::{self, Layout, Sketch};
+use crate::stats::Stats;
+
+const HELP: &str = "\
+tree <bst|avl|rbt|btree> [order]   start over with an empty tree, btree takes an order
+insert <key>...                    insert keys, a key is an i32
+delete <key>...                    delete keys
+find <key>                         tell whether a key is present
+range <low> <high>                 list the keys between low and high, both included
+rank <key>                         count the keys less than key
+dump                               draw the tree and list its keys
+draw                               draw the tree top-down with the heights of the nodes
+validate                           check the invariants of the tree
+stats                              show the height, the leaf depths and the memory of the tree
+load <file>                        run the commands of a script, one per line, `#` starts a comment
+save <file>                        write the commands run so far as a script
+help                               show this help
+quit                               leave
+";
+
+/// The operations the REPL needs from a tree, the keys are `i32`.
+trait Playground {
+    fn name(&self) -> String;
+
+    /// Returns `false` if `key` was already present.
+    fn insert(&mut self, key: i32) -> bool;
+
+    /// Returns `false` if `key` wasn't present.
+    fn delete(&mut self, key: i32) -> bool;
+
+    fn find(&self, key: i32) -> bool;
+
+    /// The keys from `low` to `high`, both included, in order.
+    fn range(&self, low: i32, high: i32) -> Vec<i32>;
+
+    fn validate(&self) -> Result<(), String>;
+
+    fn sketch(&self) -> Option<Sketch>;
+
+    fn stats(&self) -> Stats;
+}
+
+impl Playground for RsNode<i32> {
+    fn name(&self) -> String {
+        "bst".to_string()
+    }
+
+    fn insert(&mut self, key: i32) -> bool {
+        let absent = !self.find(key);
+        self.add(key);
+        absent
+    }
+
+    fn delete(&mut self, key: i32) -> bool {
+        self.remove(&key)
+    }
+
+    fn find(&self, key: i32) -> bool {
+        RsNode::find(self, key)
+    }
+
+    fn range(&self, low: i32, high: i32) -> Vec<i32> {
+        self.iter().copied().skip_while(|&key| key < low).take_while(|&key| key <= high).collect()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        RsNode::validate(self)
+    }
+
+    fn sketch(&self) -> Option<Sketch> {
+        RsNode::sketch(self)
+    }
+
+    fn stats(&self) -> Stats {
+        RsNode::stats(self)
+    }
+}
+
+impl Playground for AvlTree<i32> {
+    fn name(&self) -> String {
+        "avl".to_string()
+    }
+
+    fn insert(&mut self, key: i32) -> bool {
+        AvlTree::insert(self, key, ()).is_none()
+    }
+
+    fn delete(&mut self, key: i32) -> bool {
+        self.remove(&key).is_some()
+    }
+
+    fn find(&self, key: i32) -> bool {
+        self.search(&key)
+    }
+
+    fn range(&self, low: i32, high: i32) -> Vec<i32> {
+        let mut keys = vec![];
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        while let Some((&key, _)) = cursor.current().filter(|(&key, _)| key <= high) {
+            keys.push(key);
+            cursor.next();
+        }
+        keys
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        AvlTree::validate(self)
+    }
+
+    fn sketch(&self) -> Option<Sketch> {
+        AvlTree::sketch(self)
+    }
+
+    fn stats(&self) -> Stats {
+        AvlTree::stats(self)
+    }
+}
+
+impl Playground for RbTree<i32> {
+    fn name(&self) -> String {
+        "rbt".to_string()
+    }
+
+    fn insert(&mut self, key: i32) -> bool {
+        RbTree::insert(self, key, ()).is_none()
+    }
+
+    fn delete(&mut self, key: i32) -> bool {
+        self.remove(&key).is_some()
+    }
+
+    fn find(&self, key: i32) -> bool {
+        self.search(&key)
+    }
+
+    fn range(&self, low: i32, high: i32) -> Vec<i32> {
+        let mut keys = vec![];
+        let mut cursor = self.cursor();
+        cursor.seek(&low);
+        while let Some((&key, _)) = cursor.current().filter(|(&key, _)| key <= high) {
+            keys.push(key);
+            cursor.next();
+        }
+        keys
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        RbTree::validate(self)
+    }
+
+    fn sketch(&self) -> Option<Sketch> {
+        RbTree::sketch(self)
+    }
+
+    fn stats(&self) -> Stats {
+        RbTree::stats(self)
+    }
+}
+
+impl Playground for ConcurrentBTree<i32, ()> {
+    fn name(&self) -> String {
+        format!("btree of order {}", self.order())
+    }
+
+    fn insert(&mut self, key: i32) -> bool {
+        ConcurrentBTree::insert(self, key, ()).is_none()
+    }
+
+    fn delete(&mut self, key: i32) -> bool {
+        self.remove(&key).is_some()
+    }
+
+    fn find(&self, key: i32) -> bool {
+        self.contains_key(&key)
+    }
+
+    fn range(&self, low: i32, high: i32) -> Vec<i32> {
+        ConcurrentBTree::range(self, low..=high).into_iter().map(|(key, _)| key).collect()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        ConcurrentBTree::validate(self)
+    }
+
+    fn sketch(&self) -> Option<Sketch> {
+        ConcurrentBTree::sketch(self)
+    }
+
+    fn stats(&self) -> Stats {
+        ConcurrentBTree::stats(self)
+    }
+}
+
+/// What a command did.
+enum Step {
+    /// the tree changed, it is drawn again
+    Changed,
+    Unchanged,
+    Quit,
+}
+
+pub struct Repl {
+    tree: Box<dyn Playground>,
+    /// the commands run so far, `save` writes them out
+    history: Vec<String>,
+    /// the scripts being loaded, the innermost last, a script can't load itself again
+    loading: Vec<PathBuf>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    /// A REPL playing with an empty AVL tree.
+    pub fn new() -> Repl {
+        Repl { tree: Box::new(AvlTree::new()), history: vec![], loading: vec![] }
+    }
+
+    /// Runs the commands read from `input` until it ends or a command quits. With `prompt` a
+    /// prompt is shown before every command.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W, prompt: bool) -> io::Result<()> {
+        if prompt {
+            writeln!(out, "playing with an empty {}, type `help` for the commands", self.tree.name())?;
+        }
+        let mut lines = input.lines();
+        loop {
+            if prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+            let Some(line) = lines.next() else { break };
+            if !self.execute(&line?, out)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one line, a command or a comment. Returns `false` if the command quits.
+    ///
+    /// A command which fails reports the error to `out`, only writing to `out` can fail.
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else { return Ok(true) };
+
+        match self.command(command, args, out)? {
+            Ok(step) => {
+                if !matches!(command, "help" | "load" | "save" | "quit" | "exit") {
+                    self.history.push(line.to_string());
+                }
+                if let Step::Changed = step {
+                    write!(out, "{}", render::ascii(self.tree.sketch().as_ref()))?;
+                }
+                Ok(!matches!(step, Step::Quit))
+            }
+            Err(message) => {
+                writeln!(out, "error: {}", message)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn command<W: Write>(&mut self, command: &str, args: &[&str], out: &mut W) -> io::Result<Result<Step, String>> {
+        let step = match (command, args) {
+            ("tree", [kind, rest @ ..]) => {
+                let tree: Box<dyn Playground> = match (*kind, rest) {
+                    ("bst", []) => Box::new(RsNode::new()),
+                    ("avl", []) => Box::new(AvlTree::new()),
+                    ("rbt", []) => Box::new(RbTree::new()),
+                    ("btree", []) => Box::new(ConcurrentBTree::new()),
+                    ("btree", [order]) => match order.parse::<usize>() {
+                        Ok(order) if order >= 4 => Box::new(ConcurrentBTree::with_order(order)),
+                        _ => return Ok(Err(format!("`{}` is not an order of at least 4", order))),
+                    },
+                    _ => return Ok(Err(format!("`{}` is not a kind of tree, try bst, avl, rbt or btree", args.join(" ")))),
+                };
+                self.tree = tree;
+                writeln!(out, "playing with an empty {}", self.tree.name())?;
+                Step::Changed
+            }
+            ("insert", keys) | ("delete", keys) if !keys.is_empty() => {
+                let keys = match parse_keys(keys) {
+                    Ok(keys) => keys,
+                    Err(message) => return Ok(Err(message)),
+                };
+                for key in keys {
+                    match command {
+                        "insert" if self.tree.insert(key) => writeln!(out, "inserted {}", key)?,
+                        "insert" => writeln!(out, "{} is already present", key)?,
+                        _ if self.tree.delete(key) => writeln!(out, "deleted {}", key)?,
+                        _ => writeln!(out, "{} is not present", key)?,
+                    }
+                }
+                Step::Changed
+            }
+            ("find", [_]) | ("rank", [_]) | ("range", [_, _]) => {
+                let keys = match parse_keys(args) {
+                    Ok(keys) => keys,
+                    Err(message) => return Ok(Err(message)),
+                };
+                match (command, keys.as_slice()) {
+                    ("find", &[key]) if self.tree.find(key) => writeln!(out, "found {}", key)?,
+                    ("find", &[key]) => writeln!(out, "{} is not present", key)?,
+                    ("rank", &[key]) => {
+                        let rank = key.checked_sub(1).map_or(0, |high| self.tree.range(i32::MIN, high).len());
+                        writeln!(out, "{} keys are less than {}", rank, key)?
+                    }
+                    (_, &[low, high]) => {
+                        let keys = self.tree.range(low, high);
+                        writeln!(out, "{} keys from {} to {}: {}", keys.len(), low, high, join(&keys))?
+                    }
+                    _ => unreachable!("the number of keys was matched above"),
+                }
+                Step::Unchanged
+            }
+            ("dump", []) => {
+                let keys = self.tree.range(i32::MIN, i32::MAX);
+                writeln!(out, "{} with {} keys: {}", self.tree.name(), keys.len(), join(&keys))?;
+                write!(out, "{}", render::ascii(self.tree.sketch().as_ref()))?;
+                Step::Unchanged
+            }
+            ("draw", []) => {
+                let layout = Layout { heights: true, max_depth: Some(8), max_width: Some(120), ..Layout::default() };
+                let mut drawing = String::new();
+                render::draw(self.tree.sketch().as_ref(), &layout, &mut drawing).expect("a string can be written to");
+                write!(out, "{}", drawing)?;
+                Step::Unchanged
+            }
+            ("validate", []) => {
+                match self.tree.validate() {
+                    Ok(()) => writeln!(out, "valid")?,
+                    Err(message) => writeln!(out, "invalid: {}", message)?,
+                }
+                Step::Unchanged
+            }
+            ("stats", []) => {
+                write!(out, "{}", self.tree.stats())?;
+                Step::Unchanged
+            }
+            ("load", [_, ..]) => return self.load(&args.join(" "), out),
+            ("save", [_, ..]) => {
+                let mut script = self.history.join("\n");
+                script.push('\n');
+                if let Err(err) = fs::write(args.join(" "), script) {
+                    return Ok(Err(format!("can't write {}: {}", args.join(" "), err)));
+                }
+                writeln!(out, "saved {} commands", self.history.len())?;
+                Step::Unchanged
+            }
+            ("help", []) => {
+                write!(out, "{}", HELP)?;
+                Step::Unchanged
+            }
+            ("quit", []) | ("exit", []) => Step::Quit,
+            _ => return Ok(Err(format!("can't run `{} {}`, type `help` for the commands", command, args.join(" ")))),
+        };
+        Ok(Ok(step))
+    }
+
+    /// Runs a script, every command is echoed before its output. A script loading itself, directly
+    /// or through other scripts, is an error instead of an endless recursion.
+    fn load<W: Write>(&mut self, path: &str, out: &mut W) -> io::Result<Result<Step, String>> {
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(err) => return Ok(Err(format!("can't read {}: {}", path, err))),
+        };
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        if self.loading.contains(&canonical) {
+            return Ok(Err(format!("{} is already loading", path)));
+        }
+        self.loading.push(canonical);
+        let step = self.run_script(&script, out);
+        self.loading.pop();
+        step.map(Ok)
+    }
+
+    fn run_script<W: Write>(&mut self, script: &str, out: &mut W) -> io::Result<Step> {
+        for line in script.lines().filter(|line| !line.trim().is_empty()) {
+            writeln!(out, "> {}", line)?;
+            if !self.execute(line, out)? {
+                return Ok(Step::Quit);
+            }
+        }
+        Ok(Step::Unchanged)
+    }
+}
+
+fn parse_keys(words: &[&str]) -> Result<Vec<i32>, String> {
+    words
+        .iter()
+        .map(|word| word.parse().map_err(|_| format!("`{}` is not a key, keys are i32", word)))
+        .collect()
+}
+
+fn join(keys: &[i32]) -> String {
+    keys.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, script: &str) -> String {
+        let mut out = vec![];
+        repl.run(script.as_bytes(), &mut out, false).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut repl = Repl::new();
+        let out = run(&mut repl, "insert 2 1 3\ninsert 3\nfind 1\nrank 3\nrange 2 9\ndelete 1 7\nvalidate\n");
+        let expected = "\
+inserted 2
+inserted 1
+inserted 3
+2
++-- 1
+`-- 3
+3 is already present
+2
++-- 1
+`-- 3
+found 1
+2 keys are less than 3
+2 keys from 2 to 9: 2 3
+deleted 1
+7 is not present
+2
++-- .
+`-- 3
+valid
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_every_kind() {
+        for kind in ["bst", "avl", "rbt", "btree 4"] {
+            let mut repl = Repl::new();
+            let mut script = format!("tree {}\n", kind);
+            for key in [5, 3, 8, 1, 4, 7, 9, 2, 6, 0] {
+                script.push_str(&format!("insert {}\n", key));
+            }
+            script.push_str("delete 5 0 9\nrange 2 7\nrank 4\ndraw\nstats\nvalidate\n");
+            let out = run(&mut repl, &script);
+            assert!(out.contains("5 keys from 2 to 7: 2 3 4 6 7"), "{}: {}", kind, out);
+            assert!(out.contains("3 keys are less than 4"), "{}: {}", kind, out);
+            assert!(out.contains("\nmemory: "), "{}: {}", kind, out);
+            assert!(out.ends_with("valid\n"), "{}: {}", kind, out);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut repl = Repl::new();
+        let out = run(&mut repl, "insert x\ntree oak\nfrobnicate\nload /nonexistent/script\n# a comment\nquit\ninsert 1\n");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| line.starts_with("error: ")));
+        assert!(repl.history.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("tiny-tree-repl-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut repl = Repl::new();
+        let played = run(&mut repl, &format!("tree rbt\ninsert 1 2 3\ndelete 2\ndump\nsave {}\n", path));
+        assert!(played.ends_with("saved 4 commands\n"));
+
+        let mut replay = Repl::new();
+        let replayed = run(&mut replay, &format!("load {}\n", path));
+        std::fs::remove_file(path).unwrap();
+        // the replay echoes the commands before their output
+        let echoed: String = played.lines().filter(|line| !line.starts_with("saved")).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(replayed.lines().filter(|line| !line.starts_with("> ")).collect::<Vec<_>>().join("\n") + "\n", echoed);
+        assert_eq!(replay.history, repl.history);
+    }
+
+    #[test]
+    fn test_recursive_load() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("tiny-tree-repl-first-{}.txt", std::process::id()));
+        let second = dir.join(format!("tiny-tree-repl-second-{}.txt", std::process::id()));
+        let (first, second) = (first.to_str().unwrap(), second.to_str().unwrap());
+        std::fs::write(first, format!("insert 1\nload {}\nload {}\n", first, second)).unwrap();
+        std::fs::write(second, format!("insert 2\nload {}\n", first)).unwrap();
+
+        let mut repl = Repl::new();
+        let out = run(&mut repl, &format!("load {}\nfind 2\nload {}\n", first, first));
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+        let errors: Vec<&str> = out.lines().filter(|line| line.starts_with("error: ")).collect();
+        assert_eq!(errors, vec![format!("error: {} is already loading", first); 4]);
+        assert!(out.contains("found 2"));
+        assert!(repl.loading.is_empty());
+    }
+}
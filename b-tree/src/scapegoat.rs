@@ -0,0 +1,485 @@
+//!
+//! an implementation for Scapegoat Tree
+//!
+//! https://en.wikipedia.org/wiki/Scapegoat_tree
+//!
+//! A scapegoat tree keeps nothing in its nodes but the key, the data and the two children. Instead
+//! of rebalancing on every change it lets the tree drift and rebuilds a whole subtree into a
+//! perfectly balanced one once the tree gets too deep:
+//!
+//! - after an insertion deeper than log_{1/α}(n), one of the ancestors of the new node, the
+//!   scapegoat, has a child holding more than α of its nodes, the subtree of the scapegoat is
+//!   rebuilt;
+//! - after deletions have shrunk the tree below α of its largest size, the whole tree is rebuilt.
+//!
+//! α is between 1/2 and 1: a lower α keeps the tree closer to balanced at the price of more
+//! rebuilds. Both operations take O(log n) amortized time, searches O(log n) in the worst case.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::map::OrderedMap;
+use crate::path::{self, Descend, Path};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+
+const DEFAULT_ALPHA: f64 = 0.7;
+
+type ScapegoatBoxOption<T, V> = Option<Box<Node<T, V>>>;
+
+struct Node<T, V> {
+    key: T,
+    data: V,
+    left: ScapegoatBoxOption<T, V>,
+    right: ScapegoatBoxOption<T, V>,
+}
+
+impl<T, V> Descend for Node<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+/// A scapegoat tree owning an optional root.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The keys are ordered by the
+/// comparator `C`, their natural order by default.
+pub struct ScapegoatTree<T, V = (), C = Natural> {
+    root: ScapegoatBoxOption<T, V>,
+    len: usize,
+    /// the largest length since the whole tree was last rebuilt
+    max_len: usize,
+    alpha: f64,
+    cmp: C,
+}
+
+impl<T, V, C: Default> Default for ScapegoatTree<T, V, C> {
+    fn default() -> Self {
+        ScapegoatTree { root: None, len: 0, max_len: 0, alpha: DEFAULT_ALPHA, cmp: C::default() }
+    }
+}
+
+impl<T: Ord, V> ScapegoatTree<T, V> {
+    pub fn new() -> ScapegoatTree<T, V> {
+        ScapegoatTree::with_alpha(DEFAULT_ALPHA)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `alpha` isn't at least 0.5 and less than 1.
+    pub fn with_alpha(alpha: f64) -> ScapegoatTree<T, V> {
+        ScapegoatTree::with_comparator_and_alpha(Natural, alpha)
+    }
+}
+
+impl<T, V, C: Compare<T>> ScapegoatTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> ScapegoatTree<T, V, C> {
+        ScapegoatTree::with_comparator_and_alpha(cmp, DEFAULT_ALPHA)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `alpha` isn't at least 0.5 and less than 1.
+    pub fn with_comparator_and_alpha(cmp: C, alpha: f64) -> ScapegoatTree<T, V, C> {
+        assert!((0.5..1.0).contains(&alpha), "alpha must be at least 0.5 and less than 1");
+        ScapegoatTree { root: None, len: 0, max_len: 0, alpha, cmp }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn search(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&mut n.data),
+                Ordering::Less => &mut n.left,
+                Ordering::Greater => &mut n.right,
+            }
+        }
+        None
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        match self.find(&key) {
+            Ok(path) => Some(mem::replace(&mut node_at(&mut self.root, &path).data, data)),
+            Err(path) => {
+                self.attach(path, key, data);
+                None
+            }
+        }
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        let path = self.find(key).ok()?;
+        let slot = slot_at(&mut self.root, &path);
+        let mut node = slot.take().expect("the key was found");
+        *slot = match (node.left.take(), node.right.take()) {
+            (None, child) | (child, None) => child,
+            (Some(left), Some(right)) => {
+                // the smallest node on the right takes the place of the removed one
+                let mut right = Some(right);
+                let mut successor = take_min(&mut right);
+                successor.left = Some(left);
+                successor.right = right;
+                Some(successor)
+            }
+        };
+
+        self.len -= 1;
+        if (self.len as f64) < self.alpha * self.max_len as f64 {
+            rebuild(&mut self.root, self.len);
+            self.max_len = self.len;
+        }
+        Some(node.data)
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { nodes: path::InOrder::new(self.root.as_deref()) }
+    }
+
+    /// Checks the order of the keys, the length and that the tree isn't deeper than α allows.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut previous = None;
+        let mut count = 0;
+        for (key, _) in self.iter() {
+            if previous.is_some_and(|previous| self.cmp.compare(previous, key) != Ordering::Less) {
+                return Err(format!("the keys at {} and {} are out of order", count - 1, count));
+            }
+            previous = Some(key);
+            count += 1;
+        }
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, count));
+        }
+
+        // every node was no deeper than that when it was inserted or rebuilt, deletions and a
+        // smaller length since then don't make it deeper
+        let depth = self.stats().height.saturating_sub(1);
+        if depth > self.max_depth(self.max_len) {
+            return Err(format!("a node has the depth {}, too deep for alpha {}", depth, self.alpha));
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree. A node holds nothing but its key, its data and its children.
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root.as_deref(), mem::size_of::<Node<T, V>>(), |_| ());
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// The path to the node of `key`, or to the empty slot where it belongs.
+    fn find(&self, key: &T) -> Result<Path, Path> {
+        let mut path = Path::new();
+        let mut node = &self.root;
+        while let Some(n) = node {
+            let left = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Ok(path),
+                Ordering::Less => true,
+                Ordering::Greater => false,
+            };
+            path.push_back(left);
+            node = if left { &n.left } else { &n.right };
+        }
+        Err(path)
+    }
+
+    /// The deepest a new node may be, log_{1/α}(len), counting the edges from the root.
+    fn max_depth(&self, len: usize) -> usize {
+        if len <= 1 {
+            return 0;
+        }
+        // the largest depth with (1/α)^depth <= len, without the logarithms of `std`
+        let (mut depth, mut size) = (0, 1.0 / self.alpha);
+        while size <= len as f64 {
+            depth += 1;
+            size /= self.alpha;
+        }
+        depth
+    }
+
+    /// Adds a new node at the empty slot `path` leads to and rebuilds the subtree of the scapegoat
+    /// if the node is too deep. Returns the path to the new node.
+    fn attach(&mut self, mut path: Path, key: T, data: V) -> Path {
+        *slot_at(&mut self.root, &path) = Some(Box::new(Node { key, data, left: None, right: None }));
+        self.len += 1;
+        self.max_len = self.max_len.max(self.len);
+        if path.len() <= self.max_depth(self.len) {
+            return path;
+        }
+
+        // climb up from the new node, counting the nodes of the subtrees and the position of the
+        // new node among them, until a child holds more than alpha of the nodes of its parent
+        let mut size = 1;
+        let mut index = 0;
+        while let Some(left) = path.pop_back() {
+            let parent = node_at(&mut self.root, &path);
+            let sibling = count(if left { &parent.right } else { &parent.left });
+            if !left {
+                index += sibling + 1;
+            }
+            let parent_size = size + sibling + 1;
+            if size as f64 > self.alpha * parent_size as f64 {
+                rebuild(slot_at(&mut self.root, &path), parent_size);
+                path.extend(balanced_path(parent_size, index));
+                return path;
+            }
+            size = parent_size;
+        }
+
+        // there is always a scapegoat, unless the rounding hides it, then the whole tree is rebuilt
+        rebuild(&mut self.root, size);
+        balanced_path(size, index)
+    }
+}
+
+fn slot_at<'a, T, V>(root: &'a mut ScapegoatBoxOption<T, V>, path: &Path) -> &'a mut ScapegoatBoxOption<T, V> {
+    let mut slot = root;
+    for &left in path {
+        let node = slot.as_mut().expect("the path leads through nodes");
+        slot = if left { &mut node.left } else { &mut node.right };
+    }
+    slot
+}
+
+fn node_at<'a, T, V>(root: &'a mut ScapegoatBoxOption<T, V>, path: &Path) -> &'a mut Node<T, V> {
+    slot_at(root, path).as_mut().expect("the path leads to a node")
+}
+
+/// The number of nodes of a subtree.
+fn count<T, V>(node: &ScapegoatBoxOption<T, V>) -> usize {
+    node.as_deref().map_or(0, |node| path::InOrder::new(Some(node)).count())
+}
+
+/// Removes the smallest node below `slot`, which isn't empty.
+fn take_min<T, V>(slot: &mut ScapegoatBoxOption<T, V>) -> Box<Node<T, V>> {
+    let mut slot = slot;
+    while slot.as_ref().expect("the subtree isn't empty").left.is_some() {
+        slot = &mut slot.as_mut().expect("the subtree isn't empty").left;
+    }
+    let mut min = slot.take().expect("the subtree isn't empty");
+    *slot = min.right.take();
+    min
+}
+
+/// Turns the subtree at `slot` of `size` nodes into a perfectly balanced one.
+fn rebuild<T, V>(slot: &mut ScapegoatBoxOption<T, V>, size: usize) {
+    // flatten the nodes in order
+    let mut nodes = Vec::with_capacity(size);
+    let mut stack = vec![];
+    let mut node = slot.take();
+    loop {
+        while let Some(mut n) = node {
+            node = n.left.take();
+            stack.push(n);
+        }
+        let Some(mut n) = stack.pop() else { break };
+        node = n.right.take();
+        nodes.push(n);
+    }
+    debug_assert_eq!(nodes.len(), size);
+    *slot = build(&mut nodes.into_iter(), size);
+}
+
+/// Builds a balanced tree from the next `size` nodes, the middle one becomes the root.
+fn build<T, V>(nodes: &mut impl Iterator<Item = Box<Node<T, V>>>, size: usize) -> ScapegoatBoxOption<T, V> {
+    if size == 0 {
+        return None;
+    }
+    let left = build(nodes, size / 2);
+    let mut root = nodes.next().expect("there are enough nodes");
+    root.left = left;
+    root.right = build(nodes, size - size / 2 - 1);
+    Some(root)
+}
+
+/// The path to the node at `index` of a tree of `size` nodes made by `build`.
+fn balanced_path(mut size: usize, mut index: usize) -> Path {
+    let mut path = Path::new();
+    loop {
+        let middle = size / 2;
+        match index.cmp(&middle) {
+            Ordering::Equal => return path,
+            Ordering::Less => {
+                path.push_back(true);
+                size = middle;
+            }
+            Ordering::Greater => {
+                path.push_back(false);
+                index -= middle + 1;
+                size -= middle + 1;
+            }
+        }
+    }
+}
+
+impl<T: Display, V, C> ScapegoatTree<T, V, C> {
+    /// The shape of the tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V>(node: &Node<T, V>) -> Sketch {
+            let children = vec![node.left.as_deref().map(sketch), node.right.as_deref().map(sketch)];
+            Sketch::new(node.key.to_string(), children)
+        }
+        self.root.as_deref().map(sketch)
+    }
+}
+
+impl<T: Display, V, C> Display for ScapegoatTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for ScapegoatTree<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        ScapegoatTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        ScapegoatTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        ScapegoatTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        ScapegoatTree::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        let path = match self.find(&key) {
+            Ok(path) => path,
+            Err(path) => self.attach(path, key, default()),
+        };
+        &mut node_at(&mut self.root, &path).data
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        ScapegoatTree::iter(self)
+    }
+}
+
+pub struct Iter<'a, T, V> {
+    nodes: path::InOrder<'a, Node<T, V>>,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.key, &node.data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::compare::Reverse;
+    use crate::testing::{check_multiset, check_ordered_map, check_reverse_order};
+    use super::*;
+
+    #[test]
+    fn test_no_balance_data() {
+        // a key, a data and two pointers
+        assert_eq!(mem::size_of::<Node<u64, u64>>(), 4 * mem::size_of::<u64>());
+    }
+
+    #[test]
+    fn test_sorted_insertions() {
+        for alpha in [0.5, 0.6, 0.75, 0.9] {
+            let mut tree = ScapegoatTree::with_alpha(alpha);
+            for key in 0..10_000 {
+                tree.insert(key, ());
+            }
+            assert!(tree.validate().is_ok(), "alpha {}", alpha);
+            let depth = tree.stats().height - 1;
+            assert!(depth <= tree.max_depth(10_000), "alpha {}: depth {}", alpha, depth);
+        }
+    }
+
+    #[test]
+    fn test_deletions_rebuild() {
+        let mut tree = ScapegoatTree::with_alpha(0.6);
+        for key in 0..1000 {
+            tree.insert(key, ());
+        }
+        for key in 0..900 {
+            tree.remove(&key);
+            assert!(tree.len() as f64 >= 0.6 * tree.max_len as f64);
+        }
+        assert!(tree.validate().is_ok());
+        // the last rebuild left a perfectly balanced tree, deletions made it no higher
+        assert!(tree.stats().height <= stats::optimal_height(tree.max_len));
+    }
+
+    #[test]
+    fn test_balanced_path() {
+        let mut root = None;
+        rebuild(&mut root, 0);
+        let mut slot = &mut root;
+        for key in 0..10 {
+            *slot = Some(Box::new(Node { key, data: (), left: None, right: None }));
+            slot = &mut slot.as_mut().unwrap().right;
+        }
+        rebuild(&mut root, 10);
+        for index in 0..10 {
+            let path = balanced_path(10, index);
+            assert_eq!(node_at(&mut root, &path).key, index);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must be at least 0.5")]
+    fn test_alpha_out_of_range() {
+        ScapegoatTree::<i32>::with_alpha(0.4);
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<ScapegoatTree<i32, i32>>().validate().is_ok());
+        assert!(check_reverse_order::<ScapegoatTree<i32, (), Reverse>>().validate().is_ok());
+        check_multiset::<ScapegoatTree<i32, usize>>();
+    }
+}
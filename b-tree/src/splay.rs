@@ -0,0 +1,498 @@
+//!
+//! an implementation for Splay Tree
+//!
+//! https://en.wikipedia.org/wiki/Splay_tree
+//!
+//! A splay tree keeps no balance data at all. Every search, insertion and deletion moves the node it
+//! reaches up to the root, so keys accessed often stay near the top and a skewed workload is served
+//! faster than by a balanced tree. A single operation may take linear time, a sequence of them takes
+//! O(log n) amortized time per operation.
+//!
+//! The splaying is top-down: on the way down from the root the nodes smaller than the key are
+//! gathered into a left tree and the larger ones into a right tree, which become the subtrees of the
+//! node reached at the end.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::path::{self, Descend};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+
+type SplayBoxOption<T, V> = Option<Box<Node<T, V>>>;
+
+struct Node<T, V> {
+    key: T,
+    data: V,
+    left: SplayBoxOption<T, V>,
+    right: SplayBoxOption<T, V>,
+}
+
+impl<T, V> Node<T, V> {
+    fn new(key: T, data: V) -> Box<Node<T, V>> {
+        Box::new(Node { key, data, left: None, right: None })
+    }
+}
+
+impl<T, V> Descend for Node<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+/// A splay tree owning an optional root.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The keys are ordered by the
+/// comparator `C`, their natural order by default. The lookups which splay take `&mut self`, `peek`
+/// looks a key up without changing the tree. For the same reason it isn't an `OrderedMap`, whose
+/// `get` takes `&self`.
+pub struct SplayTree<T, V = (), C = Natural> {
+    root: SplayBoxOption<T, V>,
+    len: usize,
+    cmp: C,
+    spine: Spine<T, V>,
+}
+
+impl<T, V, C: Default> Default for SplayTree<T, V, C> {
+    fn default() -> Self {
+        SplayTree { root: None, len: 0, cmp: C::default(), spine: Spine::default() }
+    }
+}
+
+impl<T: Ord, V> SplayTree<T, V> {
+    pub fn new() -> SplayTree<T, V> {
+        SplayTree::with_comparator(Natural)
+    }
+}
+
+impl<T, V, C: Compare<T>> SplayTree<T, V, C> {
+    /// An empty tree ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> SplayTree<T, V, C> {
+        SplayTree { root: None, len: 0, cmp, spine: Spine::default() }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Tells whether `key` is present and splays it, or the last node reached, to the root.
+    pub fn search(&mut self, key: &T) -> bool {
+        self.splay(key)
+    }
+
+    pub fn get(&mut self, key: &T) -> Option<&V> {
+        if !self.splay(key) {
+            return None;
+        }
+        self.root.as_ref().map(|root| &root.data)
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        if !self.splay(key) {
+            return None;
+        }
+        self.root.as_mut().map(|root| &mut root.data)
+    }
+
+    /// Looks `key` up without splaying, the tree is left as it is.
+    pub fn peek(&self, key: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            }
+        }
+        None
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present. Either way `key` ends
+    /// up at the root.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        if self.splay(&key) {
+            let root = self.root.as_mut().expect("the key was found");
+            return Some(mem::replace(&mut root.data, data));
+        }
+
+        let mut node = Node::new(key, data);
+        if let Some(mut root) = self.root.take() {
+            // the root is the last node reached, `key` goes between it and one of its subtrees
+            if self.cmp.compare(&node.key, &root.key) == Ordering::Less {
+                node.left = root.left.take();
+                node.right = Some(root);
+            } else {
+                node.right = root.right.take();
+                node.left = Some(root);
+            }
+        }
+        self.root = Some(node);
+        self.len += 1;
+        None
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        self.remove_entry(key).map(|(_, data)| data)
+    }
+
+    /// Removes `key`, returns it with its data if it was present.
+    pub fn remove_entry(&mut self, key: &T) -> Option<(T, V)> {
+        if !self.splay(key) {
+            return None;
+        }
+        let mut root = self.root.take().expect("the key was found");
+        self.root = match root.left.take() {
+            None => root.right.take(),
+            Some(left) => {
+                // every key on the left is smaller, splaying for `key` brings the largest one up
+                // and leaves it without a right child
+                let mut left = Some(left);
+                self.spine.splay(&mut left, key, &self.cmp);
+                let mut left = left.expect("the left subtree isn't empty");
+                left.right = root.right.take();
+                Some(left)
+            }
+        };
+        self.len -= 1;
+        let Node { key, data, .. } = *root;
+        Some((key, data))
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { nodes: path::InOrder::new(self.root.as_deref()) }
+    }
+
+    /// Checks the order of the keys and the length.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut previous = None;
+        let mut count = 0;
+        for (key, _) in self.iter() {
+            if previous.is_some_and(|previous| self.cmp.compare(previous, key) != Ordering::Less) {
+                return Err(format!("the keys at {} and {} are out of order", count - 1, count));
+            }
+            previous = Some(key);
+            count += 1;
+        }
+        if count != self.len {
+            return Err(format!("the length is {} but there are {} keys", self.len, count));
+        }
+        Ok(())
+    }
+
+    /// The shape of the tree, which depends on the order of the accesses.
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root.as_deref(), mem::size_of::<Node<T, V>>(), |_| ());
+        let spine = self.spine.smaller.capacity() + self.spine.larger.capacity();
+        stats.memory += mem::size_of::<Self>() + spine * mem::size_of::<Box<Node<T, V>>>();
+        stats
+    }
+
+    /// Splays `key` or the last node reached looking for it to the root, returns whether `key` is
+    /// present.
+    fn splay(&mut self, key: &T) -> bool {
+        self.spine.splay(&mut self.root, key, &self.cmp);
+        self.root.as_ref().is_some_and(|root| self.cmp.compare(key, &root.key) == Ordering::Equal)
+    }
+}
+
+/// The nodes passed on the way down while splaying, the ones smaller than the key and the larger
+/// ones, linked into the left and the right tree once the last node is reached. The tree keeps them
+/// between two splays so that their buffers are allocated once.
+struct Spine<T, V> {
+    smaller: Vec<Box<Node<T, V>>>,
+    larger: Vec<Box<Node<T, V>>>,
+}
+
+impl<T, V> Default for Spine<T, V> {
+    fn default() -> Self {
+        Spine { smaller: vec![], larger: vec![] }
+    }
+}
+
+impl<T, V> Spine<T, V> {
+    /// Top-down splaying of the subtree at `root`.
+    fn splay<C: Compare<T>>(&mut self, root: &mut SplayBoxOption<T, V>, key: &T, cmp: &C) {
+        let Some(mut node) = root.take() else { return };
+        loop {
+            match cmp.compare(key, &node.key) {
+                Ordering::Less => {
+                    let Some(mut child) = node.left.take() else { break };
+                    if cmp.compare(key, &child.key) == Ordering::Less {
+                        // zig-zig, rotate right
+                        node.left = child.right.take();
+                        child.right = Some(node);
+                        node = child;
+                        match node.left.take() {
+                            Some(next) => child = next,
+                            None => break,
+                        }
+                    }
+                    // link right
+                    self.larger.push(node);
+                    node = child;
+                }
+                Ordering::Greater => {
+                    let Some(mut child) = node.right.take() else { break };
+                    if cmp.compare(key, &child.key) == Ordering::Greater {
+                        // zag-zag, rotate left
+                        node.right = child.left.take();
+                        child.left = Some(node);
+                        node = child;
+                        match node.right.take() {
+                            Some(next) => child = next,
+                            None => break,
+                        }
+                    }
+                    // link left
+                    self.smaller.push(node);
+                    node = child;
+                }
+                Ordering::Equal => break,
+            }
+        }
+
+        // assemble: the subtrees of the last node hang below the innermost nodes of both trees
+        let mut left = node.left.take();
+        for mut n in self.smaller.drain(..).rev() {
+            n.right = left;
+            left = Some(n);
+        }
+        let mut right = node.right.take();
+        for mut n in self.larger.drain(..).rev() {
+            n.left = right;
+            right = Some(n);
+        }
+        node.left = left;
+        node.right = right;
+        *root = Some(node);
+    }
+}
+
+impl<T: Display, V, C> SplayTree<T, V, C> {
+    /// The shape of the tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V>(node: &Node<T, V>) -> Sketch {
+            let children = vec![node.left.as_deref().map(sketch), node.right.as_deref().map(sketch)];
+            Sketch::new(node.key.to_string(), children)
+        }
+        self.root.as_deref().map(sketch)
+    }
+}
+
+impl<T: Display, V, C> Display for SplayTree<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+/// Frees the nodes one by one, a splay tree may well be a chain as long as the tree is large.
+impl<T, V, C> Drop for SplayTree<T, V, C> {
+    fn drop(&mut self) {
+        let mut nodes: Vec<Box<Node<T, V>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = nodes.pop() {
+            nodes.extend(node.left.take());
+            nodes.extend(node.right.take());
+        }
+    }
+}
+
+pub struct Iter<'a, T, V> {
+    nodes: path::InOrder<'a, Node<T, V>>,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.key, &node.data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    #[cfg(feature = "std")]
+    use std::time::Instant;
+    #[cfg(feature = "std")]
+    use crate::avl::AvlTree;
+    use crate::compare::Reverse;
+    use crate::testing::Random;
+    use super::*;
+
+    /// Draws `0..n` with the probability of `k` proportional to `1 / (k + 1)^s`.
+    #[cfg(feature = "std")]
+    struct Zipf {
+        cumulative: Vec<f64>,
+        random: Random,
+    }
+
+    #[cfg(feature = "std")]
+    impl Zipf {
+        fn new(n: usize, s: f64, seed: u64) -> Zipf {
+            let mut total = 0.0;
+            let cumulative = (0..n).map(|k| {
+                total += 1.0 / ((k + 1) as f64).powf(s);
+                total
+            }).collect();
+            Zipf { cumulative, random: Random(seed) }
+        }
+
+        fn sample(&mut self) -> i32 {
+            let total = self.cumulative[self.cumulative.len() - 1];
+            let x = (self.random.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * total;
+            self.cumulative.partition_point(|&c| c < x) as i32
+        }
+    }
+
+    #[test]
+    fn test_splayed_to_root() {
+        let mut tree = SplayTree::new();
+        for key in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            assert_eq!(tree.insert(key, key * 10), None);
+            assert_eq!(tree.root.as_ref().unwrap().key, key);
+        }
+        assert_eq!(tree.get(&7), Some(&70));
+        assert_eq!(tree.root.as_ref().unwrap().key, 7);
+        assert_eq!(tree.peek(&4), Some(&40));
+        assert_eq!(tree.root.as_ref().unwrap().key, 7);
+        // a missing key splays the last node reached
+        assert!(!tree.search(&10));
+        assert_eq!(tree.root.as_ref().unwrap().key, 9);
+        assert_eq!(tree.remove(&9), Some(90));
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        let mut tree = SplayTree::new();
+        let mut expected = BTreeMap::new();
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for i in 0..5000 {
+            let key = (random.next_u64() % 300) as i32;
+            match random.next_u64() % 3 {
+                0 => assert_eq!(tree.remove(&key), expected.remove(&key)),
+                1 => assert_eq!(tree.get(&key), expected.get(&key)),
+                _ => assert_eq!(tree.insert(key, i), expected.insert(key, i)),
+            }
+        }
+        assert!(tree.validate().is_ok());
+        assert!(tree.iter().map(|(&key, &data)| (key, data)).eq(expected));
+    }
+
+    #[test]
+    fn test_comparator() {
+        let mut tree: SplayTree<i32, (), Reverse> = SplayTree::default();
+        for key in 0..10 {
+            tree.insert(key, ());
+        }
+        assert!(tree.iter().map(|(&key, _)| key).eq((0..10).rev()));
+        assert!(tree.search(&3));
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_degenerate() {
+        // sorted insertions make a chain
+        let mut tree = SplayTree::new();
+        for key in 0..1_000_000 {
+            tree.insert(key, ());
+        }
+        assert_eq!(tree.stats().height, 1_000_000);
+        // splaying the deepest node roughly halves the depth of the chain
+        assert!(tree.search(&0));
+        assert!(tree.stats().height <= 500_001);
+        assert!(tree.validate().is_ok());
+    }
+
+    #[test]
+    fn test_skewed_access() {
+        let mut tree = SplayTree::new();
+        for key in 0..1000 {
+            tree.insert((key * 7919) % 1000, ());
+        }
+        // a few keys accessed over and over again stay near the root
+        let hot = [17, 402, 555, 690, 731, 868, 901, 999];
+        let mut depth = 0;
+        for round in 0..100 {
+            for key in hot {
+                if round > 0 {
+                    depth += tree.peek_depth(&key);
+                }
+                assert!(tree.search(&key));
+            }
+        }
+        // in a balanced tree of a thousand nodes the average depth of a node is about 8
+        let average = depth as f64 / (99 * hot.len()) as f64;
+        assert!(average < 5.0, "the average depth is {}", average);
+        assert!(tree.validate().is_ok());
+    }
+
+    impl<T, V, C: Compare<T>> SplayTree<T, V, C> {
+        fn peek_depth(&self, key: &T) -> usize {
+            let mut depth = 0;
+            let mut node = &self.root;
+            while let Some(n) = node {
+                node = match self.cmp.compare(key, &n.key) {
+                    Ordering::Equal => break,
+                    Ordering::Less => &n.left,
+                    Ordering::Greater => &n.right,
+                };
+                depth += 1;
+            }
+            depth
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_zipf`.
+    #[cfg(feature = "std")]
+    #[test]
+    #[ignore]
+    fn bench_zipf() {
+        const N: usize = 100_000;
+        const LOOKUPS: usize = 2_000_000;
+        let mut splay = SplayTree::new();
+        let mut avl = AvlTree::new();
+        let mut random = Random(42);
+        for _ in 0..N {
+            let key = (random.next_u64() % (4 * N as u64)) as i32;
+            splay.insert(key, ());
+            avl.insert(key, ());
+        }
+        let keys: Vec<i32> = splay.iter().map(|(&key, _)| key).collect();
+        println!("{} Zipfian lookups among {} keys", LOOKUPS, keys.len());
+
+        for s in [0.8, 1.0, 1.2, 1.5] {
+            let mut zipf = Zipf::new(keys.len(), s, 3);
+            // the most frequent keys are spread over the whole tree
+            let lookups: Vec<i32> = (0..LOOKUPS).map(|_| keys[(zipf.sample() as usize * 7919) % keys.len()]).collect();
+
+            let start = Instant::now();
+            let found = lookups.iter().filter(|key| splay.search(key)).count();
+            let splay_time = start.elapsed();
+            assert_eq!(found, LOOKUPS);
+
+            let start = Instant::now();
+            let found = lookups.iter().filter(|key| avl.search(key)).count();
+            let avl_time = start.elapsed();
+            assert_eq!(found, LOOKUPS);
+
+            println!("s = {}: SplayTree::search {:?}, AvlTree::search {:?}", s, splay_time, avl_time);
+        }
+    }
+}
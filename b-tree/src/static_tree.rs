@@ -0,0 +1,304 @@
+//!
+//! a static search tree in the Eytzinger layout
+//!
+//! https://arxiv.org/abs/1509.05053
+//!
+//! A table built once and only read doesn't need nodes linked by pointers. The keys are laid out in
+//! a single `Vec` in the order of a breadth-first walk of a complete binary tree, the children of
+//! the node `k` are `2k` and `2k + 1`, counting from 1. The first levels, which every search goes
+//! through, share a few cache lines, and the next keys to look at are known early enough for the
+//! processor to fetch them while it compares. The search doesn't branch on the comparisons, it
+//! only descends and works out where it turned right for the last time at the end.
+//!
+//! The values are kept apart in the same order, so the searches only read the keys.
+//!
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Display};
+use core::ops::{Bound, RangeBounds};
+use crate::map::OrderedMap;
+use crate::render::{self, Layout, Sketch};
+
+/// The keys `T` with their values `V`, `()` for a set, in the Eytzinger layout.
+pub struct StaticTree<T, V = ()> {
+    /// the key of the node `k` is at `k - 1`
+    keys: Vec<T>,
+    values: Vec<V>,
+}
+
+impl<T: Ord, V> StaticTree<T, V> {
+    /// A tree of `entries`, which are sorted and unique, in linear time. `validate` reports entries
+    /// out of order.
+    pub fn from_sorted<I: IntoIterator<Item = (T, V)>>(entries: I) -> StaticTree<T, V> {
+        let entries: Vec<(T, V)> = entries.into_iter().collect();
+        let len = entries.len();
+        // the nodes in order, an in-order walk of the complete tree
+        let mut order = Vec::with_capacity(len);
+        let mut stack = vec![];
+        let mut k = 1;
+        loop {
+            while k <= len {
+                stack.push(k);
+                k *= 2;
+            }
+            let Some(node) = stack.pop() else { break };
+            order.push(node);
+            k = 2 * node + 1;
+        }
+
+        let mut slots: Vec<Option<(T, V)>> = (0..len).map(|_| None).collect();
+        for (entry, k) in entries.into_iter().zip(order) {
+            slots[k - 1] = Some(entry);
+        }
+        let (keys, values) = slots.into_iter().map(|slot| slot.expect("every node has an entry")).unzip();
+        StaticTree { keys, values }
+    }
+
+    /// A copy of the entries of `map`.
+    pub fn from_map<M: OrderedMap<T, V>>(map: &M) -> StaticTree<T, V>
+    where
+        T: Clone,
+        V: Clone,
+    {
+        StaticTree::from_sorted(map.iter().map(|(key, value)| (key.clone(), value.clone())))
+    }
+
+    pub fn contains(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let k = self.first(|other| other < key);
+        (k != 0 && self.keys[k - 1] == *key).then(|| &self.values[k - 1])
+    }
+
+    /// The first entry whose key is not less than `key`.
+    pub fn lower_bound(&self, key: &T) -> Option<(&T, &V)> {
+        self.entry(self.first(|other| other < key))
+    }
+
+    /// Visits the entries within `range` in order.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T, V, R> {
+        let k = match range.start_bound() {
+            Bound::Included(start) => self.first(|key| key < start),
+            Bound::Excluded(start) => self.first(|key| key <= start),
+            Bound::Unbounded => self.first(|_| false),
+        };
+        Range { tree: self, k, range }
+    }
+
+    /// Checks that the keys are in order.
+    pub fn validate(&self) -> Result<(), String> {
+        let keys: Vec<&T> = self.iter().map(|(key, _)| key).collect();
+        if keys.len() != self.keys.len() {
+            return Err(format!("{} keys are visited out of {}", keys.len(), self.keys.len()));
+        }
+        match keys.windows(2).position(|pair| pair[0] >= pair[1]) {
+            Some(i) => Err(format!("the keys at {} and {} are out of order", i, i + 1)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T, V> StaticTree<T, V> {
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Visits the entries in order.
+    pub fn iter(&self) -> Range<'_, T, V, (Bound<T>, Bound<T>)> {
+        Range { tree: self, k: self.first(|_| false), range: (Bound::Unbounded, Bound::Unbounded) }
+    }
+
+    /// The node of the first key for which `below` is false, 0 if there is none. The keys for which
+    /// it is true come first.
+    fn first<F: Fn(&T) -> bool>(&self, below: F) -> usize {
+        let mut k = 1;
+        while k <= self.keys.len() {
+            k = 2 * k + usize::from(below(&self.keys[k - 1]));
+        }
+        // the path is in the bits of `k`, a 1 for every turn to the right, the last turn to the
+        // left was made at the node found
+        k >> (k.trailing_ones() + 1)
+    }
+
+    /// The node after `k` in order, 0 after the last one.
+    fn next(&self, k: usize) -> usize {
+        if 2 * k < self.keys.len() {
+            let mut k = 2 * k + 1;
+            while 2 * k <= self.keys.len() {
+                k *= 2;
+            }
+            k
+        } else {
+            // up to the first ancestor this node is on the left of
+            k >> (k.trailing_ones() + 1)
+        }
+    }
+
+    fn entry(&self, k: usize) -> Option<(&T, &V)> {
+        (k != 0).then(|| (&self.keys[k - 1], &self.values[k - 1]))
+    }
+}
+
+impl<T: Display, V> StaticTree<T, V> {
+    /// The shape of the implicit tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display>(keys: &[T], k: usize) -> Option<Sketch> {
+            let key = keys.get(k - 1)?;
+            Some(Sketch::new(key.to_string(), vec![sketch(keys, 2 * k), sketch(keys, 2 * k + 1)]))
+        }
+
+        sketch(&self.keys, 1)
+    }
+}
+
+impl<T: Display, V> Display for StaticTree<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+pub struct Range<'a, T, V, R> {
+    tree: &'a StaticTree<T, V>,
+    /// the next node, 0 at the end
+    k: usize,
+    range: R,
+}
+
+impl<'a, T: Ord, V, R: RangeBounds<T>> Iterator for Range<'a, T, V, R> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.tree.entry(self.k)?;
+        let within = match self.range.end_bound() {
+            Bound::Included(end) => entry.0 <= end,
+            Bound::Excluded(end) => entry.0 < end,
+            Bound::Unbounded => true,
+        };
+        if !within {
+            self.k = 0;
+            return None;
+        }
+        self.k = self.tree.next(self.k);
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    #[cfg(feature = "std")]
+    use std::time::Instant;
+    use crate::avl::AvlTree;
+    use crate::rbt::RbTree;
+    use crate::testing::Random;
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let tree = StaticTree::from_sorted((1..=10).map(|key| (key, key * 10)));
+        //          7
+        //       /     \
+        //      4       9
+        //     / \     / \
+        //    2   6   8   10
+        //   / \  /
+        //  1  3 5
+        assert_eq!(tree.keys, vec![7, 4, 9, 2, 6, 8, 10, 1, 3, 5]);
+        assert_eq!(tree.values[0], 70);
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.sketch().map(|sketch| sketch.size()), Some(10));
+
+        assert!(tree.contains(&3));
+        assert!(!tree.contains(&0));
+        assert_eq!(tree.get(&10), Some(&100));
+        assert_eq!(tree.get(&11), None);
+        assert_eq!(tree.lower_bound(&0), Some((&1, &10)));
+        assert_eq!(tree.lower_bound(&10), Some((&10, &100)));
+        assert_eq!(tree.lower_bound(&11), None);
+        assert!(tree.iter().map(|(&key, _)| key).eq(1..=10));
+
+        let empty: StaticTree<i32> = StaticTree::from_sorted([]);
+        assert_eq!(empty.lower_bound(&0), None);
+        assert_eq!(empty.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let tree = StaticTree::from_sorted([(1, ()), (3, ()), (2, ())]);
+        assert_eq!(tree.validate(), Err("the keys at 1 and 2 are out of order".to_string()));
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for len in 0..150 {
+            let expected: BTreeMap<u64, u64> = (0..len).map(|_| (random.next_u64() % 500, random.next_u64())).collect();
+            let tree = StaticTree::from_sorted(expected.iter().map(|(&key, &value)| (key, value)));
+            assert!(tree.validate().is_ok());
+            assert_eq!(tree.len(), expected.len());
+            for _ in 0..20 {
+                let (a, b) = (random.next_u64() % 520, random.next_u64() % 520);
+                assert_eq!(tree.get(&a), expected.get(&a));
+                assert_eq!(tree.lower_bound(&a), expected.range(a..).next());
+                let (start, end) = (a.min(b), a.max(b));
+                assert!(tree.range(start..end).eq(expected.range(start..end)));
+                assert!(tree.range(start..=end).eq(expected.range(start..=end)));
+                assert!(tree.range(..end).eq(expected.range(..end)));
+                let bounds = (Bound::Excluded(start), Bound::Included(end));
+                assert!(tree.range(bounds).eq(expected.range(bounds)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_map() {
+        let mut avl = AvlTree::new();
+        let mut rbt = RbTree::new();
+        for key in [5, 3, 8, 1, 4, 9, 7] {
+            avl.insert(key, key * 2);
+            rbt.insert(key, key * 2);
+        }
+        let from_avl = StaticTree::from_map(&avl);
+        let from_rbt = StaticTree::from_map(&rbt);
+        assert!(from_avl.iter().eq(avl.iter()));
+        assert!(from_rbt.iter().eq(rbt.iter()));
+        assert_eq!(from_avl.range(2..8).map(|(&key, _)| key).collect::<Vec<_>>(), vec![3, 4, 5, 7]);
+    }
+
+    /// Looks up random keys, half of them present, in the static tree and in the AVL and red-black
+    /// trees, run it with `cargo test --release -- --ignored --nocapture bench_search`.
+    #[cfg(feature = "std")]
+    #[test]
+    #[ignore]
+    fn bench_search() {
+        const LOOKUPS: usize = 2_000_000;
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for n in [1_000, 100_000, 1_000_000] {
+            let entries: Vec<(u64, ())> = (0..n).map(|key| (2 * key, ())).collect();
+            let avl = AvlTree::from_sorted(entries.clone());
+            let rbt = RbTree::from_sorted(entries.clone());
+            let tree = StaticTree::from_sorted(entries);
+            let lookups: Vec<u64> = (0..LOOKUPS).map(|_| random.next_u64() % (2 * n)).collect();
+
+            let start = Instant::now();
+            let found = lookups.iter().filter(|key| tree.contains(key)).count();
+            let static_time = start.elapsed();
+            let start = Instant::now();
+            assert_eq!(lookups.iter().filter(|key| avl.search(key)).count(), found);
+            let avl_time = start.elapsed();
+            let start = Instant::now();
+            assert_eq!(lookups.iter().filter(|key| rbt.search(key)).count(), found);
+            let rbt_time = start.elapsed();
+            println!("{} keys, {} lookups: StaticTree::contains {:?}, AvlTree::search {:?}, RbTree::search {:?}",
+                     n, LOOKUPS, static_time, avl_time, rbt_time);
+        }
+    }
+}
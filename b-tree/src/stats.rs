@@ -0,0 +1,165 @@
+//!
+//! statistics about the shape of a tree
+//!
+//! Every tree has a `stats()` method, the figures shared by all of them are collected here. A leaf
+//! is a node without children, the root is at depth 0 and the height counts the levels, so a single
+//! node has the height 1 and the height compares with `optimal_height`.
+//!
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::path::Descend;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Stats {
+    pub nodes: usize,
+    pub height: usize,
+    pub leaves: usize,
+    pub min_leaf_depth: usize,
+    pub max_leaf_depth: usize,
+    total_leaf_depth: usize,
+    /// how many nodes have each balance factor, the height of the left subtree minus the height of
+    /// the right one, for the trees keeping the heights of their nodes
+    pub balance: Option<BTreeMap<i32, usize>>,
+    /// the number of black nodes on every path from the root down to a leaf, for red-black trees
+    pub black_height: Option<usize>,
+    /// for red-black trees
+    pub red_nodes: Option<usize>,
+    /// an estimate in bytes of the memory taken by the tree and its nodes, not counting what the
+    /// keys and values own on the heap
+    pub memory: usize,
+}
+
+/// The least height of a binary tree with `n` nodes, ⌈log2(n + 1)⌉.
+pub fn optimal_height(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()) as usize
+}
+
+impl Stats {
+    /// Counts a node found at `depth`.
+    pub(crate) fn visit(&mut self, depth: usize, leaf: bool) {
+        self.nodes += 1;
+        self.height = self.height.max(depth + 1);
+        if leaf {
+            self.min_leaf_depth = if self.leaves == 0 { depth } else { self.min_leaf_depth.min(depth) };
+            self.max_leaf_depth = self.max_leaf_depth.max(depth);
+            self.total_leaf_depth += depth;
+            self.leaves += 1;
+        }
+    }
+
+    pub fn avg_leaf_depth(&self) -> f64 {
+        if self.leaves == 0 {
+            return 0.0;
+        }
+        self.total_leaf_depth as f64 / self.leaves as f64
+    }
+
+    /// The share of red nodes, for red-black trees.
+    pub fn red_ratio(&self) -> Option<f64> {
+        let red = self.red_nodes?;
+        Some(if self.nodes == 0 { 0.0 } else { red as f64 / self.nodes as f64 })
+    }
+
+    /// The least height a binary tree with as many nodes could have.
+    pub fn optimal_height(&self) -> usize {
+        optimal_height(self.nodes)
+    }
+
+    /// How many levels the tree has beyond the optimal height.
+    pub fn height_excess(&self) -> usize {
+        self.height.saturating_sub(self.optimal_height())
+    }
+
+    /// The height divided by the optimal height, 1 for a perfectly balanced tree.
+    pub fn height_ratio(&self) -> f64 {
+        match self.optimal_height() {
+            0 => 1.0,
+            optimal => self.height as f64 / optimal as f64,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "nodes: {}", self.nodes)?;
+        writeln!(f, "height: {} (optimal {}, ratio {:.2})", self.height, self.optimal_height(), self.height_ratio())?;
+        writeln!(
+            f,
+            "leaf depth: min {}, avg {:.2}, max {} over {} leaves",
+            self.min_leaf_depth,
+            self.avg_leaf_depth(),
+            self.max_leaf_depth,
+            self.leaves
+        )?;
+        if let Some(balance) = &self.balance {
+            let factors: Vec<String> = balance.iter().map(|(factor, count)| format!("{:+}: {}", factor, count)).collect();
+            writeln!(f, "balance factors: {}", factors.join(", "))?;
+        }
+        if let Some(black_height) = self.black_height {
+            writeln!(f, "black height: {}", black_height)?;
+        }
+        if let (Some(red), Some(ratio)) = (self.red_nodes, self.red_ratio()) {
+            writeln!(f, "red nodes: {} ({:.1}%)", red, ratio * 100.0)?;
+        }
+        writeln!(f, "memory: {} bytes", self.memory)
+    }
+}
+
+/// Collects the statistics of a binary tree, `visit` is called on every node as well. `node_size`
+/// is the memory taken by one node, the caller adds the memory of whatever holds the root.
+pub(crate) fn binary<N: Descend>(root: Option<&N>, node_size: usize, mut visit: impl FnMut(&N)) -> Stats {
+    let mut stats = Stats::default();
+    // a stack rather than recursion, a degenerate tree is as deep as it is large
+    let mut nodes: Vec<(&N, usize)> = root.into_iter().map(|root| (root, 0)).collect();
+    while let Some((node, depth)) = nodes.pop() {
+        let (left, right) = (node.descend(true), node.descend(false));
+        stats.visit(depth, left.is_none() && right.is_none());
+        visit(node);
+        nodes.extend(right.map(|right| (right, depth + 1)));
+        nodes.extend(left.map(|left| (left, depth + 1)));
+    }
+    stats.memory = stats.nodes * node_size;
+    stats
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimal_height() {
+        let heights: Vec<usize> = (0..9).map(optimal_height).collect();
+        assert_eq!(heights, vec![0, 1, 2, 2, 3, 3, 3, 3, 4]);
+        assert_eq!(optimal_height(1_000_000), 20);
+    }
+
+    #[test]
+    fn test_visit() {
+        //   0
+        //  / \
+        // 1   1
+        //      \
+        //       2
+        let mut stats = Stats::default();
+        for (depth, leaf) in [(0, false), (1, true), (1, false), (2, true)] {
+            stats.visit(depth, leaf);
+        }
+        assert_eq!((stats.nodes, stats.height, stats.leaves), (4, 3, 2));
+        assert_eq!((stats.min_leaf_depth, stats.max_leaf_depth), (1, 2));
+        assert_eq!(stats.avg_leaf_depth(), 1.5);
+        assert_eq!(stats.height_excess(), 0);
+        assert_eq!(stats.red_ratio(), None);
+
+        // a chain of three nodes
+        let mut stats = Stats::default();
+        for (depth, leaf) in [(0, false), (1, false), (2, true)] {
+            stats.visit(depth, leaf);
+        }
+        assert_eq!(stats.height_excess(), 1);
+        assert_eq!(stats.height_ratio(), 1.5);
+    }
+}
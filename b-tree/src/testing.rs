@@ -0,0 +1,102 @@
+//!
+//! helpers shared by the tests of the trees
+//!
+//! The random keys come from one seeded generator so that a failure can be replayed, and the
+//! conformance checks of `OrderedMap` run the same operations on every map against a `BTreeMap`.
+//! The tests of a tree only add what is specific to its structure.
+//!
+
+use std::collections::BTreeMap;
+use crate::map::OrderedMap;
+use crate::multiset::Multiset;
+
+/// A xorshift generator, the state must not be 0.
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Runs the operations of `OrderedMap` on an `M` in the natural order: a few keys one by one, then
+/// random ones compared with a `BTreeMap`. Returns the map for the checks of its structure.
+pub fn check_ordered_map<M: OrderedMap<i32, i32>>() -> M {
+    let mut map = M::default();
+    for key in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+        assert_eq!(map.insert(key, key * 10), None);
+    }
+    assert_eq!(map.insert(4, 0), Some(40));
+    assert_eq!(map.get(&4), Some(&0));
+    *map.get_mut(&1).unwrap() += 1;
+    assert_eq!(map.get(&1), Some(&11));
+    assert_eq!(map.get(&10), None);
+    assert_eq!(map.get_mut(&10), None);
+
+    assert_eq!(map.remove(&5), Some(50));
+    assert_eq!(map.remove(&5), None);
+    assert_eq!(map.remove(&9), Some(90));
+    assert!(map.iter().map(|(&key, _)| key).eq([1, 2, 3, 4, 6, 7, 8]));
+
+    // a key already present keeps its data and the default isn't made
+    *map.get_or_insert_with(3, || unreachable!("3 is present")) += 1;
+    assert_eq!(*map.get_or_insert_with(0, || -1), -1);
+    assert_eq!(map.get(&3), Some(&31));
+
+    let mut expected: BTreeMap<i32, i32> = map.iter().map(|(&key, &data)| (key, data)).collect();
+    let mut random = Random(0x2545_f491_4f6c_dd1d);
+    for i in 0..5000 {
+        let key = (random.next_u64() % 300) as i32;
+        match random.next_u64() % 4 {
+            0 => assert_eq!(map.remove(&key), expected.remove(&key)),
+            1 => assert_eq!(map.get(&key), expected.get(&key)),
+            2 => assert_eq!(*map.get_or_insert_with(key, || i), *expected.entry(key).or_insert(i)),
+            _ => assert_eq!(map.insert(key, i), expected.insert(key, i)),
+        }
+    }
+    assert!(map.iter().map(|(&key, &data)| (key, data)).eq(expected));
+    map
+}
+
+/// Fills an `M` whose comparator reverses the natural order, the keys are visited from the
+/// greatest. Returns the map for the checks of its structure.
+pub fn check_reverse_order<M: OrderedMap<i32, ()>>() -> M {
+    let mut map = M::default();
+    for key in 0..100 {
+        assert_eq!(map.insert((key * 37) % 100, ()), None);
+    }
+    assert!(map.iter().map(|(&key, _)| key).eq((0..100).rev()));
+    map
+}
+
+/// Counts random duplicates in a `Multiset` over an `M` and compares the counts with a `BTreeMap`.
+pub fn check_multiset<M: OrderedMap<i32, usize>>() {
+    let mut multiset: Multiset<i32, M> = Multiset::new();
+    let mut expected = BTreeMap::new();
+    let mut random = Random(7);
+    for _ in 0..2000 {
+        let value = (random.next_u64() % 50) as i32;
+        if random.next_u64().is_multiple_of(3) {
+            let removed = expected.get_mut(&value).map(|count: &mut usize| *count -= 1).is_some();
+            if expected.get(&value) == Some(&0) {
+                expected.remove(&value);
+            }
+            assert_eq!(multiset.remove_one(&value), removed);
+        } else {
+            let count = expected.entry(value).or_insert(0);
+            *count += 1;
+            assert_eq!(multiset.insert(value), *count);
+        }
+    }
+
+    assert_eq!(multiset.len(), expected.values().sum::<usize>());
+    assert!(multiset.counts().map(|(&value, &count)| (value, count)).eq(expected.clone()));
+    let repeated = expected.iter().flat_map(|(&value, &count)| std::iter::repeat_n(value, count));
+    assert!(multiset.iter().copied().eq(repeated));
+    for value in 0..50 {
+        assert_eq!(multiset.count(&value), expected.get(&value).copied().unwrap_or(0));
+    }
+}
@@ -0,0 +1,280 @@
+//!
+//! lazy deletion over an ordered map
+//!
+//! https://en.wikipedia.org/wiki/Lazy_deletion
+//!
+//! A removal only marks the entry dead, a `None` in place of its value found with a single walk
+//! down the tree, so it takes O(log n) and never rebalances. Lookups and iterators skip the dead
+//! entries and an insertion of a dead key brings the entry back to life.
+//!
+//! The dead entries still take room and lengthen the searches, `compact` drops them all and
+//! rebuilds the tree balanced in linear time. It runs on its own once the dead entries exceed a
+//! fraction of all the entries, so its cost spreads over the removals which made it necessary.
+//!
+
+use alloc::format;
+use alloc::string::String;
+use core::marker::PhantomData;
+use crate::avl::AvlTree;
+use crate::compare::Compare;
+use crate::map::OrderedMap;
+use crate::rbt::RbTree;
+
+/// An ordered map which can drop many entries at once and rebuild itself balanced.
+pub trait Rebuild<K, V>: OrderedMap<K, V> {
+    /// Keeps the entries `f` accepts, in linear time.
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F);
+
+    fn validate(&self) -> Result<(), String>;
+}
+
+impl<K, V, C: Compare<K> + Default> Rebuild<K, V> for AvlTree<K, V, C> {
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        AvlTree::retain(self, f)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        AvlTree::validate(self)
+    }
+}
+
+impl<K, V, C: Compare<K> + Default> Rebuild<K, V> for RbTree<K, V, C> {
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F) {
+        RbTree::retain(self, f)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        RbTree::validate(self)
+    }
+}
+
+/// A map from `K` to `V` with lazy deletion, on top of the map `M` of live values and tombstones.
+pub struct Tombstones<K, V, M: Rebuild<K, Option<V>> = AvlTree<K, Option<V>>> {
+    map: M,
+    live: usize,
+    dead: usize,
+    /// the fraction of dead entries which triggers a compaction, never if `None`
+    threshold: Option<f64>,
+    compactions: usize,
+    entries: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, M: Rebuild<K, Option<V>>> Default for Tombstones<K, V, M> {
+    fn default() -> Self {
+        Tombstones::with_threshold(Some(0.5))
+    }
+}
+
+impl<K: Ord, V> Tombstones<K, V> {
+    /// An empty map compacted once half of its entries are dead.
+    pub fn new() -> Tombstones<K, V> {
+        Tombstones::default()
+    }
+}
+
+impl<K, V, M: Rebuild<K, Option<V>>> Tombstones<K, V, M> {
+    /// An empty map compacted once the dead entries are more than `threshold` of all the entries,
+    /// only by `compact` if `threshold` is `None`.
+    pub fn with_threshold(threshold: Option<f64>) -> Tombstones<K, V, M> {
+        Tombstones { map: M::default(), live: 0, dead: 0, threshold, compactions: 0, entries: PhantomData }
+    }
+
+    /// The number of live entries.
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// The number of dead entries still in the tree.
+    pub fn dead(&self) -> usize {
+        self.dead
+    }
+
+    /// The number of compactions so far, either automatic or by `compact`.
+    pub fn compactions(&self) -> usize {
+        self.compactions
+    }
+
+    /// The underlying tree, the dead entries included.
+    pub fn map(&self) -> &M {
+        &self.map
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.map.get_mut(key)?.as_mut()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `key` with `value`, returns the old value if `key` was live. A dead entry of `key`
+    /// comes back to life.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.map.insert(key, Some(value)) {
+            Some(Some(old)) => return Some(old),
+            Some(None) => self.dead -= 1,
+            None => {}
+        }
+        self.live += 1;
+        None
+    }
+
+    /// Marks the entry of `key` dead and returns its value, compacts the map if the dead entries
+    /// reach the threshold.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.map.get_mut(key)?.take()?;
+        self.live -= 1;
+        self.dead += 1;
+        if self.threshold.is_some_and(|threshold| self.dead as f64 > threshold * (self.live + self.dead) as f64) {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    /// Drops the dead entries and rebuilds the tree balanced, in linear time.
+    pub fn compact(&mut self) {
+        self.map.retain(|_, value| value.is_some());
+        self.dead = 0;
+        self.compactions += 1;
+    }
+
+    /// Visits the live keys and their values in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter().filter_map(|(key, value)| Some((key, value.as_ref()?)))
+    }
+
+    /// Checks the tree and the numbers of live and dead entries.
+    pub fn validate(&self) -> Result<(), String> {
+        self.map.validate()?;
+        let dead = self.map.iter().filter(|(_, value)| value.is_none()).count();
+        let live = self.map.iter().count() - dead;
+        if (live, dead) != (self.live, self.dead) {
+            return Err(format!("{} live and {} dead entries are counted as {} and {}", live, dead, self.live, self.dead));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use crate::rbt;
+    use crate::testing::Random;
+    use super::*;
+
+    #[test]
+    fn test_remove_and_revive() {
+        let mut map: Tombstones<i32, i32> = Tombstones::with_threshold(None);
+        for key in 0..10 {
+            map.insert(key, key * 10);
+        }
+        assert_eq!(map.remove(&3), Some(30));
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(map.remove(&42), None);
+        assert_eq!((map.len(), map.dead()), (9, 1));
+        assert_eq!(map.get(&3), None);
+        assert!(!map.contains_key(&3));
+        assert_eq!(map.iter().map(|(&key, _)| key).collect::<Vec<_>>(), vec![0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        // the tombstone is still in the tree
+        assert_eq!(map.map().len(), 10);
+
+        assert_eq!(map.insert(3, 33), None);
+        assert_eq!((map.len(), map.dead()), (10, 0));
+        assert_eq!(map.insert(3, 34), Some(33));
+        *map.get_mut(&3).unwrap() += 1;
+        assert_eq!(map.get(&3), Some(&35));
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut map: Tombstones<i32, ()> = Tombstones::with_threshold(None);
+        for key in 0..1000 {
+            map.insert(key, ());
+        }
+        for key in 0..1000 {
+            if key % 10 != 0 {
+                map.remove(&key);
+            }
+        }
+        assert_eq!((map.len(), map.dead(), map.compactions()), (100, 900, 0));
+        map.compact();
+        assert_eq!((map.len(), map.dead(), map.compactions()), (100, 0, 1));
+        assert_eq!(map.map().len(), 100);
+        let stats = map.map().stats();
+        assert_eq!(stats.height, stats.optimal_height());
+        assert!(map.iter().map(|(&key, _)| key).eq((0..1000).step_by(10)));
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn test_threshold() {
+        let mut map: Tombstones<i32, i32> = Tombstones::with_threshold(Some(0.25));
+        for key in 0..100 {
+            map.insert(key, key);
+        }
+        for key in 0..25 {
+            map.remove(&key);
+        }
+        // 25 dead entries out of 100 are not more than a quarter
+        assert_eq!((map.dead(), map.compactions()), (25, 0));
+        map.remove(&25);
+        assert_eq!((map.len(), map.dead(), map.compactions()), (74, 0, 1));
+        assert_eq!(map.map().len(), 74);
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn test_remove_without_rotations() {
+        let mut map: Tombstones<i32, (), RbTree<i32, Option<()>>> = Tombstones::with_threshold(None);
+        for key in 0..100 {
+            map.insert(key, ());
+        }
+        let before = rbt::rotations();
+        for key in 0..100 {
+            map.remove(&key);
+        }
+        assert_eq!(rbt::rotations(), before);
+        map.compact();
+        assert!(map.map().is_empty());
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn test_random() {
+        fn check<M: Rebuild<u64, Option<u64>>>(threshold: Option<f64>) {
+            let mut map: Tombstones<u64, u64, M> = Tombstones::with_threshold(threshold);
+            let mut expected = BTreeMap::new();
+            let mut random = Random(0x2545_f491_4f6c_dd1d);
+            for i in 0..5000 {
+                let key = random.next_u64() % 300;
+                if random.next_u64() % 5 < 2 {
+                    assert_eq!(map.remove(&key), expected.remove(&key));
+                } else {
+                    assert_eq!(map.insert(key, i), expected.insert(key, i));
+                }
+                if i % 500 == 0 {
+                    assert!(map.validate().is_ok());
+                }
+            }
+            assert!(map.iter().eq(expected.iter()));
+            assert_eq!(map.len(), expected.len());
+            map.compact();
+            assert!(map.iter().eq(expected.iter()));
+            assert!(map.validate().is_ok());
+        }
+
+        for threshold in [None, Some(0.1), Some(0.5)] {
+            check::<AvlTree<u64, Option<u64>>>(threshold);
+            check::<RbTree<u64, Option<u64>>>(threshold);
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//!
+//! an implementation for Treap
+//!
+//! https://en.wikipedia.org/wiki/Treap
+//!
+//! A treap is a binary search tree by its keys and a heap by random priorities given to the nodes:
+//! a parent never has a lower priority than its children. The shape is the one a binary search tree
+//! would get from inserting the keys in the order of decreasing priority, a random order, so the
+//! expected depth of a node is O(log n) whatever the order of the insertions.
+//!
+//! Insertions and deletions restore the heap with the same rotations as `Avl::left_rotate` and
+//! `Avl::right_rotate`, `split` and `merge` cut and join whole trees along one path. The priorities
+//! come from a seeded generator, a treap built twice from the same seed has the same shape.
+//!
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::{vec, vec::Vec};
+use core::cmp::Ordering;
+use core::fmt::{self, Display};
+use core::mem;
+use crate::compare::{Compare, Natural};
+use crate::map::OrderedMap;
+use crate::path::{self, Descend};
+use crate::render::{self, Layout, Sketch};
+use crate::stats::{self, Stats};
+
+const DEFAULT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A SplitMix64 generator, small and good enough to draw priorities.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+type TreapBoxOption<T, V> = Option<Box<Node<T, V>>>;
+
+/// `size` is the number of nodes of the subtree, it gives the length of both halves of a split.
+struct Node<T, V> {
+    key: T,
+    data: V,
+    priority: u64,
+    size: usize,
+    left: TreapBoxOption<T, V>,
+    right: TreapBoxOption<T, V>,
+}
+
+impl<T, V> Node<T, V> {
+    fn new(key: T, data: V, priority: u64) -> Box<Node<T, V>> {
+        Box::new(Node { key, data, priority, size: 1, left: None, right: None })
+    }
+
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+
+    /// The left child moves up, this node becomes its right child.
+    fn right_rotate(mut self: Box<Self>) -> Box<Self> {
+        let Some(mut child) = self.left.take() else { return self };
+        self.left = child.right.take();
+        self.update_size();
+        child.right = Some(self);
+        child.update_size();
+        child
+    }
+
+    /// The right child moves up, this node becomes its left child.
+    fn left_rotate(mut self: Box<Self>) -> Box<Self> {
+        let Some(mut child) = self.right.take() else { return self };
+        self.right = child.left.take();
+        self.update_size();
+        child.left = Some(self);
+        child.update_size();
+        child
+    }
+}
+
+fn size<T, V>(node: &TreapBoxOption<T, V>) -> usize {
+    node.as_ref().map_or(0, |node| node.size)
+}
+
+impl<T, V> Descend for Node<T, V> {
+    fn descend(&self, left: bool) -> Option<&Self> {
+        if left { self.left.as_deref() } else { self.right.as_deref() }
+    }
+}
+
+/// A treap owning an optional root and the generator of the priorities.
+///
+/// It is a map from `T` to `V`, keys are unique. Use `V = ()` for a set. The keys are ordered by the
+/// comparator `C`, their natural order by default.
+pub struct Treap<T, V = (), C = Natural> {
+    root: TreapBoxOption<T, V>,
+    cmp: C,
+    rng: Rng,
+}
+
+impl<T, V, C: Default> Default for Treap<T, V, C> {
+    fn default() -> Self {
+        Treap { root: None, cmp: C::default(), rng: Rng::new(DEFAULT_SEED) }
+    }
+}
+
+impl<T: Ord, V> Treap<T, V> {
+    pub fn new() -> Treap<T, V> {
+        Treap::with_seed(DEFAULT_SEED)
+    }
+
+    /// An empty treap drawing its priorities from `seed`.
+    pub fn with_seed(seed: u64) -> Treap<T, V> {
+        Treap::with_comparator_and_seed(Natural, seed)
+    }
+}
+
+impl<T, V, C: Compare<T>> Treap<T, V, C> {
+    /// An empty treap ordered by `cmp` instead of the `Ord` of the keys.
+    pub fn with_comparator(cmp: C) -> Treap<T, V, C> {
+        Treap::with_comparator_and_seed(cmp, DEFAULT_SEED)
+    }
+
+    pub fn with_comparator_and_seed(cmp: C, seed: u64) -> Treap<T, V, C> {
+        Treap { root: None, cmp, rng: Rng::new(seed) }
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn search(&self, key: &T) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn get(&self, key: &T) -> Option<&V> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&n.data),
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return Some(&mut n.data),
+                Ordering::Less => &mut n.left,
+                Ordering::Greater => &mut n.right,
+            }
+        }
+        None
+    }
+
+    /// The key at `index` in the order of the keys, with its data.
+    pub fn get_index(&self, mut index: usize) -> Option<(&T, &V)> {
+        let mut node = &self.root;
+        while let Some(n) = node {
+            let left = size(&n.left);
+            node = match index.cmp(&left) {
+                Ordering::Equal => return Some((&n.key, &n.data)),
+                Ordering::Less => &n.left,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &n.right
+                }
+            }
+        }
+        None
+    }
+
+    /// The number of keys less than `key`.
+    pub fn rank(&self, key: &T) -> usize {
+        let mut rank = 0;
+        let mut node = &self.root;
+        while let Some(n) = node {
+            node = match self.cmp.compare(key, &n.key) {
+                Ordering::Equal => return rank + size(&n.left),
+                Ordering::Less => &n.left,
+                Ordering::Greater => {
+                    rank += size(&n.left) + 1;
+                    &n.right
+                }
+            }
+        }
+        rank
+    }
+
+    fn data_at_mut(&mut self, mut index: usize) -> &mut V {
+        let mut node = self.root.as_mut().expect("the index is within the treap");
+        loop {
+            let left = size(&node.left);
+            node = match index.cmp(&left) {
+                Ordering::Equal => return &mut node.data,
+                Ordering::Less => node.left.as_mut().expect("the index is within the left subtree"),
+                Ordering::Greater => {
+                    index -= left + 1;
+                    node.right.as_mut().expect("the index is within the right subtree")
+                }
+            }
+        }
+    }
+
+    /// Inserts `key` with `data`, returns the old data if `key` was present.
+    pub fn insert(&mut self, key: T, data: V) -> Option<V> {
+        let (root, old, _) = insert::<T, V, C, fn() -> V>(self.root.take(), key, Data::Replace(data), self.rng.next_u64(), &self.cmp);
+        self.root = Some(root);
+        old
+    }
+
+    /// Removes `key`, returns its data if it was present.
+    pub fn remove(&mut self, key: &T) -> Option<V> {
+        let (root, removed) = remove(self.root.take(), key, &self.cmp);
+        self.root = root;
+        removed.map(|node| node.data)
+    }
+
+    /// Moves the keys not less than `key` into a new treap, which is returned.
+    pub fn split(&mut self, key: &T) -> Treap<T, V, C>
+    where
+        C: Clone,
+    {
+        let (left, right) = split(self.root.take(), |node| self.cmp.compare(&node.key, key) == Ordering::Less);
+        self.root = left;
+        self.split_treap(right)
+    }
+
+    /// Moves the keys from the position `index` on into a new treap, which is returned.
+    pub fn split_at(&mut self, index: usize) -> Treap<T, V, C>
+    where
+        C: Clone,
+    {
+        // the nodes before `index` within the subtree being looked at
+        let mut index = index;
+        let (left, right) = split(self.root.take(), |node| {
+            let left = size(&node.left);
+            if left < index {
+                index -= left + 1;
+                true
+            } else {
+                false
+            }
+        });
+        self.root = left;
+        self.split_treap(right)
+    }
+
+    /// Moves all the keys of `other` into this treap, they must all be greater than the keys of this
+    /// one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a key of `other` isn't greater than the keys of this treap.
+    pub fn merge(&mut self, mut other: Treap<T, V, C>) {
+        if let (Some((last, _)), Some((first, _))) = (self.last(), other.first()) {
+            assert!(self.cmp.compare(last, first) == Ordering::Less, "the keys to merge must be greater than the keys of the treap");
+        }
+        self.root = merge(self.root.take(), other.root.take());
+    }
+
+    pub fn first(&self) -> Option<(&T, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some((&node.key, &node.data))
+    }
+
+    pub fn last(&self) -> Option<(&T, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some((&node.key, &node.data))
+    }
+
+    /// Visits the keys and their data in order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter { nodes: path::InOrder::new(self.root.as_deref()) }
+    }
+
+    /// Checks the order of the keys, the priorities and the sizes of the subtrees.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut nodes: Vec<&Node<T, V>> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = nodes.pop() {
+            if node.size != 1 + size(&node.left) + size(&node.right) {
+                return Err(format!("a node has the size {} instead of {}", node.size, 1 + size(&node.left) + size(&node.right)));
+            }
+            for child in [&node.left, &node.right].into_iter().flatten() {
+                if child.priority > node.priority {
+                    return Err(format!("a node has the priority {} below a node of the priority {}", child.priority, node.priority));
+                }
+                nodes.push(child);
+            }
+        }
+
+        let mut previous = None;
+        for (count, (key, _)) in self.iter().enumerate() {
+            if previous.is_some_and(|previous| self.cmp.compare(previous, key) != Ordering::Less) {
+                return Err(format!("the keys at {} and {} are out of order", count - 1, count));
+            }
+            previous = Some(key);
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = stats::binary(self.root.as_deref(), mem::size_of::<Node<T, V>>(), |_| ());
+        stats.memory += mem::size_of::<Self>();
+        stats
+    }
+
+    /// A treap with the same comparator and a generator seeded from this one.
+    fn split_treap(&mut self, root: TreapBoxOption<T, V>) -> Treap<T, V, C>
+    where
+        C: Clone,
+    {
+        Treap { root, cmp: self.cmp.clone(), rng: Rng::new(self.rng.next_u64()) }
+    }
+}
+
+/// The data of an insertion: it replaces the data of a key already present, or it is only made if
+/// the key is missing.
+enum Data<V, F> {
+    Replace(V),
+    OrInsertWith(F),
+}
+
+/// Inserts `key` as a leaf and rotates it up as long as its priority is higher than the one of its
+/// parent. Returns the new root, the old data of the key if it was replaced and the index of the key,
+/// which the rotations don't change.
+fn insert<T, V, C: Compare<T>, F: FnOnce() -> V>(node: TreapBoxOption<T, V>, key: T, data: Data<V, F>, priority: u64, cmp: &C) -> (Box<Node<T, V>>, Option<V>, usize) {
+    let Some(mut node) = node else {
+        let data = match data {
+            Data::Replace(data) => data,
+            Data::OrInsertWith(default) => default(),
+        };
+        return (Node::new(key, data, priority), None, 0);
+    };
+    match cmp.compare(&key, &node.key) {
+        Ordering::Equal => {
+            let old = match data {
+                Data::Replace(data) => Some(mem::replace(&mut node.data, data)),
+                Data::OrInsertWith(_) => None,
+            };
+            let index = size(&node.left);
+            (node, old, index)
+        }
+        Ordering::Less => {
+            let (left, old, index) = insert(node.left.take(), key, data, priority, cmp);
+            let rotate = left.priority > node.priority;
+            node.left = Some(left);
+            node.update_size();
+            (if rotate { node.right_rotate() } else { node }, old, index)
+        }
+        Ordering::Greater => {
+            let (right, old, index) = insert(node.right.take(), key, data, priority, cmp);
+            let rotate = right.priority > node.priority;
+            node.right = Some(right);
+            node.update_size();
+            let index = size(&node.left) + 1 + index;
+            (if rotate { node.left_rotate() } else { node }, old, index)
+        }
+    }
+}
+
+/// Removes the node of `key` by merging its subtrees. Returns the new root and the removed node.
+fn remove<T, V, C: Compare<T>>(node: TreapBoxOption<T, V>, key: &T, cmp: &C) -> (TreapBoxOption<T, V>, TreapBoxOption<T, V>) {
+    let Some(mut node) = node else { return (None, None) };
+    let removed = match cmp.compare(key, &node.key) {
+        Ordering::Equal => {
+            let rest = merge(node.left.take(), node.right.take());
+            return (rest, Some(node));
+        }
+        Ordering::Less => {
+            let (left, removed) = remove(node.left.take(), key, cmp);
+            node.left = left;
+            removed
+        }
+        Ordering::Greater => {
+            let (right, removed) = remove(node.right.take(), key, cmp);
+            node.right = right;
+            removed
+        }
+    };
+    node.update_size();
+    (Some(node), removed)
+}
+
+/// Cuts a tree in two along one path, `goes_left` tells whether a node and its left subtree belong
+/// to the left part. It is called once per level, from the root down.
+fn split<T, V>(node: TreapBoxOption<T, V>, mut goes_left: impl FnMut(&Node<T, V>) -> bool) -> (TreapBoxOption<T, V>, TreapBoxOption<T, V>) {
+    fn split_node<T, V>(node: TreapBoxOption<T, V>, goes_left: &mut impl FnMut(&Node<T, V>) -> bool) -> (TreapBoxOption<T, V>, TreapBoxOption<T, V>) {
+        let Some(mut node) = node else { return (None, None) };
+        if goes_left(&node) {
+            let (left, right) = split_node(node.right.take(), goes_left);
+            node.right = left;
+            node.update_size();
+            (Some(node), right)
+        } else {
+            let (left, right) = split_node(node.left.take(), goes_left);
+            node.left = right;
+            node.update_size();
+            (left, Some(node))
+        }
+    }
+    split_node(node, &mut goes_left)
+}
+
+/// Joins two trees, every key of `left` being less than the keys of `right`. The root with the
+/// higher priority stays on top.
+fn merge<T, V>(left: TreapBoxOption<T, V>, right: TreapBoxOption<T, V>) -> TreapBoxOption<T, V> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update_size();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update_size();
+                Some(right)
+            }
+        }
+    }
+}
+
+impl<T: Display, V, C> Treap<T, V, C> {
+    /// The shape of the tree, to be drawn by `render`.
+    pub fn sketch(&self) -> Option<Sketch> {
+        fn sketch<T: Display, V>(node: &Node<T, V>) -> Sketch {
+            let children = vec![node.left.as_deref().map(sketch), node.right.as_deref().map(sketch)];
+            Sketch::new(node.key.to_string(), children)
+        }
+        self.root.as_deref().map(sketch)
+    }
+}
+
+impl<T: Display, V, C> Display for Treap<T, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        render::draw(self.sketch().as_ref(), &Layout::default(), f)
+    }
+}
+
+impl<T, V, C: Compare<T> + Default> OrderedMap<T, V> for Treap<T, V, C> {
+    type Iter<'a> = Iter<'a, T, V> where T: 'a, V: 'a, C: 'a;
+
+    fn get(&self, key: &T) -> Option<&V> {
+        Treap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &T) -> Option<&mut V> {
+        Treap::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: T, value: V) -> Option<V> {
+        Treap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &T) -> Option<V> {
+        Treap::remove(self, key)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: T, default: F) -> &mut V {
+        // the key is moved into the tree, the node is found again by its position
+        let (root, _, index) = insert(self.root.take(), key, Data::OrInsertWith(default), self.rng.next_u64(), &self.cmp);
+        self.root = Some(root);
+        self.data_at_mut(index)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Treap::iter(self)
+    }
+}
+
+pub struct Iter<'a, T, V> {
+    nodes: path::InOrder<'a, Node<T, V>>,
+}
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a T, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nodes.next().map(|node| (&node.key, &node.data))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use crate::compare::Reverse;
+    use crate::testing::{check_multiset, check_ordered_map, check_reverse_order};
+    use super::*;
+
+    fn keys<C: Compare<i32>>(treap: &Treap<i32, i32, C>) -> Vec<i32> {
+        treap.iter().map(|(&key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_rank_and_index() {
+        let mut treap = Treap::new();
+        for key in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            treap.insert(key, key * 10);
+        }
+        assert_eq!(treap.rank(&5), 4);
+        assert_eq!(treap.rank(&10), 9);
+        assert_eq!(treap.get_index(4), Some((&5, &50)));
+        assert_eq!(treap.get_index(9), None);
+
+        treap.remove(&5);
+        treap.remove(&9);
+        assert_eq!(treap.rank(&6), 4);
+        assert_eq!(treap.get_index(4), Some((&6, &60)));
+        assert_eq!(treap.first(), Some((&1, &10)));
+        assert_eq!(treap.last(), Some((&8, &80)));
+    }
+
+    #[test]
+    fn test_seed() {
+        let build = |seed| {
+            let mut treap = Treap::with_seed(seed);
+            for key in 0..100 {
+                treap.insert(key, ());
+            }
+            treap.sketch()
+        };
+        assert_eq!(build(1), build(1));
+        assert_ne!(build(1), build(2));
+    }
+
+    #[test]
+    fn test_sorted_insertions() {
+        let mut treap = Treap::new();
+        for key in 0..100_000 {
+            treap.insert(key, ());
+        }
+        assert!(treap.validate().is_ok());
+        // the expected height is about 3 log2(n)
+        let stats = treap.stats();
+        assert!(stats.height < 3 * stats.optimal_height(), "{}", stats);
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let mut treap = Treap::new();
+        for key in 0..100 {
+            treap.insert(key, key);
+        }
+        let mut right = treap.split(&60);
+        assert_eq!(keys(&treap), (0..60).collect::<Vec<_>>());
+        assert_eq!(keys(&right), (60..100).collect::<Vec<_>>());
+        assert_eq!((treap.len(), right.len()), (60, 40));
+        assert!(treap.validate().is_ok() && right.validate().is_ok());
+
+        let middle = treap.split_at(25);
+        assert_eq!(keys(&middle), (25..60).collect::<Vec<_>>());
+        assert_eq!(treap.len(), 25);
+        let all = treap.split(&-1);
+        assert!(treap.is_empty());
+        treap.merge(all);
+        assert!(right.split(&1000).is_empty());
+
+        treap.merge(middle);
+        treap.merge(right);
+        assert_eq!(keys(&treap), (0..100).collect::<Vec<_>>());
+        assert!(treap.validate().is_ok());
+        treap.merge(Treap::new());
+        assert_eq!(treap.len(), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "the keys to merge must be greater")]
+    fn test_merge_overlapping() {
+        let mut treap = Treap::new();
+        treap.insert(5, ());
+        let mut other = Treap::new();
+        other.insert(3, ());
+        treap.merge(other);
+    }
+
+    #[test]
+    fn test_random_against_btree_map() {
+        let mut treap = Treap::with_seed(7);
+        let mut expected = BTreeMap::new();
+        let mut rng = Rng::new(11);
+        for i in 0..5000 {
+            let key = (rng.next_u64() % 300) as i32;
+            match rng.next_u64() % 4 {
+                0 => assert_eq!(treap.remove(&key), expected.remove(&key)),
+                1 => {
+                    // cut and join again at a random key
+                    let right = treap.split(&key);
+                    treap.merge(right);
+                }
+                _ => assert_eq!(treap.insert(key, i), expected.insert(key, i)),
+            }
+        }
+        assert!(treap.validate().is_ok());
+        assert!(treap.iter().map(|(&key, &data)| (key, data)).eq(expected));
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        // the new node is found again by its index, which the keys inserted before it shift
+        let mut treap = Treap::new();
+        for key in (0..101).map(|key| (key * 37) % 101) {
+            *OrderedMap::get_or_insert_with(&mut treap, key, || 0) = key + 1;
+            assert_eq!(treap.get_index(treap.rank(&key)), Some((&key, &(key + 1))));
+        }
+        assert!(treap.iter().all(|(&key, &data)| data == key + 1));
+        assert!(treap.validate().is_ok());
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        assert!(check_ordered_map::<Treap<i32, i32>>().validate().is_ok());
+        let mut treap = check_reverse_order::<Treap<i32, (), Reverse>>();
+        let right = treap.split(&4);
+        assert!(right.iter().map(|(&key, _)| key).eq((0..5).rev()));
+        check_multiset::<Treap<i32, usize>>();
+    }
+}